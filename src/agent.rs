@@ -0,0 +1,568 @@
+use anyhow::{anyhow, Result};
+use scraper::{ElementRef, Html, Node, Selector};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::browser::BrowserSession;
+use crate::llama_client::LlamaClient;
+use crate::llm::{ChatResponse, Message, Tool};
+use crate::types::{
+    AgentRequest, AgentResponse, AgentStatus, AgentStep, BrowserAction, PageObservation, TaskResult,
+};
+
+const DEFAULT_MAX_STEPS: usize = 15;
+const MAX_STEPS_LIMIT: usize = 50;
+const DEFAULT_MAX_DURATION_SECS: u64 = 120;
+const MAX_DURATION_LIMIT_SECS: u64 = 600;
+
+// Keep observations small enough to fit comfortably in the model context
+const DOM_SUMMARY_MAX_CHARS: usize = 4000;
+const STEP_OUTPUT_MAX_CHARS: usize = 1500;
+const MAX_INTERACTIVE_ELEMENTS: usize = 50;
+const MAX_CONSECUTIVE_MODEL_ERRORS: usize = 3;
+
+const ACTION_TOOL_NAME: &str = "perform_action";
+const FINISH_TOOL_NAME: &str = "finish";
+
+/// Step and wall-clock limits for a single agent run
+#[derive(Debug, Clone, Copy)]
+pub struct AgentBudget {
+    pub max_steps: usize,
+    pub max_duration: Duration,
+}
+
+impl AgentBudget {
+    pub fn from_request(request: &AgentRequest) -> Self {
+        let max_steps = request
+            .max_steps
+            .unwrap_or(DEFAULT_MAX_STEPS)
+            .clamp(1, MAX_STEPS_LIMIT);
+        let max_duration_secs = request
+            .max_duration_seconds
+            .unwrap_or(DEFAULT_MAX_DURATION_SECS)
+            .clamp(1, MAX_DURATION_LIMIT_SECS);
+
+        Self {
+            max_steps,
+            max_duration: Duration::from_secs(max_duration_secs),
+        }
+    }
+}
+
+enum AgentDecision {
+    Act {
+        action: BrowserAction,
+        reasoning: Option<String>,
+    },
+    Finish {
+        success: bool,
+        summary: Option<String>,
+    },
+    Invalid(String),
+}
+
+/// Observe the page, ask the model for the next action, run it, and repeat until the model
+/// declares the goal done or a budget runs out.
+pub async fn run_agent(
+    llama_client: &LlamaClient,
+    session: &mut BrowserSession,
+    request: &AgentRequest,
+) -> Result<AgentResponse> {
+    let budget = AgentBudget::from_request(request);
+    let started = Instant::now();
+    let task_id = uuid::Uuid::new_v4().to_string();
+
+    info!(
+        "Starting agent run {} (max {} steps, {}s): {}",
+        task_id,
+        budget.max_steps,
+        budget.max_duration.as_secs(),
+        request.goal
+    );
+
+    let initial_observation = observe(session, budget.max_duration).await?;
+    let tools = agent_tools();
+    let mut messages = vec![
+        Message::system(agent_system_prompt()),
+        Message::user(format!(
+            "Goal: {}\n\nCurrent page:\n{}",
+            request.goal,
+            format_observation(&initial_observation)
+        )),
+    ];
+
+    let mut steps = Vec::new();
+    let mut consecutive_model_errors = 0;
+
+    let (status, summary) = loop {
+        if steps.len() >= budget.max_steps {
+            break (AgentStatus::StepBudgetExhausted, None);
+        }
+
+        let Some(remaining) = budget.max_duration.checked_sub(started.elapsed()) else {
+            break (AgentStatus::TimeBudgetExhausted, None);
+        };
+
+        let response = match tokio::time::timeout(
+            remaining,
+            llama_client.call_llama_with_tools(&messages, &tools),
+        )
+        .await
+        {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                warn!("Agent run {} could not reach the model: {}", task_id, e);
+                break (AgentStatus::ModelError, Some(e.to_string()));
+            }
+            Err(_) => break (AgentStatus::TimeBudgetExhausted, None),
+        };
+
+        let (action, reasoning) = match parse_decision(&response) {
+            AgentDecision::Finish { success, summary } => {
+                let status = if success {
+                    AgentStatus::Completed
+                } else {
+                    AgentStatus::GaveUp
+                };
+                break (status, summary);
+            }
+            AgentDecision::Invalid(problem) => {
+                consecutive_model_errors += 1;
+                warn!(
+                    "Agent run {} got an unusable model response ({}/{}): {}",
+                    task_id, consecutive_model_errors, MAX_CONSECUTIVE_MODEL_ERRORS, problem
+                );
+                if consecutive_model_errors >= MAX_CONSECUTIVE_MODEL_ERRORS {
+                    break (AgentStatus::ModelError, Some(problem));
+                }
+
+                let retry = format!(
+                    "{problem}\nRespond by calling {ACTION_TOOL_NAME} with the next action, \
+                    or {FINISH_TOOL_NAME} when the goal is done or cannot be achieved."
+                );
+                messages.push(Message::assistant(&response));
+                messages.extend(replies_to(&response, &retry, &retry));
+                continue;
+            }
+            AgentDecision::Act { action, reasoning } => (action, reasoning),
+        };
+        consecutive_model_errors = 0;
+
+        let step_number = steps.len() + 1;
+        info!("Agent run {} step {}: {:?}", task_id, step_number, action);
+
+        let step_started = Instant::now();
+        let remaining = budget
+            .max_duration
+            .saturating_sub(started.elapsed())
+            .max(Duration::from_millis(1));
+        let result = match tokio::time::timeout(remaining, session.interact(&action)).await {
+            Ok(Ok(output)) => TaskResult {
+                step_id: format!("step_{step_number}"),
+                success: true,
+                output: Some(output),
+                error: None,
+            },
+            Ok(Err(e)) => TaskResult {
+                step_id: format!("step_{step_number}"),
+                success: false,
+                output: None,
+                error: Some(e.to_string()),
+            },
+            Err(_) => TaskResult {
+                step_id: format!("step_{step_number}"),
+                success: false,
+                output: None,
+                error: Some("Action did not finish within the time budget".to_string()),
+            },
+        };
+
+        let remaining = budget
+            .max_duration
+            .saturating_sub(started.elapsed())
+            .max(Duration::from_millis(1));
+        let observation = match observe(session, remaining).await {
+            Ok(observation) => Some(observation),
+            Err(e) => {
+                warn!("Agent run {} could not observe the page: {}", task_id, e);
+                None
+            }
+        };
+
+        let feedback = format_step_feedback(&result, observation.as_ref());
+        messages.push(Message::assistant(&response));
+        messages.extend(replies_to(
+            &response,
+            &feedback,
+            "Not run: only one action is performed per step.",
+        ));
+
+        steps.push(AgentStep {
+            step: step_number,
+            reasoning,
+            action,
+            result,
+            observation,
+            elapsed_ms: step_started.elapsed().as_millis() as u64,
+        });
+    };
+
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    info!(
+        "Agent run {} finished with status {:?} after {} steps in {}ms",
+        task_id,
+        status,
+        steps.len(),
+        elapsed_ms
+    );
+
+    Ok(AgentResponse {
+        success: status == AgentStatus::Completed,
+        task_id,
+        status,
+        summary,
+        steps,
+        elapsed_ms,
+    })
+}
+
+/// Read the page without recording it as the session's last action, giving up after `timeout`
+async fn observe(session: &BrowserSession, timeout: Duration) -> Result<PageObservation> {
+    let read = async {
+        Ok::<_, anyhow::Error>((
+            session.get_current_url().await?,
+            session.page_source().await?,
+        ))
+    };
+    let (url, html) = tokio::time::timeout(timeout, read)
+        .await
+        .map_err(|_| anyhow!("Observing the page did not finish within the time budget"))?
+        .map_err(|e| anyhow!("Failed to observe page: {}", e))?;
+
+    let document = Html::parse_document(&html);
+    Ok(PageObservation {
+        url,
+        title: page_title(&document),
+        dom_summary: summarize_document(&document, DOM_SUMMARY_MAX_CHARS),
+    })
+}
+
+/// The messages answering a model response: one tool result per tool call, since
+/// OpenAI-compatible APIs reject a tool call left without a reply, or a user message when it
+/// made none. The first call gets `reply` and any others get `others`.
+pub fn replies_to(response: &ChatResponse, reply: &str, others: &str) -> Vec<Message> {
+    match response.tool_calls.as_deref() {
+        Some([first, rest @ ..]) => std::iter::once(Message::tool_result(first, reply))
+            .chain(rest.iter().map(|call| Message::tool_result(call, others)))
+            .collect(),
+        _ => vec![Message::user(reply)],
+    }
+}
+
+fn parse_decision(message: &ChatResponse) -> AgentDecision {
+    if let Some(tool_call) = message.tool_calls.as_ref().and_then(|calls| calls.first()) {
+        let arguments = tool_call.function.parsed_arguments();
+        return match tool_call.function.name.as_str() {
+            ACTION_TOOL_NAME => decision_from_action(&arguments, message.content.as_deref()),
+            FINISH_TOOL_NAME => decision_from_finish(&arguments),
+            other => AgentDecision::Invalid(format!("Unknown tool `{other}`.")),
+        };
+    }
+
+    // Models without tool calling can answer with a JSON object instead
+    let parsed = message.content.as_deref().and_then(|content| {
+        let start = content.find('{')?;
+        let end = content.rfind('}')?;
+        serde_json::from_str::<Value>(&content[start..=end]).ok()
+    });
+
+    match parsed {
+        Some(value) if value.get("action").is_some() => {
+            decision_from_action(&value, message.content.as_deref())
+        }
+        Some(value) if value.get("done").is_some() || value.get("summary").is_some() => {
+            decision_from_finish(&value)
+        }
+        _ => AgentDecision::Invalid("No action or finish decision found.".to_string()),
+    }
+}
+
+fn decision_from_action(arguments: &Value, content: Option<&str>) -> AgentDecision {
+    let Some(action) = arguments.get("action") else {
+        return AgentDecision::Invalid("Missing `action` argument.".to_string());
+    };
+
+    match serde_json::from_value::<BrowserAction>(action.clone()) {
+        Ok(action) => AgentDecision::Act {
+            action,
+            reasoning: arguments
+                .get("reasoning")
+                .and_then(|r| r.as_str())
+                .or(content.filter(|c| !c.trim().is_empty()))
+                .map(|r| r.to_string()),
+        },
+        Err(e) => AgentDecision::Invalid(format!("Invalid action: {e}.")),
+    }
+}
+
+fn decision_from_finish(arguments: &Value) -> AgentDecision {
+    AgentDecision::Finish {
+        success: arguments
+            .get("success")
+            .and_then(|s| s.as_bool())
+            .unwrap_or(true),
+        summary: arguments
+            .get("summary")
+            .and_then(|s| s.as_str())
+            .map(|s| s.to_string()),
+    }
+}
+
+fn agent_tools() -> Vec<Tool> {
+    vec![
+        Tool::function(
+            ACTION_TOOL_NAME,
+            "Perform the next browser action on the current page",
+            json!({
+                    "type": "object",
+                    "properties": {
+                        "reasoning": {
+                            "type": "string",
+                            "description": "Why this action moves towards the goal"
+                        },
+                        "action": BrowserAction::json_schema()
+                    },
+                    "required": ["action"]
+            }),
+        ),
+        Tool::function(
+            FINISH_TOOL_NAME,
+            "Stop because the goal is achieved or cannot be achieved",
+            json!({
+                    "type": "object",
+                    "properties": {
+                        "success": {
+                            "type": "boolean",
+                            "description": "Whether the goal was achieved"
+                        },
+                        "summary": {
+                            "type": "string",
+                            "description": "What was done and any information the user asked for"
+                        }
+                    },
+                    "required": ["success", "summary"]
+            }),
+        ),
+    ]
+}
+
+fn agent_system_prompt() -> String {
+    format!(
+        "You are a web automation agent controlling a real browser. You work in a loop: \
+        you receive the current page state, choose exactly one next action, and then see \
+        the result and the new page state.
+
+Call {ACTION_TOOL_NAME} with one browser action at a time. Action types:
+- Click: click an element by CSS selector
+- Type: type text into an input by CSS selector
+- Wait / WaitForElement: wait for time to pass or for an element to appear
+- Scroll: scroll Up, Down, Left or Right
+- Screenshot, GetPageSource: capture the page
+- ExecuteScript: evaluate JavaScript (use window.location.href = '...' to navigate)
+
+Use selectors from the \"Interactive elements\" list when possible. If an action fails, \
+read the error and try a different approach instead of repeating it.
+
+Call {FINISH_TOOL_NAME} as soon as the goal is achieved, with a summary that includes any \
+information the user asked for. Call it with success=false if the goal cannot be achieved."
+    )
+}
+
+fn format_observation(observation: &PageObservation) -> String {
+    format!(
+        "URL: {}\nTitle: {}\n{}",
+        observation.url,
+        observation.title.as_deref().unwrap_or("(none)"),
+        observation.dom_summary
+    )
+}
+
+fn format_step_feedback(result: &TaskResult, observation: Option<&PageObservation>) -> String {
+    let outcome = if result.success {
+        format!(
+            "Action succeeded. Output: {}",
+            truncate_chars(
+                result.output.as_deref().unwrap_or(""),
+                STEP_OUTPUT_MAX_CHARS
+            )
+        )
+    } else {
+        format!(
+            "Action failed. Error: {}",
+            result.error.as_deref().unwrap_or("unknown error")
+        )
+    };
+
+    match observation {
+        Some(observation) => format!(
+            "{outcome}\n\nCurrent page:\n{}",
+            format_observation(observation)
+        ),
+        None => format!("{outcome}\n\nThe page state could not be observed."),
+    }
+}
+
+fn page_title(document: &Html) -> Option<String> {
+    let selector = Selector::parse("title").ok()?;
+    document
+        .select(&selector)
+        .next()
+        .map(|title| collapse_whitespace(&title.text().collect::<String>()))
+        .filter(|title| !title.is_empty())
+}
+
+/// Build a compact, model-friendly summary of a page: headings, interactive elements with
+/// suggested selectors, and the visible text, truncated to `max_chars`.
+pub fn summarize_dom(html: &str, max_chars: usize) -> String {
+    summarize_document(&Html::parse_document(html), max_chars)
+}
+
+fn summarize_document(document: &Html, max_chars: usize) -> String {
+    let mut summary = String::new();
+
+    if let Ok(selector) = Selector::parse("h1, h2, h3") {
+        let headings: Vec<String> = document
+            .select(&selector)
+            .map(|heading| collapse_whitespace(&heading.text().collect::<String>()))
+            .filter(|text| !text.is_empty())
+            .take(10)
+            .collect();
+        if !headings.is_empty() {
+            summary.push_str("Headings:\n");
+            for heading in headings {
+                summary.push_str(&format!("- {heading}\n"));
+            }
+        }
+    }
+
+    if let Ok(selector) =
+        Selector::parse("a[href], button, input, select, textarea, [role='button']")
+    {
+        let elements: Vec<String> = document
+            .select(&selector)
+            .filter(|element| element.value().attr("type") != Some("hidden"))
+            .take(MAX_INTERACTIVE_ELEMENTS)
+            .map(describe_element)
+            .collect();
+        if !elements.is_empty() {
+            summary.push_str("Interactive elements:\n");
+            for element in elements {
+                summary.push_str(&format!("- {element}\n"));
+            }
+        }
+    }
+
+    if let Ok(selector) = Selector::parse("body") {
+        if let Some(body) = document.select(&selector).next() {
+            let text = visible_text(body);
+            if !text.is_empty() {
+                summary.push_str("Text:\n");
+                summary.push_str(&text);
+            }
+        }
+    }
+
+    truncate_chars(&summary, max_chars)
+}
+
+fn describe_element(element: ElementRef) -> String {
+    let value = element.value();
+    let mut description = format!("[{}", value.name());
+    if let Some(input_type) = value.attr("type") {
+        description.push_str(&format!(" type={input_type}"));
+    }
+    description.push(']');
+
+    let text = collapse_whitespace(&element.text().collect::<String>());
+    let label = Some(text)
+        .filter(|t| !t.is_empty())
+        .or_else(|| value.attr("aria-label").map(|l| l.to_string()))
+        .or_else(|| value.attr("placeholder").map(|p| p.to_string()))
+        .or_else(|| value.attr("value").map(|v| v.to_string()));
+    if let Some(label) = label {
+        description.push_str(&format!(" \"{}\"", truncate_chars(&label, 80)));
+    }
+
+    if let Some(href) = value.attr("href") {
+        description.push_str(&format!(" href={}", truncate_chars(href, 120)));
+    }
+
+    description.push_str(&format!(" selector: {}", suggest_selector(element)));
+    description
+}
+
+fn suggest_selector(element: ElementRef) -> String {
+    let value = element.value();
+    let tag = value.name();
+
+    if let Some(id) = value.id() {
+        let is_simple = id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && !id.starts_with(|c: char| c.is_ascii_digit());
+        return if is_simple {
+            format!("#{id}")
+        } else {
+            format!("[id=\"{id}\"]")
+        };
+    }
+
+    for attribute in ["name", "aria-label", "data-testid"] {
+        if let Some(attr_value) = value.attr(attribute) {
+            return format!("{tag}[{attribute}=\"{attr_value}\"]");
+        }
+    }
+
+    if let Some(href) = value.attr("href") {
+        return format!("{tag}[href=\"{href}\"]");
+    }
+
+    match value.classes().next() {
+        Some(class) => format!("{tag}.{class}"),
+        None => tag.to_string(),
+    }
+}
+
+fn visible_text(root: ElementRef) -> String {
+    let mut parts = Vec::new();
+
+    for node in root.descendants() {
+        if let Node::Text(text) = node.value() {
+            let hidden = node.ancestors().any(|ancestor| {
+                ancestor.value().as_element().is_some_and(|element| {
+                    matches!(element.name(), "script" | "style" | "noscript" | "template")
+                })
+            });
+            if !hidden {
+                let text = text.trim();
+                if !text.is_empty() {
+                    parts.push(text);
+                }
+            }
+        }
+    }
+
+    collapse_whitespace(&parts.join(" "))
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}...[truncated]", &text[..index]),
+        None => text.to_string(),
+    }
+}
@@ -1,18 +1,29 @@
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use chromiumoxide::browser::{Browser, BrowserConfig};
+use chromiumoxide::cdp::browser_protocol::browser::BrowserContextId;
+use chromiumoxide::cdp::browser_protocol::target::{
+    CreateBrowserContextParams, CreateTargetParams,
+};
 use chromiumoxide::page::Page;
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::OnceCell;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::{oneshot, RwLock};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
-use crate::types::{BrowserAction, ScrollDirection, TaskPlan, TaskResult};
-
-// Global browser singleton
-static BROWSER_SINGLETON: OnceCell<Arc<Browser>> = OnceCell::const_new();
+use crate::browser_supervisor::{BrowserLauncher, BrowserSupervisor, LaunchedBrowser};
+use crate::page_pool::{browser_page_pool, PageSlot, PooledPage};
+use crate::page_wait::navigate_and_wait;
+use crate::session_reaper::{SessionExpiry, SessionTimeouts};
+use crate::types::{
+    BrowserAction, ScrollDirection, SessionAction, SessionIsolation, TaskPlan, TaskResult,
+    WaitOutcome, WaitUntil,
+};
 
 // Helper function to determine if a browser error is fatal and should stop the handler
 fn is_fatal_browser_error(error: &chromiumoxide::error::CdpError) -> bool {
@@ -32,87 +43,279 @@ fn is_fatal_browser_error(error: &chromiumoxide::error::CdpError) -> bool {
     }
 }
 
-// Initialize the global browser instance
-async fn get_or_create_browser() -> Result<Arc<Browser>> {
-    BROWSER_SINGLETON
-        .get_or_try_init(|| async {
-            info!("Creating browser singleton instance");
-
-            let (browser, mut handler) = Browser::launch(
-                BrowserConfig::builder()
-                    .args(vec![
-                        "--headless",
-                        "--no-sandbox",
-                        "--disable-dev-shm-usage",
-                        "--disable-gpu",
-                        "--remote-debugging-port=0",
-                    ])
-                    .build()
-                    .map_err(|e| anyhow!("Failed to build browser config: {}", e))?,
-            )
-            .await
-            .map_err(|e| anyhow!("Failed to launch browser: {}", e))?;
-
-            // Spawn task to handle browser events
-            tokio::task::spawn(async move {
-                while let Some(h) = handler.next().await {
-                    match h {
-                        Ok(_) => {
-                            // Successfully handled browser event
-                        }
-                        Err(e) => {
-                            // Log the error but continue handling events
-                            // Many errors are recoverable WebSocket communication issues
-                            warn!("Browser handler encountered error (continuing): {:?}", e);
-
-                            // Only break on truly fatal errors
-                            if is_fatal_browser_error(&e) {
-                                error!("Fatal browser error, stopping handler: {:?}", e);
-                                break;
-                            }
+/// Launches headless Chromium for the browser supervisor
+pub struct ChromiumLauncher;
+
+#[async_trait]
+impl BrowserLauncher for ChromiumLauncher {
+    type Browser = Browser;
+
+    async fn launch(&self) -> Result<LaunchedBrowser<Browser>> {
+        info!("Launching browser");
+
+        let (mut browser, mut handler) = Browser::launch(
+            BrowserConfig::builder()
+                .args(vec![
+                    "--headless",
+                    "--no-sandbox",
+                    "--disable-dev-shm-usage",
+                    "--disable-gpu",
+                    "--remote-debugging-port=0",
+                ])
+                .build()
+                .map_err(|e| anyhow!("Failed to build browser config: {}", e))?,
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to launch browser: {}", e))?;
+
+        let pid = browser
+            .get_mut_child()
+            .map(|child| child.as_mut_inner().id());
+        let (exited_tx, exited) = oneshot::channel();
+
+        // Spawn task to handle browser events; the supervisor learns when it stops
+        tokio::task::spawn(async move {
+            let mut reason = "browser connection closed".to_string();
+            while let Some(h) = handler.next().await {
+                match h {
+                    Ok(_) => {
+                        // Successfully handled browser event
+                    }
+                    Err(e) => {
+                        // Log the error but continue handling events
+                        // Many errors are recoverable WebSocket communication issues
+                        warn!("Browser handler encountered error (continuing): {:?}", e);
+
+                        // Only break on truly fatal errors
+                        if is_fatal_browser_error(&e) {
+                            error!("Fatal browser error, stopping handler: {:?}", e);
+                            reason = format!("fatal browser error: {}", e);
+                            break;
                         }
                     }
                 }
-                info!("Browser handler task ended");
-            });
+            }
+            info!("Browser handler task ended");
+            let _ = exited_tx.send(reason);
+        });
 
-            info!("Browser singleton created successfully");
-            Ok(Arc::new(browser))
+        Ok(LaunchedBrowser {
+            browser,
+            pid,
+            exited,
         })
+    }
+
+    async fn is_responsive(&self, browser: &Browser) -> bool {
+        browser.version().await.is_ok()
+    }
+}
+
+/// The supervisor of the shared browser. A browser that dies is relaunched, and idle pooled
+/// pages from it are discarded.
+pub fn browser_supervisor() -> &'static Arc<BrowserSupervisor<ChromiumLauncher>> {
+    static SUPERVISOR: OnceLock<Arc<BrowserSupervisor<ChromiumLauncher>>> = OnceLock::new();
+    SUPERVISOR.get_or_init(|| {
+        Arc::new(BrowserSupervisor::new(ChromiumLauncher).on_restart(|_| {
+            tokio::spawn(browser_page_pool().clear_idle());
+        }))
+    })
+}
+
+/// Returned by session operations once the browser the session's page lived in has died
+#[derive(Debug, thiserror::Error)]
+#[error("Browser session {session_id} is no longer valid: the browser crashed and was restarted")]
+pub struct BrowserRestarted {
+    pub session_id: String,
+}
+
+/// A page in its own incognito browser context. Dropping it disposes of the context, which
+/// closes the page and discards its cookies, cache and storage.
+pub struct IsolatedPage {
+    page: Page,
+    browser: Arc<Browser>,
+    context: Option<BrowserContextId>,
+    slot: Option<PageSlot>,
+}
+
+impl Drop for IsolatedPage {
+    fn drop(&mut self) {
+        let (Some(context), Some(slot)) = (self.context.take(), self.slot.take()) else {
+            return;
+        };
+        let browser = self.browser.clone();
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    if let Err(e) = browser.dispose_browser_context(context).await {
+                        warn!("Failed to dispose of browser context: {}", e);
+                    }
+                    drop(slot);
+                });
+            }
+            // The context lives until the browser exits
+            Err(_) => info!("Dropping isolated browser page outside the runtime"),
+        }
+    }
+}
+
+/// The page a session drives
+pub enum SessionPage {
+    /// A tab in the default browser context, checked out of the shared page pool
+    Shared(PooledPage<Page>),
+    Isolated(IsolatedPage),
+}
+
+impl Deref for SessionPage {
+    type Target = Page;
+
+    fn deref(&self) -> &Page {
+        match self {
+            SessionPage::Shared(page) => page,
+            SessionPage::Isolated(isolated) => &isolated.page,
+        }
+    }
+}
+
+/// Open a page, retrying once since the first page of a fresh browser sometimes fails
+async fn new_page_with_retry(browser: &Browser, params: CreateTargetParams) -> Result<Page> {
+    match browser.new_page(params.clone()).await {
+        Ok(page) => {
+            info!("Successfully created new page in browser");
+            Ok(page)
+        }
+        Err(e) => {
+            warn!("First attempt to create page failed: {}, retrying...", e);
+
+            // Wait a moment and retry
+            tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+            browser
+                .new_page(params)
+                .await
+                .map_err(|e| anyhow!("Failed to create new page after retry: {}", e))
+        }
+    }
+}
+
+/// No element matched a selector an action needed
+#[derive(Debug, thiserror::Error)]
+#[error("Element not found: {selector}")]
+pub struct ElementNotFound {
+    pub selector: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum NavigationError {
+    #[error("Navigation to {url} timed out after {timeout_ms}ms")]
+    Timeout { url: String, timeout_ms: u64 },
+    /// The browser could not load the URL: a malformed address, DNS failure, refused
+    /// connection and so on
+    #[error("Failed to navigate to {url}: {reason}")]
+    Failed { url: String, reason: String },
+}
+
+const MAX_HISTORY: usize = 100;
+
+/// A session shared between requests. Each operation locks just this session.
+pub type SharedSession = Arc<tokio::sync::Mutex<BrowserSession>>;
+
+/// Open sessions by ID. The map lock is only held to look sessions up, add or remove them,
+/// never across a browser operation.
+pub type BrowserSessions = Arc<RwLock<HashMap<String, SharedSession>>>;
+
+/// Handles to every open session, copied out so the map isn't locked while waiting on busy
+/// sessions
+pub async fn open_sessions(
+    sessions: &RwLock<HashMap<String, SharedSession>>,
+) -> Vec<(String, SharedSession)> {
+    sessions
+        .read()
         .await
-        .map(|browser| browser.clone())
+        .iter()
+        .map(|(session_id, session)| (session_id.clone(), session.clone()))
+        .collect()
+}
+
+pub async fn find_session(
+    sessions: &RwLock<HashMap<String, SharedSession>>,
+    session_id: &str,
+) -> Option<SharedSession> {
+    sessions.read().await.get(session_id).cloned()
 }
 
-#[allow(dead_code)]
 pub struct BrowserSession {
-    browser: Arc<Browser>,
-    page: Page,
+    /// Returned to the pool (shared) or disposed of with its context (isolated) on drop
+    page: SessionPage,
     session_id: String,
+    isolation: SessionIsolation,
+    /// Supervisor generation of `browser`; the session is invalid once that browser dies
+    generation: u64,
+    /// Base64 PNG of the most recent screenshot
+    last_screenshot: Option<String>,
+    /// Result of the most recent extraction run against this page
+    last_extraction: Option<Value>,
+    /// Where the page is, as of the last navigation or page-changing action
+    current_url: Option<String>,
+    title: Option<String>,
+    /// Distinct URLs visited, oldest first, capped at `MAX_HISTORY`
+    history: Vec<String>,
+    last_action: Option<SessionAction>,
+    created_at: DateTime<Utc>,
+    /// Updated by every operation on the page; reads go through shared references
+    last_used_at: Mutex<DateTime<Utc>>,
+    timeouts: SessionTimeouts,
 }
 
 impl BrowserSession {
+    /// A session in the browser's shared default context
     pub async fn new() -> Result<Self> {
-        info!("Creating new browser session");
-
-        // Get the shared browser instance
-        let browser = get_or_create_browser().await?;
+        Self::with_isolation(SessionIsolation::Shared).await
+    }
 
-        // Create a new page in the existing browser with retry logic
-        let page = match browser.new_page("about:blank").await {
-            Ok(page) => {
-                info!("Successfully created new page in browser");
-                page
+    pub async fn with_isolation(isolation: SessionIsolation) -> Result<Self> {
+        info!("Creating new browser session ({:?})", isolation);
+
+        // Get the shared browser instance, launching or relaunching it if needed
+        let lease = browser_supervisor().get().await?;
+        let browser = lease.browser;
+
+        // Both kinds wait for a free slot when the pool is at its page limit
+        let page = match isolation {
+            SessionIsolation::Shared => {
+                // Reuse a pooled page, or create a new one in the existing browser
+                let page = browser_page_pool()
+                    .acquire(lease.generation, || {
+                        new_page_with_retry(&browser, CreateTargetParams::new("about:blank"))
+                    })
+                    .await?;
+                SessionPage::Shared(page)
             }
-            Err(e) => {
-                warn!("First attempt to create page failed: {}, retrying...", e);
-
-                // Wait a moment and retry
-                tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-
-                browser
-                    .new_page("about:blank")
+            SessionIsolation::Isolated => {
+                let slot = browser_page_pool().reserve().await?;
+                let context = browser
+                    .create_browser_context(CreateBrowserContextParams::default())
                     .await
-                    .map_err(|e| anyhow!("Failed to create new page after retry: {}", e))?
+                    .map_err(|e| anyhow!("Failed to create browser context: {}", e))?;
+
+                let mut params = CreateTargetParams::new("about:blank");
+                params.browser_context_id = Some(context.clone());
+                let page = match new_page_with_retry(&browser, params).await {
+                    Ok(page) => page,
+                    Err(e) => {
+                        if let Err(dispose_error) = browser.dispose_browser_context(context).await {
+                            warn!("Failed to dispose of browser context: {}", dispose_error);
+                        }
+                        return Err(e);
+                    }
+                };
+                SessionPage::Isolated(IsolatedPage {
+                    page,
+                    browser: browser.clone(),
+                    context: Some(context),
+                    slot: Some(slot),
+                })
             }
         };
 
@@ -123,43 +326,125 @@ impl BrowserSession {
         );
 
         Ok(Self {
-            browser,
             page,
             session_id,
+            isolation,
+            generation: lease.generation,
+            last_screenshot: None,
+            last_extraction: None,
+            current_url: None,
+            title: None,
+            history: Vec::new(),
+            last_action: None,
+            created_at: Utc::now(),
+            last_used_at: Mutex::new(Utc::now()),
+            timeouts: SessionTimeouts::from_env(),
         })
     }
 
-    pub async fn navigate(&mut self, url: &str) -> Result<()> {
-        info!("Navigating to: {}", url);
+    /// The ID clients use for this session; crash errors report it too
+    pub fn id(&self) -> &str {
+        &self.session_id
+    }
 
-        // Simple navigation without waiting for navigation events
-        // This avoids WebSocket communication issues with wait_for_navigation
-        let navigation_result = tokio::time::timeout(tokio::time::Duration::from_secs(30), async {
-            self.page
-                .goto(url)
-                .await
-                .map_err(|e| anyhow!("Failed to navigate to {}: {}", url, e))?;
+    pub fn isolation(&self) -> SessionIsolation {
+        self.isolation
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    pub fn last_used_at(&self) -> DateTime<Utc> {
+        *self.last_used_at.lock().unwrap()
+    }
 
-            // Give the page a moment to start loading, but don't wait for navigation events
-            tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
+    pub fn timeouts(&self) -> SessionTimeouts {
+        self.timeouts
+    }
+
+    pub fn set_timeouts(&mut self, timeouts: SessionTimeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Whether the session has passed one of its timeouts by `now`
+    pub fn expiry(&self, now: DateTime<Utc>) -> Option<SessionExpiry> {
+        self.timeouts
+            .expiry(self.created_at, self.last_used_at(), now)
+    }
+
+    /// False once the browser this session's page lived in has died
+    pub fn is_valid(&self) -> bool {
+        browser_supervisor().is_current(self.generation)
+    }
 
+    /// Fail if the session died with its browser; otherwise count this as a use
+    fn ensure_valid(&self) -> Result<()> {
+        if self.is_valid() {
+            *self.last_used_at.lock().unwrap() = Utc::now();
             Ok(())
-        })
-        .await
-        .map_err(|_| anyhow!("Navigation timeout after 30 seconds"))?;
+        } else {
+            Err(BrowserRestarted {
+                session_id: self.session_id.clone(),
+            }
+            .into())
+        }
+    }
+
+    /// Navigate and wait for the load event
+    pub async fn navigate(&mut self, url: &str) -> Result<()> {
+        self.navigate_until(url, &WaitUntil::default())
+            .await
+            .map(|_| ())
+    }
 
+    /// Navigate, then wait for `wait` to hold. Reaching the timeout is not an error; the
+    /// outcome reports whether the condition was met and how long it took.
+    pub async fn navigate_until(&mut self, url: &str, wait: &WaitUntil) -> Result<WaitOutcome> {
+        self.ensure_valid()?;
+        info!(
+            "Navigating to: {} (waiting for {})",
+            url,
+            wait.condition.name()
+        );
+
+        let navigation_result = navigate_and_wait(&self.page, url, wait).await;
+
+        match &navigation_result {
+            Ok(outcome) if !outcome.satisfied => warn!(
+                "Gave up waiting for {} on {} after {}ms",
+                outcome.condition, url, outcome.elapsed_ms
+            ),
+            _ => {}
+        }
+        self.record_action("Navigate", navigation_result.is_ok());
+        self.refresh_location().await;
         navigation_result
     }
 
     pub async fn interact(&mut self, action: &BrowserAction) -> Result<String> {
+        self.ensure_valid()?;
+        let result = self.perform(action).await;
+
+        self.record_action(action.name(), result.is_ok());
+        if action.changes_page() {
+            // Clicks and scripts can navigate too
+            self.refresh_location().await;
+        }
+        result
+    }
+
+    async fn perform(&mut self, action: &BrowserAction) -> Result<String> {
         match action {
             BrowserAction::Click { selector } => {
                 info!("Clicking element: {}", selector);
-                let element = self
-                    .page
-                    .find_element(selector)
-                    .await
-                    .map_err(|e| anyhow!("Element not found {}: {}", selector, e))?;
+                let element =
+                    self.page
+                        .find_element(selector)
+                        .await
+                        .map_err(|_| ElementNotFound {
+                            selector: selector.clone(),
+                        })?;
 
                 element
                     .click()
@@ -171,11 +456,13 @@ impl BrowserSession {
 
             BrowserAction::Type { selector, text } => {
                 info!("Typing '{}' into element: {}", text, selector);
-                let element = self
-                    .page
-                    .find_element(selector)
-                    .await
-                    .map_err(|e| anyhow!("Element not found {}: {}", selector, e))?;
+                let element =
+                    self.page
+                        .find_element(selector)
+                        .await
+                        .map_err(|_| ElementNotFound {
+                            selector: selector.clone(),
+                        })?;
 
                 element
                     .click()
@@ -210,7 +497,10 @@ impl BrowserSession {
                         break;
                     }
                     if start.elapsed().as_millis() > timeout as u128 {
-                        return Err(anyhow!("Element not found within {}ms", timeout));
+                        return Err(ElementNotFound {
+                            selector: selector.clone(),
+                        }
+                        .into());
                     }
                     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
                 }
@@ -248,7 +538,9 @@ impl BrowserSession {
                 // Convert to base64 for response
                 use base64::Engine;
                 let base64_image = base64::engine::general_purpose::STANDARD.encode(&screenshot);
-                Ok(format!("data:image/png;base64,{base64_image}"))
+                let data_url = format!("data:image/png;base64,{base64_image}");
+                self.last_screenshot = Some(base64_image);
+                Ok(data_url)
             }
 
             BrowserAction::GetPageSource => {
@@ -276,6 +568,7 @@ impl BrowserSession {
     }
 
     pub async fn extract_data(&self, selector: &str) -> Result<HashMap<String, Value>> {
+        self.ensure_valid()?;
         info!("Extracting data using selector: {}", selector);
 
         let script = format!(
@@ -311,6 +604,7 @@ impl BrowserSession {
     }
 
     pub async fn execute_task_plan(&mut self, plan: &TaskPlan) -> Result<Vec<TaskResult>> {
+        self.ensure_valid()?;
         info!("Executing task plan: {}", plan.description);
         let mut results = Vec::new();
 
@@ -349,7 +643,69 @@ impl BrowserSession {
         Ok(results)
     }
 
+    /// The current HTML of the page, without needing exclusive access to the session
+    pub async fn page_source(&self) -> Result<String> {
+        self.ensure_valid()?;
+        self.page
+            .content()
+            .await
+            .map_err(|e| anyhow!("Failed to get page source: {}", e))
+    }
+
+    pub fn current_url(&self) -> Option<&str> {
+        self.current_url.as_deref()
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn last_action(&self) -> Option<&SessionAction> {
+        self.last_action.as_ref()
+    }
+
+    fn record_action(&mut self, action: &str, success: bool) {
+        self.last_action = Some(SessionAction {
+            action: action.to_string(),
+            success,
+            at: Utc::now().to_rfc3339(),
+        });
+    }
+
+    /// Read the page's URL and title, adding the URL to the history when it changed
+    async fn refresh_location(&mut self) {
+        let Ok(Some(url)) = self.page.url().await else {
+            return;
+        };
+        self.title = self.page.get_title().await.ok().flatten();
+
+        if self.history.last() != Some(&url) {
+            if self.history.len() == MAX_HISTORY {
+                self.history.remove(0);
+            }
+            self.history.push(url.clone());
+        }
+        self.current_url = Some(url);
+    }
+
+    pub fn last_screenshot(&self) -> Option<&str> {
+        self.last_screenshot.as_deref()
+    }
+
+    pub fn last_extraction(&self) -> Option<&Value> {
+        self.last_extraction.as_ref()
+    }
+
+    pub fn record_extraction(&mut self, result: Value) {
+        self.last_extraction = Some(result);
+    }
+
     pub async fn get_current_url(&self) -> Result<String> {
+        self.ensure_valid()?;
         let url = self
             .page
             .url()
@@ -362,7 +718,7 @@ impl BrowserSession {
 
 impl Drop for BrowserSession {
     fn drop(&mut self) {
-        info!("Dropping browser session");
-        // The browser will be closed when dropped
+        // Dropping the page hands it back to the pool, or disposes of its browser context
+        info!("Dropping browser session {}", self.session_id);
     }
 }
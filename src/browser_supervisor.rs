@@ -0,0 +1,243 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::{error, info, warn};
+
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(15);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A freshly started browser
+pub struct LaunchedBrowser<B> {
+    pub browser: B,
+    /// OS process ID, when the launcher spawned a process
+    pub pid: Option<u32>,
+    /// Resolves with a reason once the browser's connection handler stops
+    pub exited: oneshot::Receiver<String>,
+}
+
+/// Starts browsers for the supervisor, and checks that a running one still answers
+#[async_trait]
+pub trait BrowserLauncher: Send + Sync + 'static {
+    type Browser: Send + Sync + 'static;
+
+    async fn launch(&self) -> Result<LaunchedBrowser<Self::Browser>>;
+
+    /// A cheap round trip to the browser; false means it is hung or gone
+    async fn is_responsive(&self, browser: &Self::Browser) -> bool;
+}
+
+/// The running browser, tagged with the launch (generation) it came from
+pub struct BrowserLease<B> {
+    pub browser: Arc<B>,
+    pub generation: u64,
+}
+
+struct Running<B> {
+    browser: Arc<B>,
+    generation: u64,
+    pid: Option<u32>,
+}
+
+/// Supervisor state reported by `/health`
+#[derive(Debug, Clone, Serialize)]
+pub struct SupervisorStats {
+    pub alive: bool,
+    pub generation: u64,
+    pub restarts: u64,
+    pub pid: Option<u32>,
+    pub last_failure: Option<String>,
+}
+
+type RestartHook = Box<dyn Fn(u64) + Send + Sync>;
+
+/// Owns the shared browser and replaces it when it dies.
+///
+/// Each launch gets a new generation. A browser counts as dead when its connection handler
+/// stops or it fails a periodic responsiveness probe; the supervisor then forgets it, runs the
+/// restart hook and launches a replacement. Anything holding the old generation can tell it
+/// is stale with `is_current`.
+pub struct BrowserSupervisor<L: BrowserLauncher> {
+    launcher: L,
+    current: Mutex<Option<Running<L::Browser>>>,
+    // Serializes launches so concurrent callers share one browser
+    launching: tokio::sync::Mutex<()>,
+    generation: AtomicU64,
+    restarts: AtomicU64,
+    last_failure: Mutex<Option<String>>,
+    probe_interval: Duration,
+    on_restart: Option<RestartHook>,
+}
+
+impl<L: BrowserLauncher> BrowserSupervisor<L> {
+    pub fn new(launcher: L) -> Self {
+        Self {
+            launcher,
+            current: Mutex::new(None),
+            launching: tokio::sync::Mutex::new(()),
+            generation: AtomicU64::new(0),
+            restarts: AtomicU64::new(0),
+            last_failure: Mutex::new(None),
+            probe_interval: DEFAULT_PROBE_INTERVAL,
+            on_restart: None,
+        }
+    }
+
+    pub fn with_probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+
+    /// Run `hook` with the dead generation whenever a browser dies
+    pub fn on_restart(mut self, hook: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.on_restart = Some(Box::new(hook));
+        self
+    }
+
+    pub fn launcher(&self) -> &L {
+        &self.launcher
+    }
+
+    /// The running browser, launching one if there is none
+    pub async fn get(self: &Arc<Self>) -> Result<BrowserLease<L::Browser>> {
+        if let Some(lease) = self.lease() {
+            return Ok(lease);
+        }
+
+        let _launching = self.launching.lock().await;
+        // Another caller may have launched it while we waited
+        if let Some(lease) = self.lease() {
+            return Ok(lease);
+        }
+
+        let launched = self.launcher.launch().await?;
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let browser = Arc::new(launched.browser);
+        *self.current.lock().unwrap() = Some(Running {
+            browser: browser.clone(),
+            generation,
+            pid: launched.pid,
+        });
+        info!(
+            "Browser launched (generation {}, pid {:?})",
+            generation, launched.pid
+        );
+
+        self.spawn_monitor(generation, Arc::downgrade(&browser), launched.exited);
+        Ok(BrowserLease {
+            browser,
+            generation,
+        })
+    }
+
+    /// Whether `generation` is the browser that is running now
+    pub fn is_current(&self, generation: u64) -> bool {
+        self.current
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|running| running.generation == generation)
+    }
+
+    pub fn stats(&self) -> SupervisorStats {
+        let current = self.current.lock().unwrap();
+        SupervisorStats {
+            alive: current.is_some(),
+            generation: self.generation.load(Ordering::SeqCst),
+            restarts: self.restarts.load(Ordering::SeqCst),
+            pid: current.as_ref().and_then(|running| running.pid),
+            last_failure: self.last_failure.lock().unwrap().clone(),
+        }
+    }
+
+    fn lease(&self) -> Option<BrowserLease<L::Browser>> {
+        self.current
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|running| BrowserLease {
+                browser: running.browser.clone(),
+                generation: running.generation,
+            })
+    }
+
+    fn spawn_monitor(
+        self: &Arc<Self>,
+        generation: u64,
+        browser: Weak<L::Browser>,
+        exited: oneshot::Receiver<String>,
+    ) {
+        let supervisor = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let reason = tokio::select! {
+                reason = exited => {
+                    reason.unwrap_or_else(|_| "browser handler was dropped".to_string())
+                }
+                reason = probe(supervisor.clone(), browser, generation) => match reason {
+                    Some(reason) => reason,
+                    // Replaced or shut down; nothing to report
+                    None => return,
+                },
+            };
+
+            if let Some(supervisor) = supervisor.upgrade() {
+                supervisor.browser_died(generation, reason).await;
+            }
+        });
+    }
+
+    async fn browser_died(self: &Arc<Self>, generation: u64, reason: String) {
+        {
+            let mut current = self.current.lock().unwrap();
+            if current.as_ref().map(|running| running.generation) != Some(generation) {
+                return;
+            }
+            *current = None;
+        }
+
+        self.restarts.fetch_add(1, Ordering::SeqCst);
+        *self.last_failure.lock().unwrap() = Some(reason.clone());
+        error!(
+            "Browser generation {} died ({}); its sessions are now invalid, relaunching",
+            generation, reason
+        );
+
+        if let Some(hook) = &self.on_restart {
+            hook(generation);
+        }
+
+        // Relaunch eagerly so the next request doesn't pay for it; on failure the next
+        // request tries again
+        if let Err(e) = self.get().await {
+            warn!("Browser relaunch failed: {}", e);
+        }
+    }
+}
+
+/// Probe the browser until it stops answering (`Some(reason)`), or it is no longer the
+/// current one (`None`)
+async fn probe<L: BrowserLauncher>(
+    supervisor: Weak<BrowserSupervisor<L>>,
+    browser: Weak<L::Browser>,
+    generation: u64,
+) -> Option<String> {
+    loop {
+        let interval = supervisor.upgrade()?.probe_interval;
+        tokio::time::sleep(interval).await;
+
+        let supervisor = supervisor.upgrade()?;
+        let browser = browser.upgrade()?;
+        if !supervisor.is_current(generation) {
+            return None;
+        }
+
+        let responsive =
+            tokio::time::timeout(PROBE_TIMEOUT, supervisor.launcher.is_responsive(&browser)).await;
+        if !matches!(responsive, Ok(true)) {
+            return Some("browser stopped responding".to_string());
+        }
+    }
+}
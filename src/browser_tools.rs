@@ -0,0 +1,433 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+use crate::browser::{find_session, BrowserSession, BrowserSessions};
+use crate::content_store::ContentStore;
+use crate::mcp_resources::SessionEvents;
+use crate::mcp_server::{extract_clean_text, ToolInfo};
+use crate::tools::{ImageContent, McpTool, ToolContext, ToolOutput, ToolRegistry};
+use crate::types::{AppError, BrowserAction};
+
+/// Page used for calls that arrive without an MCP session
+const DEFAULT_MCP_SESSION: &str = "default";
+
+/// Register the tools that drive the headless browser. Pages opened through MCP live in
+/// `sessions`, the same map that backs the REST `/api/browser/*` endpoints.
+pub fn register_browser_tools(
+    registry: &mut ToolRegistry,
+    content: Arc<ContentStore>,
+    sessions: BrowserSessions,
+    events: SessionEvents,
+) {
+    registry.register(Arc::new(FetchPageTool::new(content)));
+
+    let pages = Arc::new(BrowserPages::new(sessions, events));
+    for kind in BrowserToolKind::ALL {
+        registry.register(Arc::new(BrowserTool::new(kind, pages.clone())));
+    }
+    registry.on_session_closed(move |mcp_session| {
+        let pages = pages.clone();
+        let mcp_session = mcp_session.to_string();
+        tokio::spawn(async move {
+            pages.close(&mcp_session).await;
+        });
+    });
+}
+
+/// Tool error text for a failed browser operation, led by the same code the REST API reports
+/// (e.g. `[element_not_found] Element not found: #buy`)
+fn tool_error(error: anyhow::Error) -> String {
+    let error = AppError::from_browser(error);
+    format!("[{}] {}", error.code(), error)
+}
+
+/// Maps each MCP session to the browser session holding its page
+pub struct BrowserPages {
+    sessions: BrowserSessions,
+    pages: Mutex<HashMap<String, String>>,
+    events: SessionEvents,
+}
+
+impl BrowserPages {
+    pub fn new(sessions: BrowserSessions, events: SessionEvents) -> Self {
+        Self {
+            sessions,
+            pages: Mutex::new(HashMap::new()),
+            events,
+        }
+    }
+
+    /// The browser session ID for an MCP session, if it has opened a page
+    pub fn page_for(&self, mcp_session: &str) -> Option<String> {
+        self.pages.lock().unwrap().get(mcp_session).cloned()
+    }
+
+    /// Return the MCP session's page, opening one if it has none
+    async fn open(&self, mcp_session: &str) -> Result<String, String> {
+        if let Some(existing) = self.page_for(mcp_session) {
+            match find_session(&self.sessions, &existing).await {
+                Some(session) if session.lock().await.is_valid() => return Ok(existing),
+                // A page lost in a browser crash is replaced rather than reused
+                Some(_) => {
+                    self.sessions.write().await.remove(&existing);
+                    self.events.closed(&existing);
+                }
+                None => {}
+            }
+        }
+
+        let session = BrowserSession::new().await.map_err(tool_error)?;
+        let browser_session_id = session.id().to_string();
+        self.sessions.write().await.insert(
+            browser_session_id.clone(),
+            Arc::new(tokio::sync::Mutex::new(session)),
+        );
+        self.pages
+            .lock()
+            .unwrap()
+            .insert(mcp_session.to_string(), browser_session_id.clone());
+        self.events.opened(&browser_session_id);
+
+        info!(
+            "Opened browser session {} for MCP session {}",
+            browser_session_id, mcp_session
+        );
+        Ok(browser_session_id)
+    }
+
+    /// Close the MCP session's page. Returns false if it had none.
+    async fn close(&self, mcp_session: &str) -> bool {
+        let Some(browser_session_id) = self.pages.lock().unwrap().remove(mcp_session) else {
+            return false;
+        };
+        info!(
+            "Closing browser session {} for MCP session {}",
+            browser_session_id, mcp_session
+        );
+        let closed = self
+            .sessions
+            .write()
+            .await
+            .remove(&browser_session_id)
+            .is_some();
+        if closed {
+            self.events.closed(&browser_session_id);
+        }
+        closed
+    }
+
+    async fn run(&self, mcp_session: &str, action: &BrowserAction) -> Result<String, String> {
+        let browser_session_id = self
+            .page_for(mcp_session)
+            .ok_or("No browser page is open for this MCP session; call browser_open first")?;
+
+        let session = find_session(&self.sessions, &browser_session_id)
+            .await
+            .ok_or("The browser page for this MCP session was closed; call browser_open again")?;
+        let mut session = session.lock().await;
+        let result = session.interact(action).await.map_err(tool_error)?;
+        if action.changes_page() {
+            self.events.changed(&browser_session_id);
+        }
+        Ok(result)
+    }
+
+    async fn current_url(&self, mcp_session: &str) -> Option<String> {
+        let browser_session_id = self.page_for(mcp_session)?;
+        let session = find_session(&self.sessions, &browser_session_id).await?;
+        let url = session.lock().await.get_current_url().await.ok();
+        url
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BrowserToolKind {
+    Open,
+    Navigate,
+    Click,
+    Type,
+    Screenshot,
+    GetText,
+    Close,
+}
+
+impl BrowserToolKind {
+    const ALL: [BrowserToolKind; 7] = [
+        BrowserToolKind::Open,
+        BrowserToolKind::Navigate,
+        BrowserToolKind::Click,
+        BrowserToolKind::Type,
+        BrowserToolKind::Screenshot,
+        BrowserToolKind::GetText,
+        BrowserToolKind::Close,
+    ];
+
+    fn info(self) -> ToolInfo {
+        let (name, description, properties, required): (&str, &str, Value, Vec<&str>) = match self {
+            BrowserToolKind::Open => (
+                "browser_open",
+                "Open a browser page for this session, optionally loading a URL",
+                json!({ "url": { "type": "string", "description": "URL to load" } }),
+                vec![],
+            ),
+            BrowserToolKind::Navigate => (
+                "browser_navigate",
+                "Load a URL in this session's browser page, opening one if needed",
+                json!({ "url": { "type": "string", "description": "URL to load" } }),
+                vec!["url"],
+            ),
+            BrowserToolKind::Click => (
+                "browser_click",
+                "Click the element matching a CSS selector",
+                json!({ "selector": { "type": "string", "description": "CSS selector" } }),
+                vec!["selector"],
+            ),
+            BrowserToolKind::Type => (
+                "browser_type",
+                "Focus the element matching a CSS selector and type text into it",
+                json!({
+                    "selector": { "type": "string", "description": "CSS selector" },
+                    "text": { "type": "string", "description": "Text to type" }
+                }),
+                vec!["selector", "text"],
+            ),
+            BrowserToolKind::Screenshot => (
+                "browser_screenshot",
+                "Take a PNG screenshot of this session's browser page",
+                json!({}),
+                vec![],
+            ),
+            BrowserToolKind::GetText => (
+                "browser_get_text",
+                "Get the readable text of the page, or of the elements matching a CSS selector",
+                json!({
+                    "selector": {
+                        "type": "string",
+                        "description": "Optional CSS selector to limit the text to"
+                    }
+                }),
+                vec![],
+            ),
+            BrowserToolKind::Close => (
+                "browser_close",
+                "Close this session's browser page",
+                json!({}),
+                vec![],
+            ),
+        };
+
+        ToolInfo {
+            name: name.to_string(),
+            description: description.to_string(),
+            input_schema: json!({
+                "type": "object",
+                "properties": properties,
+                "required": required
+            }),
+        }
+    }
+}
+
+/// One of the `browser_*` tools, acting on the page of the calling MCP session
+pub struct BrowserTool {
+    info: ToolInfo,
+    kind: BrowserToolKind,
+    pages: Arc<BrowserPages>,
+}
+
+impl BrowserTool {
+    fn new(kind: BrowserToolKind, pages: Arc<BrowserPages>) -> Self {
+        Self {
+            info: kind.info(),
+            kind,
+            pages,
+        }
+    }
+}
+
+#[async_trait]
+impl McpTool for BrowserTool {
+    fn info(&self) -> &ToolInfo {
+        &self.info
+    }
+
+    async fn call(&self, arguments: &Value, context: &ToolContext) -> Result<ToolOutput, String> {
+        let mcp_session = context.session_id.as_deref().unwrap_or(DEFAULT_MCP_SESSION);
+        let string_arg = |name: &str| {
+            arguments[name]
+                .as_str()
+                .map(|value| value.to_string())
+                .ok_or_else(|| format!("Missing {} parameter", name))
+        };
+
+        match self.kind {
+            BrowserToolKind::Open | BrowserToolKind::Navigate => {
+                let url = match self.kind {
+                    BrowserToolKind::Navigate => Some(string_arg("url")?),
+                    _ => arguments["url"].as_str().map(|url| url.to_string()),
+                };
+
+                let browser_session_id = self.pages.open(mcp_session).await?;
+                if let Some(url) = &url {
+                    let session = find_session(&self.pages.sessions, &browser_session_id)
+                        .await
+                        .ok_or("The browser page was closed while navigating")?;
+                    session
+                        .lock()
+                        .await
+                        .navigate(url)
+                        .await
+                        .map_err(tool_error)?;
+                    self.pages.events.changed(&browser_session_id);
+                }
+
+                Ok(json!({
+                    "browser_session_id": browser_session_id,
+                    "url": self.pages.current_url(mcp_session).await
+                })
+                .into())
+            }
+            BrowserToolKind::Click => {
+                let action = BrowserAction::Click {
+                    selector: string_arg("selector")?,
+                };
+                let message = self.pages.run(mcp_session, &action).await?;
+                Ok(json!({ "message": message }).into())
+            }
+            BrowserToolKind::Type => {
+                let action = BrowserAction::Type {
+                    selector: string_arg("selector")?,
+                    text: string_arg("text")?,
+                };
+                let message = self.pages.run(mcp_session, &action).await?;
+                Ok(json!({ "message": message }).into())
+            }
+            BrowserToolKind::Screenshot => {
+                let data_url = self
+                    .pages
+                    .run(mcp_session, &BrowserAction::Screenshot)
+                    .await?;
+                let data = data_url
+                    .strip_prefix("data:image/png;base64,")
+                    .unwrap_or(&data_url)
+                    .to_string();
+
+                Ok(ToolOutput {
+                    value: Value::Null,
+                    images: vec![ImageContent {
+                        data,
+                        mime_type: "image/png".to_string(),
+                    }],
+                })
+            }
+            BrowserToolKind::GetText => {
+                let html_content = self
+                    .pages
+                    .run(mcp_session, &BrowserAction::GetPageSource)
+                    .await?;
+
+                let text = match arguments["selector"].as_str() {
+                    Some(selector) => selected_text(&html_content, selector)?,
+                    None => extract_clean_text(&html_content, arguments)?["clean_text"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                };
+
+                Ok(json!({
+                    "url": self.pages.current_url(mcp_session).await,
+                    "text": text
+                })
+                .into())
+            }
+            BrowserToolKind::Close => {
+                let closed = self.pages.close(mcp_session).await;
+                Ok(json!({ "closed": closed }).into())
+            }
+        }
+    }
+}
+
+fn selected_text(html_content: &str, selector: &str) -> Result<String, String> {
+    let selector = scraper::Selector::parse(selector)
+        .map_err(|e| format!("Invalid CSS selector {}: {:?}", selector, e))?;
+    let document = scraper::Html::parse_document(html_content);
+
+    Ok(document
+        .select(&selector)
+        .map(|element| element.text().collect::<Vec<_>>().join(" "))
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Load a URL in a fresh browser page and store the rendered HTML in the content store.
+///
+/// Returns a `content_ref` that the HTML tools accept, so a client can fetch once and then
+/// run any number of extractions without handling the page itself.
+pub struct FetchPageTool {
+    info: ToolInfo,
+    content: Arc<ContentStore>,
+}
+
+impl FetchPageTool {
+    pub fn new(content: Arc<ContentStore>) -> Self {
+        Self {
+            info: ToolInfo {
+                name: "fetch_page".to_string(),
+                description: "Load a URL in a headless browser and store the rendered HTML. \
+                    Returns a content_ref for the HTML extraction tools"
+                    .to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "url": {
+                            "type": "string",
+                            "description": "URL of the page to load"
+                        }
+                    },
+                    "required": ["url"]
+                }),
+            },
+            content,
+        }
+    }
+}
+
+#[async_trait]
+impl McpTool for FetchPageTool {
+    fn info(&self) -> &ToolInfo {
+        &self.info
+    }
+
+    async fn call(&self, arguments: &Value, _context: &ToolContext) -> Result<ToolOutput, String> {
+        let url = arguments["url"].as_str().ok_or("Missing url parameter")?;
+
+        let mut session = BrowserSession::new().await.map_err(tool_error)?;
+        session.navigate(url).await.map_err(tool_error)?;
+
+        let html_content = session
+            .interact(&BrowserAction::GetPageSource)
+            .await
+            .map_err(|e| format!("Failed to get page source: {}", e))?;
+        let final_url = session
+            .get_current_url()
+            .await
+            .unwrap_or_else(|_| url.to_string());
+
+        let page = self.content.insert(&final_url, html_content);
+        info!("Fetched {} into content store as {}", final_url, page.id);
+
+        Ok(json!({
+            "content_ref": page.id,
+            "uri": page.uri(),
+            "url": page.url,
+            "length": page.size()
+        })
+        .into())
+    }
+}
@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// URI prefix under which stored pages are exposed as MCP resources
+pub const CONTENT_URI_PREFIX: &str = "resource://content/";
+
+const DEFAULT_TTL_SECS: u64 = 600;
+const DEFAULT_MAX_MB: usize = 64;
+
+/// A fetched page held in the content store
+#[derive(Debug)]
+pub struct StoredPage {
+    pub id: String,
+    pub url: String,
+    pub html: String,
+    pub stored_at: DateTime<Utc>,
+    expires_at: Instant,
+}
+
+impl StoredPage {
+    pub fn uri(&self) -> String {
+        format!("{}{}", CONTENT_URI_PREFIX, self.id)
+    }
+
+    pub fn size(&self) -> usize {
+        self.html.len()
+    }
+}
+
+#[derive(Default)]
+struct StoreInner {
+    pages: HashMap<String, Arc<StoredPage>>,
+    /// Page IDs, oldest first. Every page shares one TTL, so this is also expiry order.
+    order: VecDeque<String>,
+    total_bytes: usize,
+}
+
+/// Page HTML kept in memory so tools can be handed a short `content_ref` instead of the page.
+///
+/// Pages expire after a fixed TTL, and the oldest pages are evicted once the total size would
+/// exceed the memory cap. A single page larger than the cap is still stored, on its own.
+pub struct ContentStore {
+    inner: Mutex<StoreInner>,
+    ttl: Duration,
+    max_bytes: usize,
+}
+
+impl ContentStore {
+    pub fn new(ttl: Duration, max_bytes: usize) -> Self {
+        Self {
+            inner: Mutex::new(StoreInner::default()),
+            ttl,
+            max_bytes,
+        }
+    }
+
+    /// Configure from `CONTENT_STORE_TTL_SECS` (default 600) and `CONTENT_STORE_MAX_MB` (default 64)
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("CONTENT_STORE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECS);
+        let max_mb = std::env::var("CONTENT_STORE_MAX_MB")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_MB);
+
+        Self::new(Duration::from_secs(ttl), max_mb * 1024 * 1024)
+    }
+
+    pub fn insert(&self, url: &str, html: String) -> Arc<StoredPage> {
+        let page = Arc::new(StoredPage {
+            id: uuid::Uuid::new_v4().to_string(),
+            url: url.to_string(),
+            html,
+            stored_at: Utc::now(),
+            expires_at: Instant::now() + self.ttl,
+        });
+
+        let mut inner = self.inner.lock().unwrap();
+        Self::purge_expired(&mut inner);
+
+        while inner.total_bytes + page.size() > self.max_bytes {
+            let Some(oldest) = inner.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = inner.pages.remove(&oldest) {
+                inner.total_bytes -= evicted.size();
+                info!(
+                    "Evicted stored page {} to stay under the memory cap",
+                    oldest
+                );
+            }
+        }
+
+        inner.total_bytes += page.size();
+        inner.order.push_back(page.id.clone());
+        inner.pages.insert(page.id.clone(), page.clone());
+        page
+    }
+
+    /// Look up a page by ID or by its `resource://content/...` URI
+    pub fn get(&self, reference: &str) -> Option<Arc<StoredPage>> {
+        let id = reference
+            .strip_prefix(CONTENT_URI_PREFIX)
+            .unwrap_or(reference);
+
+        let mut inner = self.inner.lock().unwrap();
+        Self::purge_expired(&mut inner);
+        inner.pages.get(id).cloned()
+    }
+
+    /// Live pages, oldest first
+    pub fn list(&self) -> Vec<Arc<StoredPage>> {
+        let mut inner = self.inner.lock().unwrap();
+        Self::purge_expired(&mut inner);
+        inner
+            .order
+            .iter()
+            .filter_map(|id| inner.pages.get(id).cloned())
+            .collect()
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.inner.lock().unwrap().total_bytes
+    }
+
+    fn purge_expired(inner: &mut StoreInner) {
+        let now = Instant::now();
+        while let Some(oldest) = inner.order.front() {
+            match inner.pages.get(oldest) {
+                Some(page) if page.expires_at > now => break,
+                _ => {
+                    let id = inner.order.pop_front().unwrap();
+                    if let Some(expired) = inner.pages.remove(&id) {
+                        inner.total_bytes -= expired.size();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for ContentStore {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
@@ -1,14 +1,30 @@
+pub mod agent;
 pub mod browser;
+pub mod browser_supervisor;
+pub mod browser_tools;
+pub mod content_store;
 pub mod llama_client;
-pub mod mcp;
+pub mod llm;
+pub mod mcp_client;
+pub mod mcp_http;
+pub mod mcp_resources;
 pub mod mcp_server;
+pub mod mcp_stdio;
+pub mod normalize;
+pub mod page_pool;
+pub mod page_wait;
+pub mod product_extraction;
+pub mod prompts;
+pub mod session_reaper;
+pub mod structured_data;
+pub mod tools;
 pub mod types;
 
 use anyhow::Result;
 use axum::{
-    extract::{Path, State},
+    extract::{FromRef, Path, State},
     http::StatusCode,
-    response::Json,
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
@@ -18,29 +34,107 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{info, warn};
 
-use crate::browser::BrowserSession;
+use crate::browser::{
+    browser_supervisor, find_session, open_sessions, BrowserSession, BrowserSessions, SharedSession,
+};
+use crate::browser_tools::register_browser_tools;
+use crate::content_store::ContentStore;
 use crate::llama_client::LlamaClient;
-use crate::mcp_server::create_mcp_router;
+use crate::mcp_resources::SessionEvents;
+use crate::mcp_server::{builtin_tools, create_mcp_router, MCPServerState};
+use crate::page_pool::browser_page_pool;
+use crate::product_extraction::ProductExtractor;
+use crate::prompts::PromptLibrary;
+use crate::session_reaper::{SessionReaper, SessionTimeouts};
+use crate::tools::ToolRegistry;
 use crate::types::*;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub browser_sessions: Arc<RwLock<HashMap<String, BrowserSession>>>,
+    pub browser_sessions: BrowserSessions,
     pub llama_client: Arc<LlamaClient>,
+    pub mcp_state: Arc<MCPServerState>,
+    pub session_events: SessionEvents,
 }
 
 impl AppState {
     pub async fn new() -> Result<Self> {
-        let browser_sessions = Arc::new(RwLock::new(HashMap::new()));
-        let llama_client = Arc::new(LlamaClient::new().await?);
+        let services = AgentServices::from_env().await;
+
+        // Operator prompt templates are served over MCP and used by the LLM client alike
+        let llama_client = Arc::new(
+            LlamaClient::new(services.llm_tools, services.content)
+                .await?
+                .with_prompts(services.prompts),
+        );
 
         Ok(Self {
-            browser_sessions,
+            browser_sessions: services.browser_sessions,
             llama_client,
+            mcp_state: services.mcp_state,
+            session_events: services.session_events,
         })
     }
 }
 
+/// The browser sessions, tools and prompts behind both the HTTP server and `--mcp-stdio`,
+/// built in one place so every transport offers the same tools in the same order
+pub struct AgentServices {
+    pub browser_sessions: BrowserSessions,
+    pub session_events: SessionEvents,
+    pub content: Arc<ContentStore>,
+    pub prompts: Arc<PromptLibrary>,
+    /// Built-in and external tools, as offered to the LLM
+    pub llm_tools: Arc<ToolRegistry>,
+    /// The LLM's tools followed by the browser tools, as served to MCP clients
+    pub mcp_state: Arc<MCPServerState>,
+}
+
+impl AgentServices {
+    /// Build from the environment and start the session reaper. Must be called from within
+    /// a Tokio runtime.
+    pub async fn from_env() -> Self {
+        let browser_sessions = Arc::new(RwLock::new(HashMap::new()));
+        let session_events = SessionEvents::new();
+        SessionReaper::new(browser_sessions.clone(), session_events.clone()).spawn();
+
+        // The MCP endpoint and the LLM tool loop share one content store and the same tool
+        // instances; only MCP clients additionally get the browser tools
+        let content = Arc::new(ContentStore::from_env());
+        let mut tools = builtin_tools(content.clone());
+        tools.register_mcp_servers_from_env().await;
+        let llm_tools = Arc::new(tools.clone());
+        register_browser_tools(
+            &mut tools,
+            content.clone(),
+            browser_sessions.clone(),
+            session_events.clone(),
+        );
+
+        let prompts = Arc::new(PromptLibrary::from_env());
+        let mcp_state = Arc::new(
+            MCPServerState::with_registry(Arc::new(tools), content.clone())
+                .with_prompts(prompts.clone())
+                .with_browser_sessions(browser_sessions.clone(), &session_events),
+        );
+
+        Self {
+            browser_sessions,
+            session_events,
+            content,
+            prompts,
+            llm_tools,
+            mcp_state,
+        }
+    }
+}
+
+impl FromRef<AppState> for Arc<MCPServerState> {
+    fn from_ref(state: &AppState) -> Self {
+        state.mcp_state.clone()
+    }
+}
+
 pub fn create_router() -> Router<AppState> {
     // Create the main API router
     let api_router = Router::new()
@@ -49,24 +143,32 @@ pub fn create_router() -> Router<AppState> {
         .route("/product/information", post(get_product_information))
         // Legacy browser session management (for advanced users)
         .route("/browser/session", post(create_session))
-        .route("/browser/session/:session_id", get(get_session))
+        .route(
+            "/browser/session/:session_id",
+            get(get_session).delete(delete_session),
+        )
+        .route("/browser/sessions", get(list_sessions))
         // Legacy browser actions (for advanced users)
         .route("/browser/navigate", post(navigate))
         .route("/browser/extract", post(extract))
+        .route("/browser/interact", post(interact))
         // AI automation
-        .route("/automation/task", post(process_task));
+        .route("/automation/task", post(process_task))
+        .route("/automation/agent", post(run_agent_task));
 
     // Combine with MCP server routes
     Router::new()
         .nest("/api", api_router)
-        .merge(create_mcp_router().with_state(Arc::new(crate::mcp_server::MCPServerState::new())))
+        .merge(create_mcp_router())
 }
 
 // Handler functions
 async fn health_check() -> Json<serde_json::Value> {
     Json(json!({
         "status": "ok",
-        "message": "LLM Web Agent with Llama + MCP is running"
+        "message": "LLM Web Agent with Llama + MCP is running",
+        "browser": browser_supervisor().stats(),
+        "browser_pool": browser_page_pool().stats()
     }))
 }
 
@@ -74,184 +176,340 @@ async fn health_check() -> Json<serde_json::Value> {
 async fn get_product_information(
     State(state): State<AppState>,
     Json(request): Json<ProductInformationRequest>,
-) -> Result<Json<ProductInfo>, StatusCode> {
+) -> Result<Json<ProductInfo>, AppError> {
     info!("Getting product information for URL: {}", request.url);
 
     // Create a temporary browser session
-    let mut session = match BrowserSession::new().await {
-        Ok(session) => session,
-        Err(e) => {
-            warn!("Failed to create browser session: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    // Navigate to the URL
-    if let Err(e) = session.navigate(&request.url).await {
-        warn!("Failed to navigate to {}: {}", request.url, e);
-        return Err(StatusCode::BAD_REQUEST);
-    }
+    let mut session = BrowserSession::new().await.map_err(|e| {
+        warn!("Failed to create browser session: {}", e);
+        AppError::from_browser(e)
+    })?;
 
-    // Give the page a moment to load
-    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    // Navigate to the URL and wait for it to load
+    let wait = request.wait_until.clone().unwrap_or_default();
+    session
+        .navigate_until(&request.url, &wait)
+        .await
+        .map_err(|e| {
+            warn!("Failed to navigate to {}: {}", request.url, e);
+            AppError::from_browser(e)
+        })?;
 
     // Get the page content
-    let html_content = match session.interact(&BrowserAction::GetPageSource).await {
-        Ok(content) => content,
-        Err(e) => {
+    let html_content = session
+        .interact(&BrowserAction::GetPageSource)
+        .await
+        .map_err(|e| {
             warn!("Failed to get page source: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
+            AppError::from_browser(e)
+        })?;
 
-    // Use Llama + MCP to extract product information
-    match state
-        .llama_client
-        .extract_product_information(&request.url, &html_content)
+    // Tell the model where the page actually ended up, after any redirects
+    let page_url = session.current_url().unwrap_or(&request.url).to_string();
+
+    // Read the page's own data first; the LLM only fills in what's missing
+    let product_info = ProductExtractor::new(state.llama_client.clone())
+        .extract(&page_url, &html_content)
         .await
-    {
-        Ok(product_info) => {
-            info!(
-                "Successfully extracted product information from {}",
-                request.url
-            );
-            Ok(Json(product_info))
-        }
-        Err(e) => {
+        .map_err(|e| {
             warn!("Product extraction failed for {}: {}", request.url, e);
-            Err(StatusCode::UNPROCESSABLE_ENTITY)
-        }
-    }
+            AppError::from(e)
+        })?;
+
+    info!(
+        "Successfully extracted product information from {}",
+        request.url
+    );
+    Ok(Json(product_info))
     // Note: Session will be automatically cleaned up when it goes out of scope
 }
 
 async fn create_session(
     State(state): State<AppState>,
-    Json(_request): Json<SessionCreateRequest>,
-) -> Result<Json<SessionResponse>, StatusCode> {
-    match BrowserSession::new().await {
-        Ok(session) => {
-            let session_id = uuid::Uuid::new_v4().to_string();
-            state
-                .browser_sessions
-                .write()
-                .await
-                .insert(session_id.clone(), session);
-
-            info!("Created new browser session: {}", session_id);
-            Ok(Json(SessionResponse {
-                session_id,
-                active: true,
-                current_url: None,
-                created_at: Some(chrono::Utc::now().to_rfc3339()),
-            }))
-        }
-        Err(e) => {
+    Json(request): Json<SessionCreateRequest>,
+) -> Result<Json<SessionResponse>, AppError> {
+    let mut session = BrowserSession::with_isolation(request.isolation)
+        .await
+        .map_err(|e| {
             warn!("Failed to create browser session: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+            AppError::from_browser(e)
+        })?;
+    session.set_timeouts(
+        SessionTimeouts::from_env()
+            .with_idle_seconds(request.timeout_seconds)
+            .with_max_lifetime_seconds(request.max_lifetime_seconds),
+    );
+
+    let session_id = session.id().to_string();
+    let response = session_response(&session_id, &session);
+    state.browser_sessions.write().await.insert(
+        session_id.clone(),
+        Arc::new(tokio::sync::Mutex::new(session)),
+    );
+    state.session_events.opened(&session_id);
+
+    info!(
+        "Created new {:?} browser session: {}",
+        request.isolation, session_id
+    );
+    Ok(Json(response))
+}
+
+fn session_response(session_id: &str, session: &BrowserSession) -> SessionResponse {
+    SessionResponse {
+        session_id: session_id.to_string(),
+        // Sessions outlive a browser crash, but can no longer be used
+        active: session.is_valid(),
+        busy: false,
+        isolation: session.isolation(),
+        current_url: session.current_url().map(str::to_string),
+        title: session.title().map(str::to_string),
+        history: session.history().to_vec(),
+        last_action: session.last_action().cloned(),
+        created_at: Some(session.created_at().to_rfc3339()),
+        last_used_at: Some(session.last_used_at().to_rfc3339()),
+    }
+}
+
+fn busy_session_response(session_id: &str) -> SessionResponse {
+    SessionResponse {
+        session_id: session_id.to_string(),
+        active: true,
+        busy: true,
+        isolation: SessionIsolation::default(),
+        current_url: None,
+        title: None,
+        history: Vec::new(),
+        last_action: None,
+        created_at: None,
+        last_used_at: None,
     }
 }
 
 async fn get_session(
     State(state): State<AppState>,
     Path(session_id): Path<String>,
-) -> Result<Json<SessionResponse>, StatusCode> {
-    let sessions = state.browser_sessions.read().await;
-
-    if sessions.contains_key(&session_id) {
-        Ok(Json(SessionResponse {
-            session_id: session_id.clone(),
-            active: true,
-            current_url: None, // TODO: Get current URL from session
-            created_at: None,
-        }))
-    } else {
-        Err(StatusCode::NOT_FOUND)
+) -> Result<Json<SessionResponse>, AppError> {
+    let session = shared_session(&state, &session_id).await?;
+    let session = session.lock().await;
+
+    Ok(Json(session_response(&session_id, &session)))
+}
+
+async fn list_sessions(State(state): State<AppState>) -> Json<SessionListResponse> {
+    let mut sessions = Vec::new();
+    for (session_id, session) in open_sessions(&state.browser_sessions).await {
+        // Waiting for a session mid-action would stall the whole listing behind it
+        sessions.push(match session.try_lock() {
+            Ok(session) => session_response(&session_id, &session),
+            Err(_) => busy_session_response(&session_id),
+        });
     }
+    sessions.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+    Json(SessionListResponse { sessions })
+}
+
+async fn delete_session(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    // The page goes back to the pool once any operation still running on it finishes
+    if state
+        .browser_sessions
+        .write()
+        .await
+        .remove(&session_id)
+        .is_none()
+    {
+        return Err(AppError::SessionNotFound(session_id));
+    }
+    state.session_events.closed(&session_id);
+
+    info!("Closed browser session: {}", session_id);
+    Ok(StatusCode::NO_CONTENT)
 }
 
 async fn navigate(
     State(state): State<AppState>,
     Json(request): Json<NavigateRequest>,
-) -> Result<Json<NavigateResponse>, StatusCode> {
-    let mut sessions = state.browser_sessions.write().await;
-
-    if let Some(session) = sessions.get_mut(&request.session_id) {
-        match session.navigate(&request.url).await {
-            Ok(_) => {
-                info!(
-                    "Navigated to {} in session {}",
-                    request.url, request.session_id
-                );
-                Ok(Json(NavigateResponse {
-                    success: true,
-                    current_url: request.url.clone(),
-                }))
-            }
-            Err(e) => {
-                warn!("Navigation failed: {}", e);
-                Err(StatusCode::INTERNAL_SERVER_ERROR)
-            }
-        }
-    } else {
-        Err(StatusCode::NOT_FOUND)
-    }
+) -> Result<Json<NavigateResponse>, AppError> {
+    let session = shared_session(&state, &request.session_id).await?;
+    let mut session = session.lock().await;
+
+    let wait = request.wait_until.clone().unwrap_or_default();
+    let outcome = session
+        .navigate_until(&request.url, &wait)
+        .await
+        .map_err(|e| {
+            warn!("Navigation failed: {}", e);
+            AppError::from_browser(e)
+        })?;
+
+    state.session_events.changed(&request.session_id);
+    info!(
+        "Navigated to {} in session {}",
+        request.url, request.session_id
+    );
+    Ok(Json(NavigateResponse {
+        success: true,
+        current_url: session.current_url().unwrap_or(&request.url).to_string(),
+        wait: Some(outcome),
+    }))
 }
 
 async fn extract(
     State(state): State<AppState>,
     Json(request): Json<ExtractRequest>,
-) -> Result<Json<ProductInfo>, StatusCode> {
-    let mut sessions = state.browser_sessions.write().await;
-
-    if let Some(session) = sessions.get_mut(&request.session_id) {
-        // Get the current page HTML
-        match session.interact(&BrowserAction::GetPageSource).await {
-            Ok(html_content) => {
-                let current_url = "https://example.com".to_string(); // TODO: Get actual URL
-
-                // Use Llama + MCP to extract product information
-                match state
-                    .llama_client
-                    .extract_product_information(&current_url, &html_content)
-                    .await
-                {
-                    Ok(product_info) => {
-                        info!("Successfully extracted product information using Llama + MCP");
-                        Ok(Json(product_info))
-                    }
-                    Err(e) => {
-                        warn!("Product extraction failed: {}", e);
-                        Err(StatusCode::INTERNAL_SERVER_ERROR)
-                    }
-                }
-            }
-            Err(e) => {
+) -> Result<Json<ProductInfo>, AppError> {
+    let session = shared_session(&state, &request.session_id).await?;
+
+    // Read the page, then let the session go while the model works
+    let (current_url, html_content) = {
+        let mut session = session.lock().await;
+        let html_content = session
+            .interact(&BrowserAction::GetPageSource)
+            .await
+            .map_err(|e| {
                 warn!("Failed to get page source: {}", e);
-                Err(StatusCode::INTERNAL_SERVER_ERROR)
-            }
-        }
-    } else {
-        Err(StatusCode::NOT_FOUND)
+                AppError::from_browser(e)
+            })?;
+        let current_url = session.get_current_url().await.map_err(|e| {
+            warn!("Failed to get current URL: {}", e);
+            AppError::from_browser(e)
+        })?;
+        (current_url, html_content)
+    };
+
+    let product_info = ProductExtractor::new(state.llama_client.clone())
+        .extract(&current_url, &html_content)
+        .await
+        .map_err(|e| {
+            warn!("Product extraction failed: {}", e);
+            AppError::from(e)
+        })?;
+
+    if let Ok(result) = serde_json::to_value(&product_info) {
+        session.lock().await.record_extraction(result);
+        state.session_events.changed(&request.session_id);
+    }
+    info!("Successfully extracted product information using Llama + MCP");
+    Ok(Json(product_info))
+}
+
+/// Look up a session for a handler that reports `AppError`s
+async fn shared_session(state: &AppState, session_id: &str) -> Result<SharedSession, AppError> {
+    find_session(&state.browser_sessions, session_id)
+        .await
+        .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))
+}
+
+async fn interact(
+    State(state): State<AppState>,
+    Json(request): Json<InteractionRequest>,
+) -> Result<Json<InteractionResponse>, AppError> {
+    let session = shared_session(&state, &request.session_id).await?;
+    let mut session = session.lock().await;
+
+    let result = session.interact(&request.action).await.map_err(|e| {
+        warn!(
+            "Interaction failed in session {}: {}",
+            request.session_id, e
+        );
+        AppError::from_browser(e)
+    })?;
+    if request.action.changes_page() {
+        state.session_events.changed(&request.session_id);
     }
+
+    info!("Performed interaction in session {}", request.session_id);
+    Ok(Json(InteractionResponse {
+        success: true,
+        result: Some(result),
+    }))
 }
 
 async fn process_task(
     State(state): State<AppState>,
     Json(request): Json<AutomationRequest>,
-) -> Result<Json<TaskPlan>, StatusCode> {
+) -> Result<Response, AppError> {
     info!("Processing automation task with Llama + MCP");
 
-    match state
+    // An unknown session fails fast rather than after a full LLM planning round
+    let session = if request.dry_run {
+        None
+    } else {
+        Some(shared_session(&state, &request.session_id).await?)
+    };
+
+    let task_plan = state
         .llama_client
         .process_automation_request(&request)
         .await
-    {
-        Ok(task_plan) => Ok(Json(task_plan)),
-        Err(e) => {
+        .map_err(|e| {
             warn!("Task processing failed: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+            AppError::from(e)
+        })?;
+
+    let Some(session) = session else {
+        return Ok(Json(task_plan).into_response());
+    };
+    let mut session = session.lock().await;
+
+    if let Some(url) = &request.target_url {
+        session.navigate(url).await.map_err(|e| {
+            warn!("Navigation to target URL {} failed: {}", url, e);
+            AppError::from_browser(e)
+        })?;
     }
+
+    let results = session
+        .execute_task_plan(&task_plan)
+        .await
+        .map_err(AppError::from_browser)?;
+    state.session_events.changed(&request.session_id);
+
+    let task_id = uuid::Uuid::new_v4().to_string();
+    let success = results.iter().all(|result| result.success);
+    info!(
+        "Executed task {} in session {} ({} steps, success: {})",
+        task_id,
+        request.session_id,
+        results.len(),
+        success
+    );
+
+    Ok(Json(AutomationResponse {
+        success,
+        task_id,
+        plan: task_plan,
+        results,
+    })
+    .into_response())
+}
+
+async fn run_agent_task(
+    State(state): State<AppState>,
+    Json(request): Json<AgentRequest>,
+) -> Result<Json<AgentResponse>, AppError> {
+    info!("Running agent task in session {}", request.session_id);
+
+    let session = shared_session(&state, &request.session_id).await?;
+    let mut session = session.lock().await;
+
+    if let Some(url) = &request.target_url {
+        session.navigate(url).await.map_err(|e| {
+            warn!("Navigation to target URL {} failed: {}", url, e);
+            AppError::from_browser(e)
+        })?;
+    }
+
+    let response = crate::agent::run_agent(&state.llama_client, &mut session, &request).await;
+    state.session_events.changed(&request.session_id);
+    let response = response.map_err(|e| {
+        warn!("Agent task failed: {}", e);
+        AppError::from_browser(e)
+    })?;
+
+    Ok(Json(response))
 }
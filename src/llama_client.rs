@@ -1,103 +1,53 @@
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::env;
+use std::sync::Arc;
 use tracing::{info, warn};
 
+use crate::agent::replies_to;
+use crate::content_store::ContentStore;
+use crate::llm::{ChatResponse, LlmConfig, LlmError, LlmProvider, Message, Tool, ToolCall};
+use crate::normalize;
+use crate::prompts::{prompt_arguments, PromptLibrary, PRODUCT_EXTRACTION_PROMPT};
+use crate::tools::{ToolContext, ToolFailed, ToolRegistry};
 use crate::types::{AutomationRequest, BrowserAction, ProductInfo, TaskPlan, TaskStep};
 
-#[derive(Debug, Clone)]
-pub enum LlamaMode {
-    Local, // Using local Ollama
-           // Could add cloud options later if needed
-}
-
 pub struct LlamaClient {
-    client: Client,
-    mode: LlamaMode,
-    ollama_endpoint: String,
-    mcp_endpoint: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaRequest {
-    model: String,
-    messages: Vec<Message>,
-    stream: bool,
-    tools: Option<Vec<Tool>>,
-    options: Option<OllamaOptions>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaOptions {
-    temperature: f32,
-    num_predict: Option<usize>,
+    provider: Arc<dyn LlmProvider>,
+    tools: Arc<ToolRegistry>,
+    content: Arc<ContentStore>,
+    prompts: Arc<PromptLibrary>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaResponse {
-    message: ResponseMessage,
-    done: bool,
-}
+const PLAN_TOOL_NAME: &str = "create_task_plan";
+const MAX_PLANNING_ATTEMPTS: usize = 3;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Message {
-    role: String,
-    content: String,
-    tool_calls: Option<Vec<ToolCall>>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Tool {
-    #[serde(rename = "type")]
-    tool_type: String,
-    function: ToolFunction,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct ToolFunction {
-    name: String,
-    description: String,
-    parameters: Value,
-}
+impl LlamaClient {
+    pub async fn new(tools: Arc<ToolRegistry>, content: Arc<ContentStore>) -> anyhow::Result<Self> {
+        let provider = LlmConfig::from_env().build_provider()?;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ResponseMessage {
-    content: Option<String>,
-    tool_calls: Option<Vec<ToolCall>>,
-}
+        Ok(Self::with_provider(provider, tools, content))
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct ToolCall {
-    id: Option<String>,
-    #[serde(rename = "type")]
-    call_type: Option<String>,
-    function: ToolCallFunction,
-}
+    pub fn with_provider(
+        provider: Arc<dyn LlmProvider>,
+        tools: Arc<ToolRegistry>,
+        content: Arc<ContentStore>,
+    ) -> Self {
+        Self {
+            provider,
+            tools,
+            content,
+            prompts: Arc::new(PromptLibrary::builtin()),
+        }
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct ToolCallFunction {
-    name: String,
-    arguments: String,
-}
+    /// Use `prompts` (for example one with operator templates loaded) instead of the built-ins
+    pub fn with_prompts(mut self, prompts: Arc<PromptLibrary>) -> Self {
+        self.prompts = prompts;
+        self
+    }
 
-impl LlamaClient {
-    pub async fn new() -> anyhow::Result<Self> {
-        let ollama_endpoint =
-            env::var("OLLAMA_ENDPOINT").unwrap_or_else(|_| "http://localhost:11434".to_string());
-
-        let mcp_endpoint =
-            env::var("MCP_ENDPOINT").unwrap_or_else(|_| "http://localhost:3000".to_string());
-
-        info!("Using Ollama endpoint: {}", ollama_endpoint);
-        info!("Using MCP endpoint: {}", mcp_endpoint);
-
-        Ok(Self {
-            client: Client::new(),
-            mode: LlamaMode::Local,
-            ollama_endpoint,
-            mcp_endpoint,
-        })
+    pub fn provider(&self) -> &Arc<dyn LlmProvider> {
+        &self.provider
     }
 
     pub async fn extract_product_information(
@@ -105,37 +55,47 @@ impl LlamaClient {
         url: &str,
         html_content: &str,
     ) -> anyhow::Result<ProductInfo> {
+        match self.extract_product_fields(url, html_content, &[]).await? {
+            Some(product) => Ok(product),
+            None => {
+                warn!("Could not extract product information using MCP tools, using fallback");
+                Ok(self.create_fallback_product_info())
+            }
+        }
+    }
+
+    /// Run the tool-calling extraction, asking only for `fields` when some are given (for
+    /// example those the page's structured data didn't provide). `None` when the model gives
+    /// no answer within the turn limit.
+    pub async fn extract_product_fields(
+        &self,
+        url: &str,
+        html_content: &str,
+        fields: &[&str],
+    ) -> anyhow::Result<Option<ProductInfo>> {
         info!("Extracting product information using Llama + MCP tools");
+        info!(
+            "Using LLM provider: {} ({})",
+            self.provider.name(),
+            self.provider.model()
+        );
         info!("URL: {}", url);
         info!("HTML content length: {} characters", html_content.len());
 
-        // First, let's get the available MCP tools
-        let tools = self.get_mcp_tools().await?;
-
-        // Create a conversation with the Llama model
-        let system_prompt = self.get_enhanced_product_extraction_prompt();
-        let user_prompt = format!(
-            "I need to extract product information from this web page. The URL is: {}\n\n\
-            I have the raw HTML content available. Please use the appropriate tools to:\n\
-            1. First analyze the page structure to understand what kind of site this is\n\
-            2. Extract clean, structured product data\n\
-            3. Return the product information in a clear format\n\n\
-            Start by analyzing the page structure.",
-            url
-        );
+        let tools = self.tools.llm_tools();
+        info!("Offering {} MCP tools", tools.len());
 
-        let mut messages = vec![
-            Message {
-                role: "system".to_string(),
-                content: system_prompt,
-                tool_calls: None,
-            },
-            Message {
-                role: "user".to_string(),
-                content: user_prompt,
-                tool_calls: None,
-            },
-        ];
+        // Tools read the page from the content store, so the HTML is never re-sent per call
+        let page = self.content.insert(url, html_content.to_string());
+
+        // Create a conversation with the Llama model from the shared prompt template
+        let mut messages = self.product_extraction_messages(url, &page.id)?;
+        if !fields.is_empty() {
+            messages.push(Message::user(format!(
+                "Other fields are already known. Only look for: {}. Leave out any you can't find.",
+                fields.join(", ")
+            )));
+        }
 
         // Run the conversation with tool calling
         let mut conversation_turns = 0;
@@ -144,203 +104,106 @@ impl LlamaClient {
         while conversation_turns < max_turns {
             let response = self.call_llama_with_tools(&messages, &tools).await?;
 
-            if let Some(tool_calls) = &response.message.tool_calls {
+            if let Some(tool_calls) = &response.tool_calls {
                 // Execute tool calls
                 for tool_call in tool_calls {
-                    let tool_result = self.execute_mcp_tool(tool_call, html_content, url).await?;
+                    let tool_result = self.execute_mcp_tool(tool_call, &page.id, url).await?;
 
                     // Add tool result to conversation
                     messages.push(Message {
-                        role: "assistant".to_string(),
-                        content: response.message.content.clone().unwrap_or_default(),
                         tool_calls: Some(vec![tool_call.clone()]),
+                        ..Message::assistant(&response)
                     });
-
-                    messages.push(Message {
-                        role: "tool".to_string(),
-                        content: tool_result,
-                        tool_calls: None,
-                    });
+                    messages.push(Message::tool_result(tool_call, tool_result));
                 }
 
                 conversation_turns += 1;
             } else {
                 // No more tool calls, parse final response
-                if let Some(content) = &response.message.content {
-                    return self.parse_final_product_response(content);
+                if let Some(content) = &response.content {
+                    return self.parse_final_product_response(content).map(Some);
                 }
                 break;
             }
         }
 
-        // Fallback if we couldn't get a good response
-        warn!("Could not extract product information using MCP tools, using fallback");
-        Ok(self.create_fallback_product_info())
-    }
-
-    async fn get_mcp_tools(&self) -> anyhow::Result<Vec<Tool>> {
-        let manifest_url = format!("{}/.well-known/mcp/manifest.json", self.mcp_endpoint);
-
-        let response = self
-            .client
-            .get(&manifest_url)
-            .send()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to fetch MCP manifest: {}", e))?;
-
-        let manifest: Value = response
-            .json()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to parse MCP manifest: {}", e))?;
-
-        let mut tools = Vec::new();
-
-        if let Some(tool_list) = manifest.get("tools").and_then(|t| t.as_array()) {
-            for tool_def in tool_list {
-                if let (Some(name), Some(description), Some(input_schema)) = (
-                    tool_def.get("name").and_then(|n| n.as_str()),
-                    tool_def.get("description").and_then(|d| d.as_str()),
-                    tool_def.get("input_schema"),
-                ) {
-                    tools.push(Tool {
-                        tool_type: "function".to_string(),
-                        function: ToolFunction {
-                            name: name.to_string(),
-                            description: description.to_string(),
-                            parameters: input_schema.clone(),
-                        },
-                    });
-                }
-            }
-        }
-
-        info!("Loaded {} MCP tools", tools.len());
-        Ok(tools)
+        Ok(None)
     }
 
-    async fn call_llama_with_tools(
+    pub(crate) async fn call_llama_with_tools(
         &self,
         messages: &[Message],
         tools: &[Tool],
-    ) -> anyhow::Result<OllamaResponse> {
-        let chat_endpoint = format!("{}/api/chat", self.ollama_endpoint);
-
-        // Use a capable Llama model with function calling support
-        let model = env::var("LLAMA_MODEL").unwrap_or_else(|_| "llama3.2:latest".to_string());
-
-        let request = OllamaRequest {
-            model,
-            messages: messages.to_vec(),
-            stream: false,
-            tools: if tools.is_empty() {
-                None
-            } else {
-                Some(tools.to_vec())
-            },
-            options: Some(OllamaOptions {
-                temperature: 0.1,
-                num_predict: Some(2000),
-            }),
-        };
-
-        info!("Calling Llama model with {} tools available", tools.len());
-
-        let response = self
-            .client
-            .post(&chat_endpoint)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to call Ollama: {}", e))?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("Ollama API error: {}", error_text));
-        }
-
-        let ollama_response: OllamaResponse = response
-            .json()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to parse Ollama response: {}", e))?;
-
-        Ok(ollama_response)
+    ) -> anyhow::Result<ChatResponse> {
+        self.provider.chat(messages, tools).await
     }
 
     async fn execute_mcp_tool(
         &self,
         tool_call: &ToolCall,
-        html_content: &str,
+        content_ref: &str,
         url: &str,
     ) -> anyhow::Result<String> {
-        let mcp_url = format!("{}/mcp", self.mcp_endpoint);
-
-        // Parse the tool arguments
-        let arguments: Value =
-            serde_json::from_str(&tool_call.function.arguments).unwrap_or_else(|_| json!({}));
-
-        // Add HTML content to arguments if not present
-        let mut final_arguments = arguments;
-        if final_arguments.get("html_content").is_none() {
-            final_arguments["html_content"] = json!(html_content);
+        // The model only names the tool; the page is always the one stored for this extraction.
+        // Tools from external MCP servers only get the page arguments their schema declares.
+        let mut arguments = tool_call.function.parsed_arguments();
+        if !arguments.is_object() {
+            arguments = json!({});
         }
-        if final_arguments.get("url").is_none() {
-            final_arguments["url"] = json!(url);
-        }
-
-        let mcp_request = json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "tools/call",
-            "params": {
-                "name": tool_call.function.name,
-                "arguments": final_arguments
+        let schema = self
+            .tools
+            .get(&tool_call.function.name)
+            .map(|tool| tool.info().input_schema.clone())
+            .unwrap_or_default();
+        if let Some(arguments) = arguments.as_object_mut() {
+            arguments.remove("html_content");
+            if schema_declares(&schema, "content_ref") {
+                arguments.insert("content_ref".to_string(), json!(content_ref));
             }
-        });
+            if schema_declares(&schema, "url") {
+                arguments.entry("url").or_insert_with(|| json!(url));
+            }
+        }
 
         info!("Executing MCP tool: {}", tool_call.function.name);
 
-        let response = self
-            .client
-            .post(&mcp_url)
-            .json(&mcp_request)
-            .send()
+        let result = self
+            .tools
+            .call(
+                &tool_call.function.name,
+                &arguments,
+                &ToolContext::default(),
+            )
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to call MCP tool: {}", e))?;
+            .map_err(|message| ToolFailed {
+                tool: tool_call.function.name.clone(),
+                message,
+            })?;
 
-        let mcp_response: Value = response
-            .json()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to parse MCP response: {}", e))?;
-
-        if let Some(error) = mcp_response.get("error") {
-            return Err(anyhow::anyhow!("MCP tool error: {}", error));
-        }
-
-        if let Some(result) = mcp_response.get("result") {
-            Ok(serde_json::to_string_pretty(result).unwrap_or_default())
-        } else {
-            Err(anyhow::anyhow!("No result from MCP tool"))
-        }
+        Ok(serde_json::to_string_pretty(&result.value).unwrap_or_default())
     }
 
-    fn get_enhanced_product_extraction_prompt(&self) -> String {
-        "You are an expert web scraping assistant with access to specialized HTML parsing tools. \
-        Your job is to extract product information from e-commerce websites using the available tools.
-
-Available tools:
-- analyze_page_structure: Identifies the type of e-commerce platform and suggests extraction strategies
-- extract_product_data: Uses CSS selectors and JSON-LD to extract structured product information
-- extract_clean_text: Removes clutter and extracts clean, readable content
-- extract_by_selectors: Extract specific data using custom CSS selectors
-
-Best practices:
-1. Always start by analyzing the page structure to understand the website type
-2. Use extract_product_data for comprehensive product extraction
-3. If extract_product_data doesn't work well, use extract_by_selectors with specific selectors
-4. Focus on extracting: name, price, description, availability, brand, rating, image URL
-5. Return results in a clear, structured format
-
-Work step by step and use the most appropriate tools for each task.".to_string()
+    fn product_extraction_messages(
+        &self,
+        url: &str,
+        content_ref: &str,
+    ) -> anyhow::Result<Vec<Message>> {
+        let prompt = self
+            .prompts
+            .render(
+                PRODUCT_EXTRACTION_PROMPT,
+                &prompt_arguments([("url", url), ("content_ref", content_ref)]),
+            )
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        let mut messages: Vec<Message> = prompt.system.map(Message::system).into_iter().collect();
+        for (role, text) in prompt.messages {
+            messages.push(Message {
+                role,
+                ..Message::user(text)
+            });
+        }
+        Ok(messages)
     }
 
     fn parse_final_product_response(&self, content: &str) -> anyhow::Result<ProductInfo> {
@@ -351,37 +214,11 @@ Work step by step and use the most appropriate tools for each task.".to_string()
             if let Some(end) = content.rfind('}') {
                 let json_str = &content[start..=end];
                 if let Ok(parsed) = serde_json::from_str::<Value>(json_str) {
+                    // Numbers, schema.org objects and display strings all normalize here
                     return Ok(ProductInfo {
-                        name: parsed
-                            .get("name")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string()),
-                        description: parsed
-                            .get("description")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string()),
-                        price: parsed
-                            .get("price")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string()),
-                        availability: parsed
-                            .get("availability")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string()),
-                        brand: parsed
-                            .get("brand")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string()),
-                        rating: parsed
-                            .get("rating")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string()),
-                        image_url: parsed
-                            .get("image_url")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string()),
                         raw_data: Some(content.to_string()),
                         raw_llm_response: Some(content.to_string()),
+                        ..normalize::product_from_json(&parsed)
                     });
                 }
             }
@@ -393,15 +230,9 @@ Work step by step and use the most appropriate tools for each task.".to_string()
 
     fn parse_text_response(&self, content: &str) -> ProductInfo {
         let mut product_info = ProductInfo {
-            name: None,
-            description: None,
-            price: None,
-            availability: None,
-            brand: None,
-            rating: None,
-            image_url: None,
             raw_data: Some(content.to_string()),
             raw_llm_response: Some(content.to_string()),
+            ..Default::default()
         };
 
         // Simple text parsing for common patterns
@@ -414,7 +245,15 @@ Work step by step and use the most appropriate tools for each task.".to_string()
                 }
             } else if line.to_lowercase().contains("price:") {
                 if let Some(price) = line.split(':').nth(1) {
-                    product_info.price = Some(price.trim().to_string());
+                    product_info.price = normalize::parse_price(price);
+                }
+            } else if line.to_lowercase().contains("rating:") {
+                if let Some(rating) = line.split(':').nth(1) {
+                    product_info.rating = normalize::parse_rating(rating);
+                }
+            } else if line.to_lowercase().contains("availability:") {
+                if let Some(availability) = line.split(':').nth(1) {
+                    product_info.availability = normalize::parse_availability(availability);
                 }
             } else if line.to_lowercase().contains("brand:") {
                 if let Some(brand) = line.split(':').nth(1) {
@@ -436,17 +275,11 @@ Work step by step and use the most appropriate tools for each task.".to_string()
             description: Some(
                 "Product information extraction failed using Llama + MCP".to_string(),
             ),
-            price: None,
-            availability: None,
-            brand: None,
-            rating: None,
-            image_url: None,
-            raw_data: None,
             raw_llm_response: Some("Fallback mode - MCP extraction failed".to_string()),
+            ..Default::default()
         }
     }
 
-    // Automation functionality (you can extend this later)
     pub async fn process_automation_request(
         &self,
         request: &AutomationRequest,
@@ -456,15 +289,263 @@ Work step by step and use the most appropriate tools for each task.".to_string()
             request.task_description
         );
 
-        // For now, return a simple plan - you can enhance this with MCP tools later
-        Ok(TaskPlan {
-            description: format!("Llama-generated plan for: {}", request.task_description),
-            steps: vec![TaskStep {
-                id: "analyze".to_string(),
-                action: BrowserAction::Screenshot,
-                description: "Take screenshot to analyze page".to_string(),
-                expected_outcome: Some("Screenshot captured for analysis".to_string()),
-            }],
-        })
+        let tools = vec![self.get_task_plan_tool()];
+        let mut messages = vec![
+            Message::system(self.get_task_planning_prompt()),
+            Message::user(self.format_task_planning_request(request)),
+        ];
+
+        let mut last_errors = Vec::new();
+
+        for attempt in 1..=MAX_PLANNING_ATTEMPTS {
+            // An unreachable or failing LLM is reported rather than replaced by a stand-in plan
+            let response = self.call_llama_with_tools(&messages, &tools).await?;
+
+            let candidate = self.extract_task_plan_candidate(&response);
+            let errors = match candidate {
+                Some(value) => match parse_task_plan(&value) {
+                    Ok(plan) => {
+                        info!(
+                            "Generated task plan with {} steps on attempt {}",
+                            plan.steps.len(),
+                            attempt
+                        );
+                        return Ok(plan);
+                    }
+                    Err(errors) => errors,
+                },
+                None => vec![format!(
+                    "No task plan found. Call the {PLAN_TOOL_NAME} tool or reply with a JSON object."
+                )],
+            };
+
+            warn!(
+                "Task plan attempt {} was invalid: {}",
+                attempt,
+                errors.join("; ")
+            );
+
+            // A plan sent as a tool call has to be answered by a tool message
+            let feedback = format!(
+                "The task plan you returned is invalid:\n- {}\n\n\
+                Fix these problems and return the complete corrected plan.",
+                errors.join("\n- ")
+            );
+            messages.push(Message::assistant(&response));
+            messages.extend(replies_to(&response, &feedback, &feedback));
+            last_errors = errors;
+        }
+
+        Err(LlmError::UnparseableOutput(format!(
+            "invalid task plan after {} attempts: {}",
+            MAX_PLANNING_ATTEMPTS,
+            last_errors.join("; ")
+        ))
+        .into())
+    }
+
+    fn extract_task_plan_candidate(&self, message: &ChatResponse) -> Option<Value> {
+        // Prefer a structured tool call
+        if let Some(tool_calls) = &message.tool_calls {
+            if let Some(tool_call) = tool_calls
+                .iter()
+                .find(|call| call.function.name == PLAN_TOOL_NAME)
+            {
+                return Some(tool_call.function.parsed_arguments());
+            }
+        }
+
+        // Fall back to a JSON object embedded in the content
+        let content = message.content.as_deref()?;
+        let start = content.find('{')?;
+        let end = content.rfind('}')?;
+        serde_json::from_str(&content[start..=end]).ok()
+    }
+
+    fn get_task_plan_tool(&self) -> Tool {
+        Tool::function(
+            PLAN_TOOL_NAME,
+            "Create a browser automation task plan",
+            json!({
+                    "type": "object",
+                    "properties": {
+                        "description": {
+                            "type": "string",
+                            "description": "Overall description of the task"
+                        },
+                        "steps": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "id": {"type": "string"},
+                                    "action": BrowserAction::json_schema(),
+                                    "description": {"type": "string"},
+                                    "expected_outcome": {"type": "string"}
+                                },
+                                "required": ["id", "action", "description"]
+                            }
+                        }
+                    },
+                    "required": ["description", "steps"]
+            }),
+        )
+    }
+
+    fn get_task_planning_prompt(&self) -> String {
+        "You are a web automation assistant. Your job is to create detailed task plans for browser automation.
+
+Given a user's automation request, break it down into specific browser actions. \
+The browser is already on the target page when the plan starts.
+
+Available browser actions (the value of the \"type\" field):
+- Click: Click on an element using a CSS selector
+- Type: Type text into an input field
+- Wait: Wait for a specified duration
+- WaitForElement: Wait for an element to appear
+- Scroll: Scroll the page in a direction (Up, Down, Left, Right)
+- Screenshot: Take a screenshot
+- GetPageSource: Get the HTML source of the page
+- ExecuteScript: Execute custom JavaScript
+
+Always provide step-by-step instructions with clear CSS selectors and expected outcomes.
+Be specific about selectors - prefer IDs and classes over generic tags.
+Include wait steps when necessary to ensure page elements are loaded.
+
+Return the plan by calling the create_task_plan tool.".to_string()
+    }
+
+    fn format_task_planning_request(&self, request: &AutomationRequest) -> String {
+        let mut prompt = format!("Task: {}", request.task_description);
+
+        if let Some(url) = &request.target_url {
+            prompt.push_str(&format!("\nTarget URL: {url}"));
+        }
+
+        if let Some(context) = &request.context {
+            prompt.push_str(&format!("\nAdditional context: {context:?}"));
+        }
+
+        prompt.push_str("\n\nPlease create a detailed task plan for this automation request. ");
+        prompt.push_str("If you cannot call tools, reply with a JSON object of this shape:\n");
+        prompt.push_str(
+            r##"{"description": "Overall task description", "steps": [{"id": "step_1", "action": {"type": "Click", "params": {"selector": "#submit"}}, "description": "What this step does", "expected_outcome": "What should happen"}]}"##,
+        );
+
+        prompt
+    }
+}
+
+/// Validate a model-produced task plan against the `TaskPlan` / `BrowserAction` types.
+///
+/// Returns every problem found (with the offending step) so they can be fed back to the model.
+pub fn parse_task_plan(value: &Value) -> Result<TaskPlan, Vec<String>> {
+    let mut errors = Vec::new();
+
+    let description = match value.get("description").and_then(|d| d.as_str()) {
+        Some(description) => description.to_string(),
+        None => {
+            errors.push("`description` must be a string".to_string());
+            String::new()
+        }
+    };
+
+    // Some models encode nested arrays as JSON strings
+    let steps_value = match value.get("steps") {
+        Some(Value::String(raw)) => serde_json::from_str(raw).unwrap_or(Value::Null),
+        Some(steps) => steps.clone(),
+        None => Value::Null,
+    };
+
+    let raw_steps = match steps_value.as_array() {
+        Some(steps) if !steps.is_empty() => steps.clone(),
+        Some(_) => {
+            errors.push("`steps` must contain at least one step".to_string());
+            Vec::new()
+        }
+        None => {
+            errors.push("`steps` must be an array".to_string());
+            Vec::new()
+        }
+    };
+
+    let mut steps = Vec::new();
+    let mut seen_ids = std::collections::HashSet::new();
+
+    for (index, raw_step) in raw_steps.iter().enumerate() {
+        let step_number = index + 1;
+
+        let id = match raw_step.get("id").and_then(|id| id.as_str()) {
+            Some(id) if !id.trim().is_empty() => id.to_string(),
+            _ => {
+                errors.push(format!(
+                    "step {step_number}: `id` must be a non-empty string"
+                ));
+                continue;
+            }
+        };
+
+        if !seen_ids.insert(id.clone()) {
+            errors.push(format!("step {step_number}: duplicate step id `{id}`"));
+        }
+
+        let action = match raw_step.get("action") {
+            Some(action) => match serde_json::from_value::<BrowserAction>(action.clone()) {
+                Ok(action) => action,
+                Err(e) => {
+                    errors.push(format!("step {step_number} (`{id}`): invalid action: {e}"));
+                    continue;
+                }
+            },
+            None => {
+                errors.push(format!("step {step_number} (`{id}`): missing `action`"));
+                continue;
+            }
+        };
+
+        match &action {
+            BrowserAction::Click { selector }
+            | BrowserAction::Type { selector, .. }
+            | BrowserAction::WaitForElement { selector, .. }
+                if selector.trim().is_empty() =>
+            {
+                errors.push(format!(
+                    "step {step_number} (`{id}`): selector must not be empty"
+                ));
+            }
+            BrowserAction::ExecuteScript { script } if script.trim().is_empty() => {
+                errors.push(format!(
+                    "step {step_number} (`{id}`): script must not be empty"
+                ));
+            }
+            _ => {}
+        }
+
+        steps.push(TaskStep {
+            id,
+            action,
+            description: raw_step
+                .get("description")
+                .and_then(|d| d.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            expected_outcome: raw_step
+                .get("expected_outcome")
+                .and_then(|o| o.as_str())
+                .map(|o| o.to_string()),
+        });
     }
+
+    if errors.is_empty() {
+        Ok(TaskPlan { steps, description })
+    } else {
+        Err(errors)
+    }
+}
+
+/// Whether a tool's JSON input schema lists `property`
+fn schema_declares(schema: &Value, property: &str) -> bool {
+    schema["properties"]
+        .as_object()
+        .is_some_and(|properties| properties.contains_key(property))
 }
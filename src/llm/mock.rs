@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::llm::{ChatResponse, LlmProvider, Message, Tool};
+
+const DEFAULT_MOCK_RESPONSE: &str = "{}";
+
+/// Deterministic provider that never touches the network.
+///
+/// Every call answers with the same text and no tool calls, which exercises the
+/// "model finished without tools" paths of the callers.
+pub struct MockProvider {
+    response: String,
+}
+
+impl MockProvider {
+    pub fn new(response: impl Into<String>) -> Self {
+        Self {
+            response: response.into(),
+        }
+    }
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self::new(
+            std::env::var("MOCK_LLM_RESPONSE")
+                .unwrap_or_else(|_| DEFAULT_MOCK_RESPONSE.to_string()),
+        )
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn model(&self) -> &str {
+        "mock"
+    }
+
+    async fn chat(&self, _messages: &[Message], _tools: &[Tool]) -> anyhow::Result<ChatResponse> {
+        Ok(ChatResponse {
+            content: Some(self.response.clone()),
+            tool_calls: None,
+        })
+    }
+}
+
+/// Provider that replays a fixed script of assistant turns, one per `chat` call.
+///
+/// Turns can carry tool calls, so multi-turn tool loops can be driven without a model.
+/// Every request is recorded for later inspection, and running past the end of the script
+/// is an error.
+pub struct ScriptedProvider {
+    turns: Mutex<VecDeque<ChatResponse>>,
+    requests: Mutex<Vec<Vec<Message>>>,
+}
+
+impl ScriptedProvider {
+    pub fn new(turns: impl IntoIterator<Item = ChatResponse>) -> Self {
+        Self {
+            turns: Mutex::new(turns.into_iter().collect()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Load turns from JSON: either an array of turns or an object with a `turns` array.
+    /// Each turn uses the `ChatResponse` shape (`content` and/or `tool_calls`).
+    pub fn from_json(value: Value) -> anyhow::Result<Self> {
+        let turns = match value {
+            Value::Object(mut object) => object
+                .remove("turns")
+                .ok_or_else(|| anyhow::anyhow!("Script object must contain a `turns` array"))?,
+            other => other,
+        };
+
+        let turns: Vec<ChatResponse> = serde_json::from_value(turns)
+            .map_err(|e| anyhow::anyhow!("Invalid scripted LLM turns: {}", e))?;
+        Ok(Self::new(turns))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read LLM script {}: {}", path.display(), e))?;
+        let value: Value = serde_json::from_str(&raw)
+            .map_err(|e| anyhow::anyhow!("Failed to parse LLM script {}: {}", path.display(), e))?;
+        Self::from_json(value)
+    }
+
+    /// Number of scripted turns not yet consumed
+    pub fn remaining(&self) -> usize {
+        self.turns.lock().unwrap().len()
+    }
+
+    /// The message history sent with each `chat` call so far, in order
+    pub fn requests(&self) -> Vec<Vec<Message>> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ScriptedProvider {
+    fn name(&self) -> &str {
+        "scripted"
+    }
+
+    fn model(&self) -> &str {
+        "scripted"
+    }
+
+    async fn chat(&self, messages: &[Message], _tools: &[Tool]) -> anyhow::Result<ChatResponse> {
+        self.requests.lock().unwrap().push(messages.to_vec());
+
+        let turn = self.turns.lock().unwrap().pop_front();
+        turn.ok_or_else(|| anyhow::anyhow!("Scripted LLM provider has no more responses"))
+    }
+}
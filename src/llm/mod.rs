@@ -0,0 +1,296 @@
+pub mod mock;
+pub mod ollama;
+pub mod openai;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::llm::mock::{MockProvider, ScriptedProvider};
+use crate::llm::ollama::OllamaProvider;
+use crate::llm::openai::OpenAiProvider;
+
+// Provider-neutral chat types shared by every backend
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// For `tool` messages: the ID of the tool call this message answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl Message {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::text("system", content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::text("user", content)
+    }
+
+    pub fn assistant(response: &ChatResponse) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: response.content.clone().unwrap_or_default(),
+            tool_calls: response.tool_calls.clone(),
+            tool_call_id: None,
+        }
+    }
+
+    pub fn tool_result(tool_call: &ToolCall, content: impl Into<String>) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: content.into(),
+            tool_calls: None,
+            tool_call_id: tool_call.id.clone(),
+        }
+    }
+
+    fn text(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: ToolFunction,
+}
+
+impl Tool {
+    pub fn function(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: Value,
+    ) -> Self {
+        Self {
+            tool_type: "function".to_string(),
+            function: ToolFunction {
+                name: name.into(),
+                description: description.into(),
+                parameters,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolFunction {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub call_type: Option<String>,
+    pub function: ToolCallFunction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallFunction {
+    pub name: String,
+    // Ollama sends arguments as a JSON object, OpenAI-style APIs as a JSON string
+    pub arguments: Value,
+}
+
+impl ToolCallFunction {
+    pub fn parsed_arguments(&self) -> Value {
+        match &self.arguments {
+            Value::String(raw) => serde_json::from_str(raw).unwrap_or_else(|_| json!({})),
+            other => other.clone(),
+        }
+    }
+}
+
+/// A single assistant turn: free text, tool calls, or both
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatResponse {
+    pub content: Option<String>,
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+impl ChatResponse {
+    pub fn text(content: impl Into<String>) -> Self {
+        Self {
+            content: Some(content.into()),
+            tool_calls: None,
+        }
+    }
+
+    pub fn tool_call(name: impl Into<String>, arguments: Value) -> Self {
+        Self {
+            content: None,
+            tool_calls: Some(vec![ToolCall {
+                id: None,
+                call_type: Some("function".to_string()),
+                function: ToolCallFunction {
+                    name: name.into(),
+                    arguments,
+                },
+            }]),
+        }
+    }
+}
+
+/// Why a chat completion failed. Providers return these inside `anyhow::Error` so callers can
+/// tell an unreachable server from a model that answered with something unusable.
+#[derive(Debug, thiserror::Error)]
+pub enum LlmError {
+    #[error("LLM server at {endpoint} is unreachable: {reason}")]
+    Unreachable { endpoint: String, reason: String },
+
+    #[error("LLM API error {status}: {message}")]
+    Api { status: u16, message: String },
+
+    #[error("Could not parse LLM output: {0}")]
+    UnparseableOutput(String),
+}
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short backend name for logs and diagnostics (e.g. "ollama")
+    fn name(&self) -> &str;
+
+    fn model(&self) -> &str;
+
+    /// Run one chat completion. `tools` may be empty, in which case no tools are offered.
+    async fn chat(&self, messages: &[Message], tools: &[Tool]) -> anyhow::Result<ChatResponse>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderKind {
+    Ollama,
+    OpenAi,
+    Mock,
+}
+
+#[derive(Debug, Clone)]
+pub struct LlmConfig {
+    pub provider: ProviderKind,
+    pub endpoint: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    pub temperature: f32,
+    pub max_tokens: usize,
+    /// For the mock provider: a JSON file of turns to replay instead of a fixed answer
+    pub mock_script: Option<String>,
+}
+
+const DEFAULT_OLLAMA_ENDPOINT: &str = "http://localhost:11434";
+const DEFAULT_OLLAMA_MODEL: &str = "llama3.2:latest";
+const DEFAULT_OPENAI_ENDPOINT: &str = "https://api.mistral.ai/v1/chat/completions";
+const DEFAULT_OPENAI_MODEL: &str = "mistral-large-latest";
+
+impl LlmConfig {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok().filter(|value| !value.is_empty()))
+    }
+
+    /// Resolve the configuration from a variable lookup.
+    ///
+    /// `LLM_PROVIDER` (`ollama`, `openai` or `mock`) wins; otherwise `MISTRAL_MODE=cloud` selects
+    /// the OpenAI-compatible backend and anything else selects Ollama.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let provider = match lookup("LLM_PROVIDER").map(|p| p.to_lowercase()).as_deref() {
+            Some("openai") | Some("openai-compatible") | Some("mistral") => ProviderKind::OpenAi,
+            Some("mock") => ProviderKind::Mock,
+            Some("ollama") => ProviderKind::Ollama,
+            Some(other) => {
+                warn!(
+                    "Unknown LLM_PROVIDER '{}', falling back to MISTRAL_MODE",
+                    other
+                );
+                Self::provider_from_mistral_mode(&lookup)
+            }
+            None => Self::provider_from_mistral_mode(&lookup),
+        };
+
+        let (endpoint, model, api_key) = match provider {
+            ProviderKind::Ollama => (
+                lookup("LLM_ENDPOINT")
+                    .or_else(|| lookup("OLLAMA_ENDPOINT"))
+                    .or_else(|| lookup("MISTRAL_LOCAL_ENDPOINT"))
+                    .unwrap_or_else(|| DEFAULT_OLLAMA_ENDPOINT.to_string()),
+                lookup("LLM_MODEL")
+                    .or_else(|| lookup("LLAMA_MODEL"))
+                    .unwrap_or_else(|| DEFAULT_OLLAMA_MODEL.to_string()),
+                None,
+            ),
+            ProviderKind::OpenAi => (
+                lookup("LLM_ENDPOINT")
+                    .or_else(|| lookup("MISTRAL_API_ENDPOINT"))
+                    .unwrap_or_else(|| DEFAULT_OPENAI_ENDPOINT.to_string()),
+                lookup("LLM_MODEL")
+                    .or_else(|| lookup("MISTRAL_MODEL"))
+                    .unwrap_or_else(|| DEFAULT_OPENAI_MODEL.to_string()),
+                lookup("LLM_API_KEY").or_else(|| lookup("MISTRAL_API_KEY")),
+            ),
+            ProviderKind::Mock => (String::new(), "mock".to_string(), None),
+        };
+
+        Self {
+            provider,
+            endpoint,
+            model,
+            api_key,
+            temperature: lookup("LLM_TEMPERATURE")
+                .and_then(|t| t.parse().ok())
+                .unwrap_or(0.1),
+            max_tokens: lookup("LLM_MAX_TOKENS")
+                .and_then(|t| t.parse().ok())
+                .unwrap_or(2000),
+            mock_script: lookup("MOCK_LLM_SCRIPT"),
+        }
+    }
+
+    fn provider_from_mistral_mode(lookup: &impl Fn(&str) -> Option<String>) -> ProviderKind {
+        match lookup("MISTRAL_MODE").map(|m| m.to_lowercase()).as_deref() {
+            Some("cloud") => ProviderKind::OpenAi,
+            _ => ProviderKind::Ollama,
+        }
+    }
+
+    pub fn build_provider(&self) -> anyhow::Result<Arc<dyn LlmProvider>> {
+        let provider: Arc<dyn LlmProvider> = match self.provider {
+            ProviderKind::Ollama => Arc::new(OllamaProvider::new(self)),
+            ProviderKind::OpenAi => {
+                if self.api_key.is_none() {
+                    warn!("No API key set for the OpenAI-compatible provider (LLM_API_KEY / MISTRAL_API_KEY)");
+                }
+                Arc::new(OpenAiProvider::new(self))
+            }
+            ProviderKind::Mock => match &self.mock_script {
+                Some(path) => Arc::new(ScriptedProvider::from_file(path)?),
+                None => Arc::new(MockProvider::default()),
+            },
+        };
+
+        info!(
+            "Using LLM provider '{}' with model '{}' at {}",
+            provider.name(),
+            provider.model(),
+            if self.endpoint.is_empty() {
+                "(in-process)"
+            } else {
+                &self.endpoint
+            }
+        );
+
+        Ok(provider)
+    }
+}
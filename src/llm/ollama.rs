@@ -0,0 +1,156 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use crate::llm::{ChatResponse, LlmConfig, LlmError, LlmProvider, Message, Tool, ToolCall};
+
+/// Ollama's native `/api/chat` endpoint, with tool calling
+pub struct OllamaProvider {
+    client: Client,
+    chat_endpoint: String,
+    model: String,
+    temperature: f32,
+    max_tokens: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaRequest<'a> {
+    model: &'a str,
+    messages: Vec<OllamaMessage<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<&'a [Tool]>,
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<ToolCall>>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    temperature: f32,
+    num_predict: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponse {
+    message: ChatResponse,
+}
+
+impl OllamaProvider {
+    pub fn new(config: &LlmConfig) -> Self {
+        Self {
+            client: Client::new(),
+            chat_endpoint: format!("{}/api/chat", config.endpoint.trim_end_matches('/')),
+            model: config.model.clone(),
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: &[Message], tools: &[Tool]) -> anyhow::Result<ChatResponse> {
+        let request = OllamaRequest {
+            model: &self.model,
+            messages: messages
+                .iter()
+                .map(|message| OllamaMessage {
+                    role: &message.role,
+                    content: &message.content,
+                    // Ollama expects arguments as JSON objects, never as encoded strings
+                    tool_calls: message.tool_calls.as_ref().map(|calls| {
+                        calls
+                            .iter()
+                            .map(|call| {
+                                let mut call = call.clone();
+                                call.function.arguments = call.function.parsed_arguments();
+                                call
+                            })
+                            .collect()
+                    }),
+                })
+                .collect(),
+            stream: false,
+            tools: if tools.is_empty() { None } else { Some(tools) },
+            options: OllamaOptions {
+                temperature: self.temperature,
+                num_predict: Some(self.max_tokens),
+            },
+        };
+
+        info!(
+            "Calling Ollama model {} with {} tools available",
+            self.model,
+            tools.len()
+        );
+
+        let response = self
+            .client
+            .post(&self.chat_endpoint)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| LlmError::Unreachable {
+                endpoint: self.chat_endpoint.clone(),
+                reason: e.to_string(),
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(LlmError::Api {
+                status,
+                message: error_text,
+            }
+            .into());
+        }
+
+        let ollama_response: OllamaResponse = response.json().await.map_err(|e| {
+            LlmError::UnparseableOutput(format!("Failed to parse Ollama response: {}", e))
+        })?;
+
+        Ok(normalize_response(ollama_response.message))
+    }
+}
+
+fn normalize_response(mut response: ChatResponse) -> ChatResponse {
+    // Ollama sends an empty string rather than omitting content when it only calls tools
+    if response
+        .content
+        .as_deref()
+        .is_some_and(|c| c.trim().is_empty())
+    {
+        response.content = None;
+    }
+    if response.tool_calls.as_ref().is_some_and(Vec::is_empty) {
+        response.tool_calls = None;
+    }
+    if let Some(calls) = &mut response.tool_calls {
+        for (index, call) in calls.iter_mut().enumerate() {
+            if call.id.is_none() {
+                call.id = Some(format!("call_{index}"));
+            }
+            if matches!(call.function.arguments, Value::Null) {
+                call.function.arguments = Value::Object(Default::default());
+            }
+        }
+    }
+    response
+}
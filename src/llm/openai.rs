@@ -0,0 +1,190 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::llm::{ChatResponse, LlmConfig, LlmError, LlmProvider, Message, Tool, ToolCall};
+
+/// Any OpenAI-compatible `/v1/chat/completions` API (Mistral, vLLM, llama.cpp server, ...)
+pub struct OpenAiProvider {
+    client: Client,
+    chat_endpoint: String,
+    model: String,
+    api_key: Option<String>,
+    temperature: f32,
+    max_tokens: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<Value>,
+    temperature: f32,
+    max_tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<&'a [Tool]>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ChatResponse,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: &LlmConfig) -> Self {
+        Self {
+            client: Client::new(),
+            chat_endpoint: chat_completions_url(&config.endpoint),
+            model: config.model.clone(),
+            api_key: config.api_key.clone(),
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+        }
+    }
+}
+
+/// Accept either the full `/chat/completions` URL or a base URL such as `http://host:8000/v1`
+pub fn chat_completions_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/chat/completions") {
+        endpoint.to_string()
+    } else if endpoint.ends_with("/v1") {
+        format!("{endpoint}/chat/completions")
+    } else {
+        format!("{endpoint}/v1/chat/completions")
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: &[Message], tools: &[Tool]) -> anyhow::Result<ChatResponse> {
+        let request = ChatCompletionRequest {
+            model: &self.model,
+            messages: to_wire_messages(messages),
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            tools: if tools.is_empty() { None } else { Some(tools) },
+        };
+
+        info!(
+            "Calling OpenAI-compatible model {} with {} tools available",
+            self.model,
+            tools.len()
+        );
+
+        let mut request_builder = self.client.post(&self.chat_endpoint).json(&request);
+        if let Some(api_key) = &self.api_key {
+            request_builder = request_builder.bearer_auth(api_key);
+        }
+
+        let response = request_builder
+            .send()
+            .await
+            .map_err(|e| LlmError::Unreachable {
+                endpoint: self.chat_endpoint.clone(),
+                reason: e.to_string(),
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(LlmError::Api {
+                status,
+                message: error_text,
+            }
+            .into());
+        }
+
+        let completion: ChatCompletionResponse = response.json().await.map_err(|e| {
+            LlmError::UnparseableOutput(format!("Failed to parse chat completions response: {}", e))
+        })?;
+
+        let mut message = completion
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message)
+            .ok_or_else(|| {
+                LlmError::UnparseableOutput("Chat completions response had no choices".to_string())
+            })?;
+
+        if message.tool_calls.as_ref().is_some_and(Vec::is_empty) {
+            message.tool_calls = None;
+        }
+        Ok(message)
+    }
+}
+
+/// Convert to the OpenAI wire format: tool call arguments are JSON-encoded strings and every
+/// `tool` message must reference the ID of the call it answers.
+fn to_wire_messages(messages: &[Message]) -> Vec<Value> {
+    let mut pending_call_ids: Vec<String> = Vec::new();
+    let mut wire = Vec::with_capacity(messages.len());
+
+    for (index, message) in messages.iter().enumerate() {
+        let mut entry = json!({
+            "role": message.role,
+            "content": message.content,
+        });
+
+        if let Some(tool_calls) = &message.tool_calls {
+            let calls: Vec<Value> = tool_calls
+                .iter()
+                .enumerate()
+                .map(|(call_index, call)| wire_tool_call(call, index, call_index))
+                .collect();
+            pending_call_ids = calls
+                .iter()
+                .filter_map(|call| call["id"].as_str().map(|id| id.to_string()))
+                .rev()
+                .collect();
+            entry["tool_calls"] = json!(calls);
+        }
+
+        if message.role == "tool" {
+            let call_id = message
+                .tool_call_id
+                .clone()
+                .or_else(|| pending_call_ids.pop())
+                .unwrap_or_else(|| format!("call_{index}"));
+            entry["tool_call_id"] = json!(call_id);
+        }
+
+        wire.push(entry);
+    }
+
+    wire
+}
+
+fn wire_tool_call(call: &ToolCall, message_index: usize, call_index: usize) -> Value {
+    let arguments = match &call.function.arguments {
+        Value::String(raw) => raw.clone(),
+        other => other.to_string(),
+    };
+
+    json!({
+        "id": call
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("call_{message_index}_{call_index}")),
+        "type": "function",
+        "function": {
+            "name": call.function.name,
+            "arguments": arguments,
+        }
+    })
+}
@@ -1,17 +1,28 @@
-use llm_web_agent::{create_router, AppState};
+use llm_web_agent::mcp_stdio::serve_stdio;
+use llm_web_agent::{create_router, AgentServices, AppState};
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use tracing::info;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    let mcp_stdio = std::env::args().any(|arg| arg == "--mcp-stdio");
+
+    // Initialize tracing. In stdio mode stdout carries the MCP protocol, so logs go to stderr.
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env());
+    if mcp_stdio {
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
 
     // Load environment variables
     dotenv::dotenv().ok();
 
+    if mcp_stdio {
+        return run_mcp_stdio().await;
+    }
+
     info!("Starting LLM Web Agent with Llama + MCP support...");
 
     // Create application state
@@ -42,3 +53,11 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Serve the MCP tools over stdin/stdout for hosts that launch the agent as a subprocess
+async fn run_mcp_stdio() -> anyhow::Result<()> {
+    info!("Starting LLM Web Agent MCP server on stdio...");
+
+    let services = AgentServices::from_env().await;
+    serve_stdio(services.mcp_state).await
+}
@@ -0,0 +1,598 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::oneshot;
+use tracing::{debug, info, warn};
+
+use crate::mcp_http::{PROTOCOL_VERSION_HEADER, SESSION_HEADER};
+use crate::mcp_server::{ToolInfo, SUPPORTED_PROTOCOL_VERSIONS};
+use crate::tools::{ImageContent, McpTool, ToolContext, ToolOutput};
+
+// Client side of MCP: connect to external servers over stdio or Streamable HTTP and offer
+// their tools through our registry under `{server}__{tool}` names.
+
+/// Joins the server name and the server's own tool name in the names we expose
+pub const TOOL_NAMESPACE_SEPARATOR: &str = "__";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How to reach an external MCP server, in the `mcpServers` format MCP hosts use
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum McpTransportConfig {
+    /// Launch a subprocess and speak newline-delimited JSON-RPC over its stdin/stdout
+    Stdio {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+    },
+    /// POST to a Streamable HTTP endpoint
+    Http {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct McpServerConfig {
+    /// Namespace for the server's tools; only `[A-Za-z0-9_-]` is kept
+    pub name: String,
+    pub transport: McpTransportConfig,
+}
+
+impl McpServerConfig {
+    /// Parse a config file of the form `{"mcpServers": {"<name>": {"command": ...} | {"url": ...}}}`
+    pub fn parse_servers(json: &str) -> anyhow::Result<Vec<Self>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct ServersFile {
+            mcp_servers: HashMap<String, McpTransportConfig>,
+        }
+
+        let file: ServersFile = serde_json::from_str(json)?;
+        let mut servers: Vec<Self> = file
+            .mcp_servers
+            .into_iter()
+            .map(|(name, transport)| Self {
+                name: sanitize_name(&name),
+                transport,
+            })
+            .collect();
+        servers.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(servers)
+    }
+
+    /// Servers from the `MCP_SERVERS_FILE` config file, plus the Streamable HTTP servers listed
+    /// in `MCP_REMOTE_ENDPOINTS` (comma-separated base URLs, served at `{url}/mcp` and named
+    /// after their host). Configuration errors are logged, never fatal.
+    pub fn from_env() -> Vec<Self> {
+        let mut servers = Vec::new();
+
+        if let Ok(path) = std::env::var("MCP_SERVERS_FILE") {
+            match std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|json| Self::parse_servers(&json))
+            {
+                Ok(configured) => servers.extend(configured),
+                Err(e) => warn!("Could not read MCP servers from {}: {}", path, e),
+            }
+        }
+
+        if let Ok(endpoints) = std::env::var("MCP_REMOTE_ENDPOINTS") {
+            for endpoint in endpoints
+                .split(',')
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+            {
+                let endpoint = endpoint.trim_end_matches('/');
+                let name = reqwest::Url::parse(endpoint)
+                    .ok()
+                    .and_then(|url| {
+                        let host = url.host_str()?.to_string();
+                        Some(match url.port() {
+                            Some(port) => format!("{}_{}", host, port),
+                            None => host,
+                        })
+                    })
+                    .unwrap_or_else(|| "remote".to_string());
+
+                servers.push(Self {
+                    name: sanitize_name(&name),
+                    transport: McpTransportConfig::Http {
+                        url: format!("{}/mcp", endpoint),
+                        headers: HashMap::new(),
+                    },
+                });
+            }
+        }
+
+        servers
+    }
+}
+
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Sends JSON-RPC messages to a server and returns its responses
+#[async_trait]
+trait Transport: Send + Sync {
+    /// Send a request and wait for the response with the same ID
+    async fn request(&self, message: Value) -> Result<Value, String>;
+
+    async fn notify(&self, message: Value) -> Result<(), String>;
+}
+
+type PendingRequests = Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>;
+
+struct StdioTransport {
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: PendingRequests,
+    // Held so the subprocess is killed when the client goes away
+    _child: Child,
+}
+
+impl StdioTransport {
+    fn spawn(
+        command: &str,
+        args: &[String],
+        env: &HashMap<String, String>,
+    ) -> anyhow::Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .envs(env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to start MCP server '{}': {}", command, e))?;
+
+        let stdin = Arc::new(tokio::sync::Mutex::new(
+            child.stdin.take().expect("stdin is piped"),
+        ));
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let pending = PendingRequests::default();
+        tokio::spawn(read_stdio_messages(stdout, stdin.clone(), pending.clone()));
+
+        Ok(Self {
+            stdin,
+            pending,
+            _child: child,
+        })
+    }
+
+    async fn write(&self, message: &Value) -> Result<(), String> {
+        write_line(&self.stdin, message).await
+    }
+}
+
+async fn write_line(stdin: &tokio::sync::Mutex<ChildStdin>, message: &Value) -> Result<(), String> {
+    let mut line = message.to_string();
+    line.push('\n');
+    let mut stdin = stdin.lock().await;
+    stdin
+        .write_all(line.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to write to MCP server: {}", e))?;
+    stdin
+        .flush()
+        .await
+        .map_err(|e| format!("Failed to write to MCP server: {}", e))
+}
+
+/// Route responses from the server to their waiting requests until it exits
+async fn read_stdio_messages(
+    stdout: ChildStdout,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: PendingRequests,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            warn!("Ignoring non-JSON output from MCP server: {}", line);
+            continue;
+        };
+
+        match (message.get("method"), message.get("id")) {
+            // A request from the server; we only implement ping
+            (Some(method), Some(id)) => {
+                let response = if method == "ping" {
+                    json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                } else {
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": "Method not found" }
+                    })
+                };
+                if let Err(e) = write_line(&stdin, &response).await {
+                    warn!("{}", e);
+                }
+            }
+            (Some(method), None) => debug!("MCP server notification: {}", method),
+            (None, Some(id)) => {
+                let waiter = id
+                    .as_u64()
+                    .and_then(|id| pending.lock().unwrap().remove(&id));
+                if let Some(waiter) = waiter {
+                    let _ = waiter.send(message);
+                }
+            }
+            (None, None) => warn!("Ignoring malformed message from MCP server"),
+        }
+    }
+
+    // The server exited; dropping the senders fails every request still waiting
+    pending.lock().unwrap().clear();
+}
+
+#[async_trait]
+impl Transport for StdioTransport {
+    async fn request(&self, message: Value) -> Result<Value, String> {
+        let id = message["id"]
+            .as_u64()
+            .ok_or("Request without a numeric id")?;
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+
+        if let Err(e) = self.write(&message).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, receiver).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err("MCP server exited before responding".to_string()),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err("Timed out waiting for MCP server".to_string())
+            }
+        }
+    }
+
+    async fn notify(&self, message: Value) -> Result<(), String> {
+        self.write(&message).await
+    }
+}
+
+struct HttpTransport {
+    client: Client,
+    url: String,
+    headers: HashMap<String, String>,
+    session_id: Mutex<Option<String>>,
+    protocol_version: Mutex<Option<String>>,
+}
+
+impl HttpTransport {
+    fn new(url: &str, headers: &HashMap<String, String>) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            url: url.to_string(),
+            headers: headers.clone(),
+            session_id: Mutex::new(None),
+            protocol_version: Mutex::new(None),
+        }
+    }
+
+    async fn post(&self, message: &Value) -> Result<reqwest::Response, String> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(
+                reqwest::header::ACCEPT,
+                "application/json, text/event-stream",
+            )
+            .json(message);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        if let Some(session_id) = self.session_id.lock().unwrap().clone() {
+            request = request.header(SESSION_HEADER, session_id);
+        }
+        if let Some(version) = self.protocol_version.lock().unwrap().clone() {
+            request = request.header(PROTOCOL_VERSION_HEADER, version);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach MCP server: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("MCP server returned HTTP {}", response.status()));
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn request(&self, message: Value) -> Result<Value, String> {
+        let response = self.post(&message).await?;
+
+        if message["method"] == "initialize" {
+            if let Some(session_id) = response
+                .headers()
+                .get(SESSION_HEADER)
+                .and_then(|value| value.to_str().ok())
+            {
+                *self.session_id.lock().unwrap() = Some(session_id.to_string());
+            }
+        }
+
+        let is_sse = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("text/event-stream"));
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read MCP response: {}", e))?;
+
+        let reply = if is_sse {
+            sse_response(&body, &message["id"])
+                .ok_or("MCP server closed the event stream without responding")?
+        } else {
+            serde_json::from_str(&body)
+                .map_err(|e| format!("Failed to parse MCP response: {}", e))?
+        };
+
+        if message["method"] == "initialize" {
+            if let Some(version) = reply["result"]["protocolVersion"].as_str() {
+                *self.protocol_version.lock().unwrap() = Some(version.to_string());
+            }
+        }
+        Ok(reply)
+    }
+
+    async fn notify(&self, message: Value) -> Result<(), String> {
+        self.post(&message).await.map(|_| ())
+    }
+}
+
+/// Find the response to request `id` among the `data:` events of an SSE body
+fn sse_response(body: &str, id: &Value) -> Option<Value> {
+    body.split("\n\n")
+        .map(|event| {
+            event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .filter_map(|data| serde_json::from_str::<Value>(&data).ok())
+        .find(|message| &message["id"] == id && message.get("method").is_none())
+}
+
+/// A connection to one external MCP server
+pub struct McpClient {
+    name: String,
+    transport: Box<dyn Transport>,
+    next_id: AtomicU64,
+    server_info: Value,
+}
+
+impl McpClient {
+    /// Connect and run the `initialize` handshake
+    pub async fn connect(config: &McpServerConfig) -> anyhow::Result<Self> {
+        let transport: Box<dyn Transport> = match &config.transport {
+            McpTransportConfig::Stdio { command, args, env } => {
+                Box::new(StdioTransport::spawn(command, args, env)?)
+            }
+            McpTransportConfig::Http { url, headers } => Box::new(HttpTransport::new(url, headers)),
+        };
+
+        let mut client = Self {
+            name: config.name.clone(),
+            transport,
+            next_id: AtomicU64::new(1),
+            server_info: Value::Null,
+        };
+
+        let result = client
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": SUPPORTED_PROTOCOL_VERSIONS[0],
+                    "capabilities": {},
+                    "clientInfo": { "name": "llm-web-agent", "version": env!("CARGO_PKG_VERSION") }
+                }),
+            )
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!("MCP server '{}' failed to initialize: {}", config.name, e)
+            })?;
+        client.server_info = result["serverInfo"].clone();
+
+        client
+            .transport
+            .notify(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        info!(
+            "Connected to MCP server '{}' ({})",
+            client.name,
+            client.server_info["name"].as_str().unwrap_or("unknown")
+        );
+        Ok(client)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The `serverInfo` the server reported from `initialize`
+    pub fn server_info(&self) -> &Value {
+        &self.server_info
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let response = self
+            .transport
+            .request(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": params
+            }))
+            .await?;
+
+        if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+            return Err(error["message"]
+                .as_str()
+                .map(|m| m.to_string())
+                .unwrap_or_else(|| error.to_string()));
+        }
+        response
+            .get("result")
+            .filter(|r| !r.is_null())
+            .cloned()
+            .ok_or_else(|| format!("No result from MCP server '{}'", self.name))
+    }
+
+    /// Every tool the server offers, following `nextCursor` pagination
+    pub async fn list_tools(&self) -> Result<Vec<ToolInfo>, String> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self.request("tools/list", params).await?;
+
+            for tool in result["tools"].as_array().into_iter().flatten() {
+                let Some(name) = tool["name"].as_str() else {
+                    warn!("Skipping unnamed tool from MCP server '{}'", self.name);
+                    continue;
+                };
+                tools.push(ToolInfo {
+                    name: name.to_string(),
+                    description: tool["description"].as_str().unwrap_or_default().to_string(),
+                    input_schema: tool
+                        .get("inputSchema")
+                        .cloned()
+                        .unwrap_or_else(|| json!({ "type": "object" })),
+                });
+            }
+
+            match result["nextCursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => return Ok(tools),
+            }
+        }
+    }
+
+    /// Call a tool by the server's own name
+    pub async fn call_tool(&self, name: &str, arguments: &Value) -> Result<ToolOutput, String> {
+        let result = self
+            .request(
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+            .await?;
+        tool_output_from_result(&result)
+    }
+}
+
+/// A tool of an external MCP server, registered under its namespaced name
+pub struct ExternalTool {
+    info: ToolInfo,
+    remote_name: String,
+    client: Arc<McpClient>,
+}
+
+impl ExternalTool {
+    pub fn new(client: Arc<McpClient>, remote: ToolInfo) -> Self {
+        Self {
+            info: ToolInfo {
+                name: format!(
+                    "{}{}{}",
+                    client.name(),
+                    TOOL_NAMESPACE_SEPARATOR,
+                    remote.name
+                ),
+                ..remote.clone()
+            },
+            remote_name: remote.name,
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl McpTool for ExternalTool {
+    fn info(&self) -> &ToolInfo {
+        &self.info
+    }
+
+    async fn call(&self, arguments: &Value, _context: &ToolContext) -> Result<ToolOutput, String> {
+        self.client.call_tool(&self.remote_name, arguments).await
+    }
+}
+
+/// Convert a `tools/call` result into our tool output. Spec-compliant servers wrap results in
+/// content blocks; some older ones return bare values.
+fn tool_output_from_result(result: &Value) -> Result<ToolOutput, String> {
+    if result["isError"].as_bool() == Some(true) {
+        return Err(content_text(result));
+    }
+
+    let value = match result.get("structuredContent") {
+        Some(structured) => structured.clone(),
+        None if result.get("content").is_some() => Value::String(content_text(result)),
+        None => result.clone(),
+    };
+    let images = result["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|block| block["type"] == "image")
+        .filter_map(|block| {
+            Some(ImageContent {
+                data: block["data"].as_str()?.to_string(),
+                mime_type: block["mimeType"].as_str()?.to_string(),
+            })
+        })
+        .collect();
+
+    Ok(ToolOutput { value, images })
+}
+
+/// Concatenate the text blocks of a `tools/call` result
+fn content_text(result: &Value) -> String {
+    result["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|block| block["text"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,290 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::mcp_server::{
+    dispatch_request, MCPRequest, MCPResponse, MCPServerState, McpSession,
+    SUPPORTED_PROTOCOL_VERSIONS,
+};
+
+// MCP Streamable HTTP transport: POST for client messages, GET for the server's SSE stream,
+// DELETE to end a session.
+
+pub const SESSION_HEADER: &str = "mcp-session-id";
+pub const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+
+pub(crate) async fn handle_mcp_post(
+    State(state): State<Arc<MCPServerState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(rejection) = check_headers(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let body: Value = match serde_json::from_slice(&body) {
+        Ok(body) => body,
+        Err(e) => {
+            let error =
+                MCPResponse::failure(Some(Value::Null), -32700, format!("Parse error: {}", e));
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+    };
+
+    // Sessions are optional, so simple clients can still send one-off requests,
+    // but a session ID we don't know is an error the client must recover from
+    let session = match header_str(&headers, SESSION_HEADER) {
+        Some(id) => match state.session(id) {
+            Some(session) => Some(session),
+            None => return unknown_session(),
+        },
+        None => None,
+    };
+
+    match body {
+        Value::Array(messages) => handle_batch(&state, session.as_deref(), messages).await,
+        message => handle_single(&state, session.as_deref(), message).await,
+    }
+}
+
+async fn handle_single(
+    state: &MCPServerState,
+    session: Option<&McpSession>,
+    message: Value,
+) -> Response {
+    let request: MCPRequest = match serde_json::from_value(message) {
+        Ok(request) => request,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(invalid_request(e))).into_response(),
+    };
+    info!("Received MCP request: {:?}", request);
+
+    if request.method == "initialize" {
+        let Some(response) = dispatch_request(state, None, request).await else {
+            return StatusCode::ACCEPTED.into_response();
+        };
+        let protocol_version = response
+            .result
+            .as_ref()
+            .and_then(|result| result["protocolVersion"].as_str())
+            .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0]);
+        let session = state.open_session(protocol_version);
+
+        return ([(SESSION_HEADER, session.id.clone())], Json(response)).into_response();
+    }
+
+    mark_initialized(session, &request);
+    if awaiting_initialization(session, &request) {
+        return match request.id {
+            Some(id) => (StatusCode::BAD_REQUEST, Json(not_initialized(id))).into_response(),
+            None => StatusCode::ACCEPTED.into_response(),
+        };
+    }
+    match dispatch_request(state, session.map(|s| s.id.as_str()), request).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+async fn handle_batch(
+    state: &MCPServerState,
+    session: Option<&McpSession>,
+    messages: Vec<Value>,
+) -> Response {
+    if messages.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(MCPResponse::failure(
+                Some(Value::Null),
+                -32600,
+                "Empty batch",
+            )),
+        )
+            .into_response();
+    }
+
+    let mut responses = Vec::new();
+    for message in messages {
+        let request: MCPRequest = match serde_json::from_value(message) {
+            Ok(request) => request,
+            Err(e) => {
+                responses.push(invalid_request(e));
+                continue;
+            }
+        };
+
+        if request.method == "initialize" {
+            responses.push(MCPResponse::failure(
+                request.id,
+                -32600,
+                "initialize must not be part of a batch",
+            ));
+            continue;
+        }
+
+        mark_initialized(session, &request);
+        if awaiting_initialization(session, &request) {
+            if let Some(id) = request.id {
+                responses.push(not_initialized(id));
+            }
+            continue;
+        }
+        if let Some(response) =
+            dispatch_request(state, session.map(|s| s.id.as_str()), request).await
+        {
+            responses.push(response);
+        }
+    }
+
+    // A batch of only notifications has nothing to answer
+    if responses.is_empty() {
+        StatusCode::ACCEPTED.into_response()
+    } else {
+        Json(responses).into_response()
+    }
+}
+
+/// Open the server-to-client SSE stream for a session
+pub(crate) async fn handle_mcp_get(
+    State(state): State<Arc<MCPServerState>>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(rejection) = check_headers(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    let accepts_sse = header_str(&headers, header::ACCEPT.as_str())
+        .is_some_and(|accept| accept.contains("text/event-stream"));
+    if !accepts_sse {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+
+    let Some(id) = header_str(&headers, SESSION_HEADER) else {
+        return (StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header").into_response();
+    };
+    let Some(session) = state.session(id) else {
+        return unknown_session();
+    };
+
+    info!("Opening SSE stream for MCP session {}", session.id);
+    let receiver = session.subscribe();
+    // Don't hold the session here, or closing it would never end the stream
+    drop(session);
+
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(message) => {
+                    let event = Event::default().event("message").data(message.to_string());
+                    return Some((Ok::<_, Infallible>(event), receiver));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("SSE client fell behind, dropped {} notifications", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Terminate a session at the client's request
+pub(crate) async fn handle_mcp_delete(
+    State(state): State<Arc<MCPServerState>>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(rejection) = check_headers(&state, &headers) {
+        return rejection.into_response();
+    }
+
+    match header_str(&headers, SESSION_HEADER) {
+        Some(id) if state.close_session(id) => StatusCode::NO_CONTENT.into_response(),
+        Some(_) => unknown_session(),
+        None => (StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header").into_response(),
+    }
+}
+
+/// Reject cross-site browser requests (DNS rebinding) and protocol versions we don't speak
+fn check_headers(state: &MCPServerState, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    if let Some(origin) = header_str(headers, header::ORIGIN.as_str()) {
+        if !origin_allowed(state.allowed_origins.as_deref(), origin) {
+            warn!("Rejected MCP request from origin {}", origin);
+            return Err((StatusCode::FORBIDDEN, "Origin not allowed".to_string()));
+        }
+    }
+
+    if let Some(version) = header_str(headers, PROTOCOL_VERSION_HEADER) {
+        if !SUPPORTED_PROTOCOL_VERSIONS.contains(&version) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unsupported MCP protocol version: {}", version),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// With no allow-list only localhost origins pass; `*` in the list allows any origin
+pub fn origin_allowed(allowed: Option<&[String]>, origin: &str) -> bool {
+    match allowed {
+        Some(allowed) => allowed.iter().any(|o| o == "*" || o == origin),
+        None => reqwest::Url::parse(origin).is_ok_and(|url| {
+            matches!(
+                url.host_str(),
+                Some("localhost") | Some("127.0.0.1") | Some("[::1]")
+            )
+        }),
+    }
+}
+
+fn mark_initialized(session: Option<&McpSession>, request: &MCPRequest) {
+    if request.method == "notifications/initialized" {
+        if let Some(session) = session {
+            session.mark_initialized();
+        }
+    }
+}
+
+/// Until the client sends `notifications/initialized`, a session only answers pings;
+/// other notifications are ignored
+fn awaiting_initialization(session: Option<&McpSession>, request: &MCPRequest) -> bool {
+    session.is_some_and(|session| !session.is_initialized()) && request.method != "ping"
+}
+
+fn not_initialized(id: Value) -> MCPResponse {
+    MCPResponse::failure(
+        Some(id),
+        -32600,
+        "Session is not initialized; send notifications/initialized first",
+    )
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn unknown_session() -> Response {
+    (StatusCode::NOT_FOUND, "Unknown or expired MCP session").into_response()
+}
+
+fn invalid_request(error: serde_json::Error) -> MCPResponse {
+    MCPResponse::failure(
+        Some(Value::Null),
+        -32600,
+        format!("Invalid request: {}", error),
+    )
+}
@@ -0,0 +1,207 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tokio::sync::{broadcast, RwLock};
+
+use crate::browser::{find_session, open_sessions, SharedSession};
+use crate::mcp_server::extract_clean_text;
+
+// MCP resources for live browser sessions:
+// browser://sessions/{id}/{html,text,screenshot,extraction}
+
+pub const BROWSER_URI_PREFIX: &str = "browser://sessions/";
+
+const SESSION_EVENT_BUFFER: usize = 256;
+
+/// Something happened to a browser session that MCP clients may want to hear about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    Opened(String),
+    /// The page changed: navigation, interaction, a new screenshot or extraction result
+    Changed(String),
+    Closed(String),
+}
+
+/// Broadcast of browser session events, shared by everything that drives sessions
+#[derive(Clone)]
+pub struct SessionEvents {
+    sender: broadcast::Sender<SessionEvent>,
+}
+
+impl SessionEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(SESSION_EVENT_BUFFER);
+        Self { sender }
+    }
+
+    pub fn opened(&self, session_id: &str) {
+        let _ = self
+            .sender
+            .send(SessionEvent::Opened(session_id.to_string()));
+    }
+
+    pub fn changed(&self, session_id: &str) {
+        let _ = self
+            .sender
+            .send(SessionEvent::Changed(session_id.to_string()));
+    }
+
+    pub fn closed(&self, session_id: &str) {
+        let _ = self
+            .sender
+            .send(SessionEvent::Closed(session_id.to_string()));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for SessionEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowserResourceKind {
+    Html,
+    Text,
+    Screenshot,
+    Extraction,
+}
+
+impl BrowserResourceKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BrowserResourceKind::Html => "html",
+            BrowserResourceKind::Text => "text",
+            BrowserResourceKind::Screenshot => "screenshot",
+            BrowserResourceKind::Extraction => "extraction",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "html" => Some(BrowserResourceKind::Html),
+            "text" => Some(BrowserResourceKind::Text),
+            "screenshot" => Some(BrowserResourceKind::Screenshot),
+            "extraction" => Some(BrowserResourceKind::Extraction),
+            _ => None,
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            BrowserResourceKind::Html => "text/html",
+            BrowserResourceKind::Text => "text/plain",
+            BrowserResourceKind::Screenshot => "image/png",
+            BrowserResourceKind::Extraction => "application/json",
+        }
+    }
+}
+
+pub fn browser_resource_uri(session_id: &str, kind: BrowserResourceKind) -> String {
+    format!("{}{}/{}", BROWSER_URI_PREFIX, session_id, kind.as_str())
+}
+
+/// Split `browser://sessions/{id}/{kind}` into its session ID and resource kind
+pub fn parse_browser_uri(uri: &str) -> Option<(&str, BrowserResourceKind)> {
+    let (session_id, kind) = uri.strip_prefix(BROWSER_URI_PREFIX)?.rsplit_once('/')?;
+    if session_id.is_empty() {
+        return None;
+    }
+    Some((session_id, BrowserResourceKind::parse(kind)?))
+}
+
+/// URI templates for `resources/templates/list`
+pub fn browser_resource_templates() -> Vec<Value> {
+    [
+        BrowserResourceKind::Html,
+        BrowserResourceKind::Text,
+        BrowserResourceKind::Screenshot,
+        BrowserResourceKind::Extraction,
+    ]
+    .into_iter()
+    .map(|kind| {
+        json!({
+            "uriTemplate": format!("{}{{session_id}}/{}", BROWSER_URI_PREFIX, kind.as_str()),
+            "name": format!("Browser session {}", kind.as_str()),
+            "mimeType": kind.mime_type()
+        })
+    })
+    .collect()
+}
+
+/// Resources for every open session. Screenshots and extraction results are only listed
+/// once the session has one, and not at all while the session is busy with an action.
+pub async fn list_browser_resources(
+    sessions: &RwLock<HashMap<String, SharedSession>>,
+) -> Vec<Value> {
+    let mut resources = Vec::new();
+
+    for (session_id, session) in open_sessions(sessions).await {
+        let mut kinds = vec![BrowserResourceKind::Html, BrowserResourceKind::Text];
+        if let Ok(session) = session.try_lock() {
+            if session.last_screenshot().is_some() {
+                kinds.push(BrowserResourceKind::Screenshot);
+            }
+            if session.last_extraction().is_some() {
+                kinds.push(BrowserResourceKind::Extraction);
+            }
+        }
+
+        for kind in kinds {
+            resources.push(json!({
+                "uri": browser_resource_uri(&session_id, kind),
+                "name": format!("Session {} {}", session_id, kind.as_str()),
+                "mimeType": kind.mime_type()
+            }));
+        }
+    }
+
+    resources
+}
+
+/// Read one browser resource as an MCP `contents` entry
+pub async fn read_browser_resource(
+    sessions: &RwLock<HashMap<String, SharedSession>>,
+    uri: &str,
+) -> Result<Value, String> {
+    let (session_id, kind) =
+        parse_browser_uri(uri).ok_or_else(|| format!("Resource not found: {}", uri))?;
+
+    let session = find_session(sessions, session_id)
+        .await
+        .ok_or_else(|| format!("Browser session not found: {}", session_id))?;
+    let session = session.lock().await;
+
+    let contents = match kind {
+        BrowserResourceKind::Html => {
+            let html = session.page_source().await.map_err(|e| e.to_string())?;
+            json!({ "uri": uri, "mimeType": kind.mime_type(), "text": html })
+        }
+        BrowserResourceKind::Text => {
+            let html = session.page_source().await.map_err(|e| e.to_string())?;
+            let text = extract_clean_text(&html, &Value::Null)?["clean_text"].clone();
+            json!({ "uri": uri, "mimeType": kind.mime_type(), "text": text })
+        }
+        BrowserResourceKind::Screenshot => {
+            let data = session
+                .last_screenshot()
+                .ok_or_else(|| format!("No screenshot taken yet in session {}", session_id))?;
+            json!({ "uri": uri, "mimeType": kind.mime_type(), "blob": data })
+        }
+        BrowserResourceKind::Extraction => {
+            let result = session
+                .last_extraction()
+                .ok_or_else(|| format!("No extraction result yet in session {}", session_id))?;
+            json!({
+                "uri": uri,
+                "mimeType": kind.mime_type(),
+                "text": serde_json::to_string_pretty(result).unwrap_or_default()
+            })
+        }
+    };
+
+    Ok(contents)
+}
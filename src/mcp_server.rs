@@ -1,15 +1,67 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{FromRef, State},
     response::Json,
-    routing::{get, post},
+    routing::get,
     Router,
 };
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::sync::Arc;
-use tracing::info;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::browser::BrowserSessions;
+use crate::content_store::{ContentStore, CONTENT_URI_PREFIX};
+use crate::mcp_http::{handle_mcp_delete, handle_mcp_get, handle_mcp_post};
+use crate::mcp_resources::{
+    browser_resource_templates, browser_resource_uri, list_browser_resources,
+    read_browser_resource, BrowserResourceKind, SessionEvent, SessionEvents, BROWSER_URI_PREFIX,
+};
+use crate::normalize;
+use crate::product_extraction::GENERIC_SELECTORS;
+use crate::prompts::PromptLibrary;
+use crate::structured_data::{StructuredData, StructuredDataFormat};
+use crate::tools::{LocalTool, McpTool, ToolContext, ToolRegistry};
+
+/// Protocol versions we speak, newest first. `initialize` falls back to the newest.
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+const SESSION_NOTIFICATION_BUFFER: usize = 64;
+const DEFAULT_SESSION_IDLE_TIMEOUT_SECS: u64 = 1800;
+const DEFAULT_MAX_SESSIONS: usize = 1000;
+
+/// How long an MCP session may go unused, and how many may be open at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct McpSessionLimits {
+    pub idle_timeout: Duration,
+    pub max_sessions: usize,
+}
+
+impl McpSessionLimits {
+    /// Configure from `MCP_SESSION_IDLE_TIMEOUT_SECS` (default 1800) and `MCP_MAX_SESSIONS`
+    /// (default 1000)
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        Self {
+            idle_timeout: Duration::from_secs(var(
+                "MCP_SESSION_IDLE_TIMEOUT_SECS",
+                DEFAULT_SESSION_IDLE_TIMEOUT_SECS,
+            )),
+            max_sessions: var("MCP_MAX_SESSIONS", DEFAULT_MAX_SESSIONS).max(1),
+        }
+    }
+}
 
 // MCP Protocol Structures
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,14 +76,42 @@ pub struct MCPRequest {
 pub struct MCPResponse {
     pub jsonrpc: String,
     pub id: Option<Value>,
+    // JSON-RPC responses carry exactly one of `result` and `error`
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<MCPError>,
 }
 
+impl MCPResponse {
+    pub fn success(id: Option<Value>, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn failure(id: Option<Value>, code: i32, message: impl Into<String>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(MCPError {
+                code,
+                message: message.into(),
+                data: None,
+            }),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MCPError {
     pub code: i32,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<Value>,
 }
 
@@ -42,92 +122,396 @@ pub struct ToolInfo {
     pub input_schema: Value,
 }
 
-#[derive(Debug, Clone)]
+/// A client connection established by `initialize` over the Streamable HTTP transport
+pub struct McpSession {
+    pub id: String,
+    pub protocol_version: String,
+    initialized: AtomicBool,
+    last_used: Mutex<Instant>,
+    notifications: broadcast::Sender<Value>,
+    /// Resource URIs the client asked to hear `notifications/resources/updated` for
+    subscriptions: Mutex<HashSet<String>>,
+}
+
+impl McpSession {
+    fn new(id: String, protocol_version: String) -> Self {
+        let (notifications, _) = broadcast::channel(SESSION_NOTIFICATION_BUFFER);
+        Self {
+            id,
+            protocol_version,
+            initialized: AtomicBool::new(false),
+            last_used: Mutex::new(Instant::now()),
+            notifications,
+            subscriptions: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Whether the client has sent `notifications/initialized`
+    pub fn is_initialized(&self) -> bool {
+        self.initialized.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn mark_initialized(&self) {
+        self.initialized.store(true, Ordering::Relaxed);
+    }
+
+    fn touch(&self) {
+        *self.last_used.lock().unwrap() = Instant::now();
+    }
+
+    fn last_used(&self) -> Instant {
+        *self.last_used.lock().unwrap()
+    }
+
+    /// A client listening for notifications is using the session even when it sends nothing
+    fn has_listener(&self) -> bool {
+        self.notifications.receiver_count() > 0
+    }
+
+    fn is_expired(&self, idle_timeout: Duration, now: Instant) -> bool {
+        !self.has_listener() && now.saturating_duration_since(self.last_used()) >= idle_timeout
+    }
+
+    /// Queue a server-to-client notification on the session's SSE stream.
+    /// Dropped silently when no stream is open.
+    pub fn notify(&self, method: &str, params: Value) {
+        let _ = self.notifications.send(json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params
+        }));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Value> {
+        self.notifications.subscribe()
+    }
+
+    pub fn subscribe_resource(&self, uri: &str) {
+        self.subscriptions.lock().unwrap().insert(uri.to_string());
+    }
+
+    pub fn unsubscribe_resource(&self, uri: &str) -> bool {
+        self.subscriptions.lock().unwrap().remove(uri)
+    }
+
+    pub fn is_subscribed(&self, uri: &str) -> bool {
+        self.subscriptions.lock().unwrap().contains(uri)
+    }
+}
+
 pub struct MCPServerState {
-    pub tools: Vec<ToolInfo>,
+    pub registry: Arc<ToolRegistry>,
+    pub content: Arc<ContentStore>,
+    pub prompts: Arc<PromptLibrary>,
+    sessions: Arc<RwLock<HashMap<String, Arc<McpSession>>>>,
+    session_limits: McpSessionLimits,
+    /// Live browser sessions exposed as `browser://sessions/...` resources
+    browser_sessions: Option<BrowserSessions>,
+    /// Browser origins allowed to call `/mcp`; `None` allows only localhost origins
+    pub(crate) allowed_origins: Option<Vec<String>>,
 }
 
 impl MCPServerState {
     pub fn new() -> Self {
+        let content = Arc::new(ContentStore::from_env());
+        Self::with_registry(Arc::new(builtin_tools(content.clone())), content)
+    }
+
+    pub fn with_registry(registry: Arc<ToolRegistry>, content: Arc<ContentStore>) -> Self {
+        let allowed_origins = std::env::var("MCP_ALLOWED_ORIGINS").ok().map(|origins| {
+            origins
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect()
+        });
+
         Self {
-            tools: vec![
-                ToolInfo {
-                    name: "extract_clean_text".to_string(),
-                    description: "Extract clean, readable text content from HTML".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "html_content": {
-                                "type": "string",
-                                "description": "Raw HTML content to clean"
-                            }
-                        },
-                        "required": ["html_content"]
-                    }),
-                },
-                ToolInfo {
-                    name: "extract_product_data".to_string(),
-                    description: "Extract structured product information using CSS selectors"
-                        .to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "html_content": {
-                                "type": "string",
-                                "description": "HTML content to parse"
-                            },
-                            "url": {
-                                "type": "string",
-                                "description": "Source URL for context"
-                            }
-                        },
-                        "required": ["html_content"]
-                    }),
-                },
-                ToolInfo {
-                    name: "extract_by_selectors".to_string(),
-                    description: "Extract specific content using CSS selectors".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "html_content": {
-                                "type": "string",
-                                "description": "HTML content to parse"
-                            },
-                            "selectors": {
-                                "type": "object",
-                                "description": "CSS selectors to extract data",
-                                "additionalProperties": {"type": "string"}
-                            }
-                        },
-                        "required": ["html_content", "selectors"]
-                    }),
-                },
-                ToolInfo {
-                    name: "analyze_page_structure".to_string(),
-                    description: "Analyze HTML structure and suggest extraction strategies"
-                        .to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "html_content": {
-                                "type": "string",
-                                "description": "HTML content to analyze"
-                            }
-                        },
-                        "required": ["html_content"]
-                    }),
-                },
-            ],
+            registry,
+            content,
+            prompts: Arc::new(PromptLibrary::builtin()),
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            session_limits: McpSessionLimits::from_env(),
+            browser_sessions: None,
+            allowed_origins,
+        }
+    }
+
+    /// Serve `prompts` from `prompts/list` and `prompts/get` instead of the built-ins
+    pub fn with_prompts(mut self, prompts: Arc<PromptLibrary>) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    pub fn with_session_limits(mut self, limits: McpSessionLimits) -> Self {
+        self.session_limits = limits;
+        self
+    }
+
+    /// Expose `sessions` as resources, and turn `events` into resource notifications for
+    /// subscribed clients. Must be called from within a Tokio runtime.
+    pub fn with_browser_sessions(
+        mut self,
+        sessions: BrowserSessions,
+        events: &SessionEvents,
+    ) -> Self {
+        self.browser_sessions = Some(sessions);
+        tokio::spawn(forward_session_events(
+            self.sessions.clone(),
+            events.subscribe(),
+        ));
+        self
+    }
+
+    /// Look up a session and count this as a use. Sessions idle past the timeout are closed.
+    pub fn session(&self, id: &str) -> Option<Arc<McpSession>> {
+        let session = self.sessions.read().unwrap().get(id).cloned()?;
+        if session.is_expired(self.session_limits.idle_timeout, Instant::now()) {
+            self.close_session(id);
+            return None;
+        }
+        session.touch();
+        Some(session)
+    }
+
+    pub fn open_session(&self, protocol_version: &str) -> Arc<McpSession> {
+        self.open_session_with_id(&uuid::Uuid::new_v4().to_string(), protocol_version)
+    }
+
+    /// Open a session under a fixed ID, for transports with a single implicit client
+    pub fn open_session_with_id(&self, id: &str, protocol_version: &str) -> Arc<McpSession> {
+        self.make_room_for_session();
+        let session = Arc::new(McpSession::new(
+            id.to_string(),
+            protocol_version.to_string(),
+        ));
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(session.id.clone(), session.clone());
+        info!("Opened MCP session {}", session.id);
+        session
+    }
+
+    /// Terminate a session, which also ends its SSE stream. Returns false for unknown IDs.
+    pub fn close_session(&self, id: &str) -> bool {
+        let removed = self.sessions.write().unwrap().remove(id).is_some();
+        if removed {
+            info!("Closed MCP session {}", id);
+            self.registry.session_closed(id);
+        }
+        removed
+    }
+
+    /// Close sessions idle past the timeout, then the least recently used ones until there
+    /// is room for one more under the session cap
+    fn make_room_for_session(&self) {
+        let now = Instant::now();
+        let closing: Vec<String> = {
+            let sessions = self.sessions.read().unwrap();
+            let mut by_last_use: Vec<&Arc<McpSession>> = sessions.values().collect();
+            by_last_use.sort_by_key(|session| session.last_used());
+            let excess = (sessions.len() + 1).saturating_sub(self.session_limits.max_sessions);
+            by_last_use
+                .iter()
+                .enumerate()
+                .filter(|(index, session)| {
+                    *index < excess || session.is_expired(self.session_limits.idle_timeout, now)
+                })
+                .map(|(_, session)| session.id.clone())
+                .collect()
+        };
+        for id in closing {
+            self.close_session(&id);
+        }
+    }
+
+    /// Send a notification to every open session
+    pub fn notify_all(&self, method: &str, params: Value) {
+        for session in self.sessions.read().unwrap().values() {
+            session.notify(method, params.clone());
+        }
+    }
+}
+
+impl Default for MCPServerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Relay browser session events to MCP sessions: list changes to everyone, page changes
+/// to the sessions subscribed to that browser session's resources
+async fn forward_session_events(
+    sessions: Arc<RwLock<HashMap<String, Arc<McpSession>>>>,
+    mut events: broadcast::Receiver<SessionEvent>,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Dropped {} browser session events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let open = sessions.read().unwrap();
+        match event {
+            SessionEvent::Opened(_) | SessionEvent::Closed(_) => {
+                for session in open.values() {
+                    session.notify("notifications/resources/list_changed", json!({}));
+                }
+            }
+            SessionEvent::Changed(browser_session_id) => {
+                let uris: Vec<String> = [
+                    BrowserResourceKind::Html,
+                    BrowserResourceKind::Text,
+                    BrowserResourceKind::Screenshot,
+                    BrowserResourceKind::Extraction,
+                ]
+                .into_iter()
+                .map(|kind| browser_resource_uri(&browser_session_id, kind))
+                .collect();
+
+                for session in open.values() {
+                    for uri in uris.iter().filter(|uri| session.is_subscribed(uri)) {
+                        session.notify("notifications/resources/updated", json!({ "uri": uri }));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The HTML extraction tools implemented in this module, reading pages from `content`
+/// when called with a `content_ref`
+pub fn builtin_tools(content: Arc<ContentStore>) -> ToolRegistry {
+    let mut registry = ToolRegistry::new();
+    registry.register(page_tool(
+        "extract_clean_text",
+        "Extract clean, readable text content from HTML",
+        json!({}),
+        &[],
+        &content,
+        extract_clean_text,
+    ));
+    registry.register(page_tool(
+        "extract_product_data",
+        "Extract structured product information using CSS selectors",
+        json!({
+            "url": {
+                "type": "string",
+                "description": "Source URL for context"
+            }
+        }),
+        &[],
+        &content,
+        extract_product_data,
+    ));
+    registry.register(page_tool(
+        "extract_structured_data",
+        "Extract the page's structured data (JSON-LD, microdata, RDFa, OpenGraph and Twitter \
+cards) as a list of schema.org entities",
+        json!({
+            "types": {
+                "type": "array",
+                "items": {"type": "string"},
+                "description": "Only return entities of these schema.org types, e.g. [\"Product\"]"
+            }
+        }),
+        &[],
+        &content,
+        extract_structured_data,
+    ));
+    registry.register(page_tool(
+        "extract_by_selectors",
+        "Extract specific content using CSS selectors",
+        json!({
+            "selectors": {
+                "type": "object",
+                "description": "CSS selectors to extract data",
+                "additionalProperties": {"type": "string"}
+            }
+        }),
+        &["selectors"],
+        &content,
+        extract_by_selectors,
+    ));
+    registry.register(page_tool(
+        "analyze_page_structure",
+        "Analyze HTML structure and suggest extraction strategies",
+        json!({}),
+        &[],
+        &content,
+        analyze_page_structure,
+    ));
+    registry
+}
+
+/// Wrap an HTML tool so it accepts either inline `html_content` or a stored page's `content_ref`
+fn page_tool(
+    name: &str,
+    description: &str,
+    extra_properties: Value,
+    required: &[&str],
+    content: &Arc<ContentStore>,
+    run: fn(&str, &Value) -> Result<Value, String>,
+) -> Arc<dyn McpTool> {
+    let mut properties = json!({
+        "content_ref": {
+            "type": "string",
+            "description": "ID or resource:// URI of a stored page (preferred over html_content)"
+        },
+        "html_content": {
+            "type": "string",
+            "description": "Raw HTML content, when no content_ref is available"
         }
+    });
+    if let (Some(properties), Some(extra)) =
+        (properties.as_object_mut(), extra_properties.as_object())
+    {
+        properties.extend(extra.clone());
     }
+
+    let content = content.clone();
+    Arc::new(LocalTool::new(
+        name,
+        description,
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required
+        }),
+        move |arguments| {
+            if let Some(reference) = arguments["content_ref"].as_str() {
+                let page = content
+                    .get(reference)
+                    .ok_or_else(|| format!("Unknown or expired content_ref: {}", reference))?;
+                run(&page.html, arguments)
+            } else {
+                let html_content = arguments["html_content"]
+                    .as_str()
+                    .ok_or("Missing content_ref or html_content parameter")?;
+                run(html_content, arguments)
+            }
+        },
+    ))
 }
 
-pub fn create_mcp_router() -> Router<Arc<MCPServerState>> {
+pub fn create_mcp_router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Arc<MCPServerState>: FromRef<S>,
+{
     Router::new()
-        .route("/mcp", post(handle_mcp_request))
+        .route(
+            "/mcp",
+            get(handle_mcp_get)
+                .post(handle_mcp_post)
+                .delete(handle_mcp_delete),
+        )
         .route("/.well-known/mcp/manifest.json", get(get_manifest))
-        .with_state(Arc::new(MCPServerState::new()))
 }
 
 async fn get_manifest(State(state): State<Arc<MCPServerState>>) -> Json<Value> {
@@ -135,7 +519,7 @@ async fn get_manifest(State(state): State<Arc<MCPServerState>>) -> Json<Value> {
         "name": "web-content-extractor",
         "version": "1.0.0",
         "description": "Specialized tools for web content extraction and parsing",
-        "tools": state.tools.iter().map(|tool| json!({
+        "tools": state.registry.definitions().iter().map(|tool| json!({
             "name": tool.name,
             "description": tool.description,
             "input_schema": tool.input_schema
@@ -143,16 +527,33 @@ async fn get_manifest(State(state): State<Arc<MCPServerState>>) -> Json<Value> {
     }))
 }
 
-async fn handle_mcp_request(
-    State(state): State<Arc<MCPServerState>>,
-    Json(request): Json<MCPRequest>,
-) -> Result<Json<MCPResponse>, StatusCode> {
-    info!("Received MCP request: {:?}", request);
+/// Handle one JSON-RPC message for any transport. Notifications (no `id`) get no response.
+/// `session_id` identifies the client connection, for tools that keep per-session state.
+pub async fn dispatch_request(
+    state: &MCPServerState,
+    session_id: Option<&str>,
+    request: MCPRequest,
+) -> Option<MCPResponse> {
+    if request.id.is_none() {
+        info!("Received MCP notification: {}", request.method);
+        return None;
+    }
 
     let response = match request.method.as_str() {
         "initialize" => handle_initialize(&request),
-        "tools/list" => handle_tools_list(&state, &request),
-        "tools/call" => handle_tool_call(&request).await,
+        "ping" => MCPResponse::success(request.id.clone(), json!({})),
+        "tools/list" => handle_tools_list(state, &request),
+        "tools/call" => handle_tool_call(state, session_id, &request).await,
+        "resources/list" => handle_resources_list(state, &request).await,
+        "resources/templates/list" => MCPResponse::success(
+            request.id.clone(),
+            json!({ "resourceTemplates": browser_resource_templates() }),
+        ),
+        "resources/read" => handle_resources_read(state, &request).await,
+        "prompts/list" => handle_prompts_list(state, &request),
+        "prompts/get" => handle_prompts_get(state, &request),
+        "resources/subscribe" => handle_resources_subscribe(state, session_id, &request, true),
+        "resources/unsubscribe" => handle_resources_subscribe(state, session_id, &request, false),
         _ => MCPResponse {
             jsonrpc: "2.0".to_string(),
             id: request.id,
@@ -165,34 +566,42 @@ async fn handle_mcp_request(
         },
     };
 
-    Ok(Json(response))
+    Some(response)
 }
 
 fn handle_initialize(request: &MCPRequest) -> MCPResponse {
-    MCPResponse {
-        jsonrpc: "2.0".to_string(),
-        id: request.id.clone(),
-        result: Some(json!({
+    // Echo the client's version when we support it, otherwise offer our newest
+    let requested = request
+        .params
+        .as_ref()
+        .and_then(|params| params["protocolVersion"].as_str());
+    let protocol_version = requested
+        .filter(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+        .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0]);
+
+    MCPResponse::success(
+        request.id.clone(),
+        json!({
+            "protocolVersion": protocol_version,
             "capabilities": {
-                "tools": true,
-                "resources": false,
-                "prompts": false
+                "tools": { "listChanged": false },
+                "resources": { "subscribe": true, "listChanged": true },
+                "prompts": { "listChanged": false }
             },
             "serverInfo": {
                 "name": "web-content-extractor",
                 "version": "1.0.0"
             }
-        })),
-        error: None,
-    }
+        }),
+    )
 }
 
-fn handle_tools_list(state: &Arc<MCPServerState>, request: &MCPRequest) -> MCPResponse {
+fn handle_tools_list(state: &MCPServerState, request: &MCPRequest) -> MCPResponse {
     MCPResponse {
         jsonrpc: "2.0".to_string(),
         id: request.id.clone(),
         result: Some(json!({
-            "tools": state.tools.iter().map(|tool| json!({
+            "tools": state.registry.definitions().iter().map(|tool| json!({
                 "name": tool.name,
                 "description": tool.description,
                 "inputSchema": tool.input_schema
@@ -202,7 +611,165 @@ fn handle_tools_list(state: &Arc<MCPServerState>, request: &MCPRequest) -> MCPRe
     }
 }
 
-async fn handle_tool_call(request: &MCPRequest) -> MCPResponse {
+async fn handle_resources_list(state: &MCPServerState, request: &MCPRequest) -> MCPResponse {
+    let mut resources: Vec<Value> = state.content.list().iter().map(|page| json!({
+        "uri": page.uri(),
+        "name": page.url,
+        "description": format!("HTML of {} stored at {}", page.url, page.stored_at.to_rfc3339()),
+        "mimeType": "text/html",
+        "size": page.size()
+    })).collect();
+
+    if let Some(browser_sessions) = &state.browser_sessions {
+        resources.extend(list_browser_resources(browser_sessions).await);
+    }
+
+    MCPResponse::success(request.id.clone(), json!({ "resources": resources }))
+}
+
+async fn handle_resources_read(state: &MCPServerState, request: &MCPRequest) -> MCPResponse {
+    let uri = request
+        .params
+        .as_ref()
+        .and_then(|params| params["uri"].as_str())
+        .unwrap_or("");
+
+    if uri.starts_with(BROWSER_URI_PREFIX) {
+        let result = match &state.browser_sessions {
+            Some(browser_sessions) => read_browser_resource(browser_sessions, uri).await,
+            None => Err(format!("Resource not found: {}", uri)),
+        };
+        return match result {
+            Ok(contents) => {
+                MCPResponse::success(request.id.clone(), json!({ "contents": [contents] }))
+            }
+            Err(message) => MCPResponse::failure(request.id.clone(), -32002, message),
+        };
+    }
+
+    let page = uri
+        .starts_with(CONTENT_URI_PREFIX)
+        .then(|| state.content.get(uri))
+        .flatten();
+
+    match page {
+        Some(page) => MCPResponse {
+            jsonrpc: "2.0".to_string(),
+            id: request.id.clone(),
+            result: Some(json!({
+                "contents": [{
+                    "uri": page.uri(),
+                    "mimeType": "text/html",
+                    "text": page.html
+                }]
+            })),
+            error: None,
+        },
+        None => MCPResponse {
+            jsonrpc: "2.0".to_string(),
+            id: request.id.clone(),
+            result: None,
+            error: Some(MCPError {
+                code: -32002,
+                message: format!("Resource not found: {}", uri),
+                data: None,
+            }),
+        },
+    }
+}
+
+fn handle_prompts_list(state: &MCPServerState, request: &MCPRequest) -> MCPResponse {
+    MCPResponse::success(
+        request.id.clone(),
+        json!({
+            "prompts": state.prompts.list().iter().map(|prompt| json!({
+                "name": prompt.name,
+                "description": prompt.description,
+                "arguments": prompt.arguments
+            })).collect::<Vec<_>>()
+        }),
+    )
+}
+
+fn handle_prompts_get(state: &MCPServerState, request: &MCPRequest) -> MCPResponse {
+    let params = request.params.clone().unwrap_or(Value::Null);
+    let Some(name) = params["name"].as_str() else {
+        return MCPResponse::failure(request.id.clone(), -32602, "Missing prompt name");
+    };
+    let Some(prompt) = state.prompts.get(name) else {
+        return MCPResponse::failure(
+            request.id.clone(),
+            -32602,
+            format!("Unknown prompt: {}", name),
+        );
+    };
+
+    let arguments = params["arguments"].as_object().cloned().unwrap_or_default();
+    let rendered = match prompt.render(&arguments) {
+        Ok(rendered) => rendered,
+        Err(message) => return MCPResponse::failure(request.id.clone(), -32602, message),
+    };
+
+    // MCP prompts have no system role, so instructions lead as a user message
+    let messages: Vec<Value> = rendered
+        .system
+        .map(|system| ("user".to_string(), system))
+        .into_iter()
+        .chain(rendered.messages)
+        .map(|(role, text)| {
+            json!({
+                "role": role,
+                "content": { "type": "text", "text": text }
+            })
+        })
+        .collect();
+
+    MCPResponse::success(
+        request.id.clone(),
+        json!({
+            "description": prompt.description,
+            "messages": messages
+        }),
+    )
+}
+
+/// `resources/subscribe` and `resources/unsubscribe`. Updates are delivered as
+/// session notifications, so both need a session.
+fn handle_resources_subscribe(
+    state: &MCPServerState,
+    session_id: Option<&str>,
+    request: &MCPRequest,
+    subscribe: bool,
+) -> MCPResponse {
+    let Some(session) = session_id.and_then(|id| state.session(id)) else {
+        return MCPResponse::failure(
+            request.id.clone(),
+            -32600,
+            "Resource subscriptions require an MCP session",
+        );
+    };
+
+    let Some(uri) = request
+        .params
+        .as_ref()
+        .and_then(|params| params["uri"].as_str())
+    else {
+        return MCPResponse::failure(request.id.clone(), -32602, "Missing uri parameter");
+    };
+
+    if subscribe {
+        session.subscribe_resource(uri);
+    } else {
+        session.unsubscribe_resource(uri);
+    }
+    MCPResponse::success(request.id.clone(), json!({}))
+}
+
+async fn handle_tool_call(
+    state: &MCPServerState,
+    session_id: Option<&str>,
+    request: &MCPRequest,
+) -> MCPResponse {
     let params = match &request.params {
         Some(params) => params,
         None => {
@@ -222,40 +789,55 @@ async fn handle_tool_call(request: &MCPRequest) -> MCPResponse {
     let tool_name = params["name"].as_str().unwrap_or("");
     let arguments = &params["arguments"];
 
-    let result = match tool_name {
-        "extract_clean_text" => extract_clean_text(arguments).await,
-        "extract_product_data" => extract_product_data(arguments).await,
-        "extract_by_selectors" => extract_by_selectors(arguments).await,
-        "analyze_page_structure" => analyze_page_structure(arguments).await,
-        _ => Err(format!("Unknown tool: {}", tool_name)),
+    if state.registry.get(tool_name).is_none() {
+        return MCPResponse::failure(
+            request.id.clone(),
+            -32602,
+            format!("Unknown tool: {}", tool_name),
+        );
+    }
+
+    // Tool failures are results the model can see, not protocol errors
+    let context = ToolContext {
+        session_id: session_id.map(|id| id.to_string()),
     };
+    let result = match state.registry.call(tool_name, arguments, &context).await {
+        Ok(output) => {
+            let mut content: Vec<Value> = output
+                .images
+                .iter()
+                .map(|image| {
+                    json!({
+                        "type": "image",
+                        "data": image.data,
+                        "mimeType": image.mime_type
+                    })
+                })
+                .collect();
+            if !output.value.is_null() {
+                content.push(json!({
+                    "type": "text",
+                    "text": serde_json::to_string_pretty(&output.value).unwrap_or_default()
+                }));
+            }
 
-    match result {
-        Ok(content) => MCPResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id.clone(),
-            result: Some(content),
-            error: None,
-        },
-        Err(error) => MCPResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id.clone(),
-            result: None,
-            error: Some(MCPError {
-                code: -32603,
-                message: error,
-                data: None,
-            }),
-        },
-    }
+            let mut result = json!({ "content": content, "isError": false });
+            if output.value.is_object() {
+                result["structuredContent"] = output.value;
+            }
+            result
+        }
+        Err(error) => json!({
+            "content": [{ "type": "text", "text": error }],
+            "isError": true
+        }),
+    };
+
+    MCPResponse::success(request.id.clone(), result)
 }
 
 // Tool implementations
-async fn extract_clean_text(arguments: &Value) -> Result<Value, String> {
-    let html_content = arguments["html_content"]
-        .as_str()
-        .ok_or("Missing html_content parameter")?;
-
+pub(crate) fn extract_clean_text(html_content: &str, _arguments: &Value) -> Result<Value, String> {
     let document = Html::parse_document(html_content);
 
     // Remove script and style elements
@@ -293,9 +875,8 @@ async fn extract_clean_text(arguments: &Value) -> Result<Value, String> {
     // Fallback to body text if no main content found
     if extracted_text.is_empty() {
         if let Ok(body_selector) = Selector::parse("body") {
-            for element in document.select(&body_selector) {
+            if let Some(element) = document.select(&body_selector).next() {
                 extracted_text = element.text().collect::<Vec<_>>().join(" ");
-                break;
             }
         }
     }
@@ -313,67 +894,14 @@ async fn extract_clean_text(arguments: &Value) -> Result<Value, String> {
     }))
 }
 
-async fn extract_product_data(arguments: &Value) -> Result<Value, String> {
-    let html_content = arguments["html_content"]
-        .as_str()
-        .ok_or("Missing html_content parameter")?;
+fn extract_product_data(html_content: &str, arguments: &Value) -> Result<Value, String> {
     let url = arguments["url"].as_str().unwrap_or("");
 
     let document = Html::parse_document(html_content);
     let mut product_data = json!({});
 
-    // Common product selectors for different e-commerce sites
-    let product_selectors = [
-        // Amazon
-        (
-            "name",
-            vec!["#productTitle", "h1.a-size-large", ".product-title"],
-        ),
-        (
-            "price",
-            vec![
-                "[data-testid='price']",
-                ".a-price-whole",
-                ".price",
-                ".current-price",
-                "[data-price]",
-            ],
-        ),
-        (
-            "description",
-            vec![
-                "[data-feature-name='productDescription']",
-                ".product-description",
-                "#description",
-            ],
-        ),
-        (
-            "availability",
-            vec!["#availability span", ".availability", "#stock-status"],
-        ),
-        ("brand", vec!["[data-testid='brand']", ".brand", "#brand"]),
-        (
-            "rating",
-            vec![
-                "[data-testid='rating']",
-                ".a-icon-alt",
-                ".rating",
-                ".star-rating",
-            ],
-        ),
-        (
-            "image",
-            vec![
-                "[data-testid='image']",
-                "#landingImage",
-                ".product-image img",
-                ".main-image img",
-            ],
-        ),
-    ];
-
-    for (field, selectors) in &product_selectors {
-        for selector_str in selectors {
+    for (field, selectors) in GENERIC_SELECTORS {
+        for selector_str in selectors.iter() {
             if let Ok(selector) = Selector::parse(selector_str) {
                 for element in document.select(&selector) {
                     let value = if *field == "image" {
@@ -399,19 +927,12 @@ async fn extract_product_data(arguments: &Value) -> Result<Value, String> {
         }
     }
 
-    // Try JSON-LD structured data
-    if let Ok(script_selector) = Selector::parse("script[type='application/ld+json']") {
-        for element in document.select(&script_selector) {
-            let script_content = element.text().collect::<String>();
-            if let Ok(json_ld) = serde_json::from_str::<Value>(&script_content) {
-                if let Some(product_json) = extract_product_from_jsonld(&json_ld) {
-                    // Merge JSON-LD data, preferring existing data
-                    for (key, value) in product_json.as_object().unwrap() {
-                        if product_data[key] == json!(null) {
-                            product_data[key] = value.clone();
-                        }
-                    }
-                }
+    // Fill the gaps from the page's structured data: JSON-LD, microdata, RDFa
+    for entity in StructuredData::from_document(&document).products() {
+        let structured = flatten_product(&entity.to_json());
+        for (key, value) in structured.as_object().into_iter().flatten() {
+            if product_data[key] == json!(null) {
+                product_data[key] = value.clone();
             }
         }
     }
@@ -420,14 +941,39 @@ async fn extract_product_data(arguments: &Value) -> Result<Value, String> {
         "url": url,
         "extracted_data": product_data,
         "extraction_timestamp": chrono::Utc::now().to_rfc3339(),
-        "extraction_method": "css_selectors_and_jsonld"
+        "extraction_method": "css_selectors_and_structured_data"
+    }))
+}
+
+fn extract_structured_data(html_content: &str, arguments: &Value) -> Result<Value, String> {
+    let types: Vec<&str> = arguments["types"]
+        .as_array()
+        .map(|types| types.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let mut data = StructuredData::from_html(html_content);
+    if !types.is_empty() {
+        data.entities = data.find(&types);
+    }
+
+    let count = |format: StructuredDataFormat| {
+        data.entities
+            .iter()
+            .filter(|entity| entity.format == format)
+            .count()
+    };
+    Ok(json!({
+        "entities": data.entities,
+        "counts": {
+            "json_ld": count(StructuredDataFormat::JsonLd),
+            "microdata": count(StructuredDataFormat::Microdata),
+            "rdfa": count(StructuredDataFormat::Rdfa),
+            "open_graph": count(StructuredDataFormat::OpenGraph),
+        }
     }))
 }
 
-async fn extract_by_selectors(arguments: &Value) -> Result<Value, String> {
-    let html_content = arguments["html_content"]
-        .as_str()
-        .ok_or("Missing html_content parameter")?;
+fn extract_by_selectors(html_content: &str, arguments: &Value) -> Result<Value, String> {
     let selectors = arguments["selectors"]
         .as_object()
         .ok_or("Missing selectors parameter")?;
@@ -464,11 +1010,7 @@ async fn extract_by_selectors(arguments: &Value) -> Result<Value, String> {
     Ok(results)
 }
 
-async fn analyze_page_structure(arguments: &Value) -> Result<Value, String> {
-    let html_content = arguments["html_content"]
-        .as_str()
-        .ok_or("Missing html_content parameter")?;
-
+fn analyze_page_structure(html_content: &str, _arguments: &Value) -> Result<Value, String> {
     let document = Html::parse_document(html_content);
 
     // Analyze common e-commerce patterns
@@ -519,61 +1061,71 @@ async fn analyze_page_structure(arguments: &Value) -> Result<Value, String> {
     Ok(analysis)
 }
 
-fn extract_product_from_jsonld(json_ld: &Value) -> Option<Value> {
-    // Handle both single objects and arrays
-    let items = if json_ld.is_array() {
-        json_ld.as_array()?
-    } else {
-        std::slice::from_ref(json_ld)
-    };
-
-    for item in items {
-        if let Some(type_val) = item.get("@type") {
-            if type_val == "Product" {
-                let mut product = json!({});
-
-                if let Some(name) = item.get("name") {
-                    product["name"] = name.clone();
-                }
-                if let Some(description) = item.get("description") {
-                    product["description"] = description.clone();
-                }
-                if let Some(brand) = item.get("brand") {
-                    product["brand"] = if brand.is_string() {
-                        brand.clone()
-                    } else if let Some(brand_name) = brand.get("name") {
-                        brand_name.clone()
-                    } else {
-                        json!(null)
-                    };
-                }
-                if let Some(offers) = item.get("offers") {
-                    if let Some(price) = offers.get("price") {
-                        product["price"] = price.clone();
-                    }
-                    if let Some(availability) = offers.get("availability") {
-                        product["availability"] = availability.clone();
-                    }
-                }
-                if let Some(aggregate_rating) = item.get("aggregateRating") {
-                    if let Some(rating_value) = aggregate_rating.get("ratingValue") {
-                        product["rating"] = rating_value.clone();
-                    }
-                }
-                if let Some(image) = item.get("image") {
-                    product["image_url"] = if image.is_string() {
-                        image.clone()
-                    } else if image.is_array() && !image.as_array().unwrap().is_empty() {
-                        image.as_array().unwrap()[0].clone()
-                    } else {
-                        json!(null)
-                    };
-                }
+/// A schema.org product entity as the flat fields `extract_product_data` reports
+fn flatten_product(item: &Value) -> Value {
+    let mut product = json!({});
 
-                return Some(product);
-            }
+    if let Some(name) = item.get("name") {
+        product["name"] = name.clone();
+    }
+    if let Some(description) = item.get("description") {
+        product["description"] = description.clone();
+    }
+    if let Some(brand) = item.get("brand") {
+        product["brand"] = if brand.is_string() {
+            brand.clone()
+        } else if let Some(brand_name) = brand.get("name") {
+            brand_name.clone()
+        } else {
+            json!(null)
+        };
+    }
+    // One offer, a list of them, or an AggregateOffer with a price range
+    let offer = item.get("offers").map(|offers| match offers {
+        Value::Array(offers) => offers.first().unwrap_or(&Value::Null),
+        offer => offer,
+    });
+    if let Some(offer) = offer {
+        if let Some(price) = offer.get("price").or_else(|| offer.get("lowPrice")) {
+            product["price"] = price.clone();
+        }
+        if let Some(high_price) = offer.get("highPrice") {
+            product["high_price"] = high_price.clone();
+        }
+        if let Some(currency) = offer.get("priceCurrency") {
+            product["currency"] = currency.clone();
+        }
+        if let Some(availability) = offer.get("availability") {
+            product["availability"] = availability.clone();
         }
     }
+    if let Some(aggregate_rating) = item.get("aggregateRating") {
+        if let Some(rating_value) = aggregate_rating.get("ratingValue") {
+            product["rating"] = rating_value.clone();
+        }
+        if let Some(best_rating) = aggregate_rating.get("bestRating") {
+            product["rating_scale"] = best_rating.clone();
+        }
+        if let Some(count) = aggregate_rating
+            .get("reviewCount")
+            .or_else(|| aggregate_rating.get("ratingCount"))
+        {
+            product["review_count"] = count.clone();
+        }
+    }
+    if let Some(image) = item.get("image") {
+        let images = normalize::image_urls(image);
+        product["image_url"] = images.first().map_or(json!(null), |url| json!(url));
+        product["images"] = images.into();
+    }
+    for key in ["sku", "gtin13", "gtin12", "gtin14", "gtin8", "gtin", "mpn"] {
+        if let Some(value) = item.get(key) {
+            product[key] = value.clone();
+        }
+    }
+    if let Some(variants) = item.get("hasVariant") {
+        product["variants"] = variants.clone();
+    }
 
-    None
+    product
 }
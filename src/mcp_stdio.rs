@@ -0,0 +1,87 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{info, warn};
+
+use crate::mcp_server::{
+    dispatch_request, MCPRequest, MCPResponse, MCPServerState, SUPPORTED_PROTOCOL_VERSIONS,
+};
+
+/// The single client of a stdio server is treated as one long-lived session
+pub const STDIO_SESSION_ID: &str = "stdio";
+
+/// Serve MCP over stdin/stdout: one JSON-RPC message per line in each direction.
+///
+/// Nothing but protocol messages may be written to stdout, so logging must go to stderr.
+pub async fn serve_stdio(state: Arc<MCPServerState>) -> anyhow::Result<()> {
+    info!("Serving MCP over stdio");
+    serve(
+        &state,
+        BufReader::new(tokio::io::stdin()),
+        tokio::io::stdout(),
+    )
+    .await
+}
+
+/// Run the line-delimited JSON-RPC loop until the reader reaches end of input.
+///
+/// The client is registered as the `stdio` session for its lifetime, so server notifications
+/// (such as resource updates) are written out between responses.
+pub async fn serve<R, W>(state: &MCPServerState, reader: R, mut writer: W) -> anyhow::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let session = state.open_session_with_id(STDIO_SESSION_ID, SUPPORTED_PROTOCOL_VERSIONS[0]);
+    let mut notifications = session.subscribe();
+    let mut lines = reader.lines();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let response = match serde_json::from_str::<MCPRequest>(&line) {
+                    Ok(request) => dispatch_request(state, Some(STDIO_SESSION_ID), request).await,
+                    Err(e) => {
+                        warn!("Invalid JSON-RPC message on stdin: {}", e);
+                        Some(MCPResponse::failure(
+                            Some(Value::Null),
+                            -32700,
+                            format!("Parse error: {}", e),
+                        ))
+                    }
+                };
+
+                if let Some(response) = response {
+                    write_message(&mut writer, &response).await?;
+                }
+            }
+            Ok(notification) = notifications.recv() => {
+                write_message(&mut writer, &notification).await?;
+            }
+        }
+    }
+
+    info!("stdin closed, stopping MCP stdio server");
+    state.close_session(STDIO_SESSION_ID);
+    Ok(())
+}
+
+async fn write_message<W, T>(writer: &mut W, message: &T) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    writer.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,496 @@
+//! Turns the loosely formatted product values found on pages and in LLM answers
+//! ("$1,299.00", "4.5 out of 5 stars", "https://schema.org/InStock") into typed values
+
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::types::{Availability, Price, ProductInfo, ProductVariant, Rating};
+
+/// ISO 4217 codes recognized when written out next to a price
+const CURRENCY_CODES: &[&str] = &[
+    "AED", "AUD", "BRL", "CAD", "CHF", "CNY", "CZK", "DKK", "EUR", "GBP", "HKD", "HUF", "IDR",
+    "ILS", "INR", "JPY", "KRW", "MXN", "MYR", "NOK", "NZD", "PHP", "PLN", "RUB", "SAR", "SEK",
+    "SGD", "THB", "TRY", "TWD", "USD", "VND", "ZAR",
+];
+
+/// Currency symbols, longest first so "US$" wins over "$"
+const CURRENCY_SYMBOLS: &[(&str, &str)] = &[
+    ("US$", "USD"),
+    ("CA$", "CAD"),
+    ("AU$", "AUD"),
+    ("NZ$", "NZD"),
+    ("HK$", "HKD"),
+    ("C$", "CAD"),
+    ("A$", "AUD"),
+    ("S$", "SGD"),
+    ("R$", "BRL"),
+    ("zł", "PLN"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("¥", "JPY"),
+    ("₹", "INR"),
+    ("₩", "KRW"),
+    ("₽", "RUB"),
+    ("₺", "TRY"),
+    ("₪", "ILS"),
+    ("$", "USD"),
+];
+
+/// Parse a price such as "$1,299.00", "1.299,00 €" or "EUR 19.99". A range ("$10 - $20")
+/// yields its first amount. Next to a currency, a lone dot followed by three digits groups
+/// thousands, so "1.299 €" is 1299 euros.
+pub fn parse_price(text: &str) -> Option<Price> {
+    let currency = parse_currency(text);
+    let (start, end) = number_spans(text).into_iter().next()?;
+    Some(Price {
+        amount: decimal_from_run(&text[start..end], currency.is_some())?,
+        currency,
+    })
+}
+
+/// The ISO 4217 code for a currency code or symbol in `text`. A bare "$" is taken as USD.
+/// Codes must be written in capitals, so words such as "try" or "rub" are not currencies.
+pub fn parse_currency(text: &str) -> Option<String> {
+    let code = text
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|word| CURRENCY_CODES.contains(word))
+        .map(str::to_string);
+    if code.is_some() {
+        return code;
+    }
+
+    CURRENCY_SYMBOLS
+        .iter()
+        .find(|(symbol, _)| text.contains(symbol))
+        .map(|(_, code)| code.to_string())
+}
+
+/// The first number in `text`, with thousands separators removed. Both "1,299.00" and
+/// "1.299,00" are 1299; a lone comma is a decimal point unless three digits follow it.
+pub fn parse_decimal(text: &str) -> Option<Decimal> {
+    let (start, end) = number_spans(text).into_iter().next()?;
+    decimal_from_run(&text[start..end], false)
+}
+
+/// Parse a rating such as "4.5 out of 5 stars", "4.5/5 (1,234 reviews)" or "4.5"
+pub fn parse_rating(text: &str) -> Option<Rating> {
+    let lower = text.to_lowercase();
+    let spans = number_spans(&lower);
+    let (value_start, value_end) = *spans.first()?;
+    let value = to_f64(decimal_from_run(&lower[value_start..value_end], false)?);
+
+    let scale = spans
+        .get(1)
+        .filter(|&&(start, _)| matches!(lower[value_end..start].trim(), "/" | "of" | "out of"))
+        .and_then(|&(start, end)| decimal_from_run(&lower[start..end], false))
+        .map(to_f64);
+
+    // Neither the value nor the scale can be the review count
+    let review_count = spans
+        .iter()
+        .skip(if scale.is_some() { 2 } else { 1 })
+        .find(|&&(start, end)| {
+            let after = lower[end..].trim_start();
+            ["review", "rating", "vote"]
+                .iter()
+                .any(|word| after.starts_with(word))
+                || (lower[..start].ends_with('(') && after.starts_with(')'))
+        })
+        .and_then(|&(start, end)| parse_count(&lower[start..end]));
+
+    Some(Rating {
+        value,
+        scale,
+        review_count,
+    })
+}
+
+/// Map stock text ("In stock", "Only 3 left", "https://schema.org/PreOrder") to availability
+pub fn parse_availability(text: &str) -> Option<Availability> {
+    // schema.org URLs end in the value's name
+    let text = if text.contains("schema.org") {
+        text.rsplit('/').next().unwrap_or(text)
+    } else {
+        text
+    };
+    let compact: String = text
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    let has = |words: &[&str]| words.iter().any(|word| compact.contains(word));
+
+    let availability = if has(&["soldout"]) {
+        Availability::SoldOut
+    } else if has(&["outofstock", "notinstock", "unavailable", "notavailable"]) {
+        Availability::OutOfStock
+    } else if has(&["discontinued"]) {
+        Availability::Discontinued
+    } else if has(&["preorder", "presale", "comingsoon"]) {
+        Availability::PreOrder
+    } else if has(&["backorder"]) {
+        Availability::BackOrder
+    } else if has(&["limited", "lowstock", "fewleft"])
+        || (compact.starts_with("only") && compact.contains("left"))
+    {
+        Availability::LimitedAvailability
+    } else if has(&["onlineonly"]) {
+        Availability::OnlineOnly
+    } else if has(&["instoreonly"]) {
+        Availability::InStoreOnly
+    } else if has(&["instock", "available"]) || compact == "true" {
+        Availability::InStock
+    } else {
+        return None;
+    };
+    Some(availability)
+}
+
+/// Build a product from JSON: either a schema.org `Product` (with `offers`, `aggregateRating`,
+/// `hasVariant`, ...) or the flatter shape LLMs answer with. Unparseable values are dropped.
+pub fn product_from_json(value: &Value) -> ProductInfo {
+    let offer = main_offer(value);
+    let currency = currency_field(value).or_else(|| offer.and_then(currency_field));
+
+    let mut rating = first_field(value, &["rating", "aggregateRating"]).and_then(rating_from_value);
+    if let (Some(rating), Some(count)) = (
+        rating.as_mut(),
+        first_field(value, &["review_count", "reviewCount", "ratingCount"])
+            .and_then(count_from_value),
+    ) {
+        rating.review_count.get_or_insert(count);
+    }
+
+    let mut variants: Vec<ProductVariant> = first_field(value, &["variants", "hasVariant"])
+        .map(as_list)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|variant| variant_from_json(variant, currency.as_deref()))
+        .collect();
+    // Several offers on one product, listed directly or inside an AggregateOffer, are its
+    // variants
+    if variants.is_empty() {
+        let offers = value.get("offers").map(as_list).unwrap_or_default();
+        let offers = match offers.as_slice() {
+            [aggregate] => aggregate.get("offers").map(as_list).unwrap_or_default(),
+            _ => offers,
+        };
+        if offers.len() > 1 {
+            variants = offers
+                .into_iter()
+                .map(|offer| variant_from_json(offer, currency.as_deref()))
+                .collect();
+        }
+    }
+
+    ProductInfo {
+        name: text_field(value, &["name", "title"]),
+        description: text_field(value, &["description"]),
+        price: price_field(value, offer, currency.as_deref()),
+        availability: first_field(value, &["availability"])
+            .or_else(|| offer.and_then(|offer| offer.get("availability")))
+            .and_then(availability_from_value),
+        brand: text_field(value, &["brand", "manufacturer"]),
+        rating,
+        images: first_field(value, &["images", "image", "image_url"])
+            .map(image_urls)
+            .unwrap_or_default(),
+        sku: text_field(value, &["sku"]).or_else(|| offer.and_then(|o| text_field(o, &["sku"]))),
+        gtin: gtin_field(value).or_else(|| offer.and_then(gtin_field)),
+        mpn: text_field(value, &["mpn"]),
+        variants,
+        ..Default::default()
+    }
+}
+
+fn variant_from_json(value: &Value, currency: Option<&str>) -> ProductVariant {
+    let offer = main_offer(value);
+    let currency = currency_field(value)
+        .or_else(|| offer.and_then(currency_field))
+        .or_else(|| currency.map(str::to_string));
+
+    let mut attributes: HashMap<String, String> = value
+        .get("attributes")
+        .and_then(Value::as_object)
+        .map(|attributes| {
+            attributes
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), text_from_value(value)?)))
+                .collect()
+        })
+        .unwrap_or_default();
+    // schema.org puts the distinguishing properties directly on the variant
+    for key in ["color", "size", "material", "pattern"] {
+        if let Some(text) = value.get(key).and_then(text_from_value) {
+            attributes.entry(key.to_string()).or_insert(text);
+        }
+    }
+
+    ProductVariant {
+        name: text_field(value, &["name", "title"]),
+        sku: text_field(value, &["sku"]).or_else(|| offer.and_then(|o| text_field(o, &["sku"]))),
+        gtin: gtin_field(value).or_else(|| offer.and_then(gtin_field)),
+        price: price_field(value, offer, currency.as_deref()),
+        availability: first_field(value, &["availability"])
+            .or_else(|| offer.and_then(|offer| offer.get("availability")))
+            .and_then(availability_from_value),
+        attributes,
+    }
+}
+
+/// The price from a product-level field or its offer, with `currency` when the value
+/// doesn't name one
+fn price_field(value: &Value, offer: Option<&Value>, currency: Option<&str>) -> Option<Price> {
+    let price = first_field(value, &["price"])
+        .and_then(price_from_value)
+        .or_else(|| {
+            offer.and_then(|offer| {
+                first_field(offer, &["price", "lowPrice"])
+                    .and_then(price_from_value)
+                    .or_else(|| offer.get("priceSpecification").and_then(price_from_value))
+            })
+        });
+    price.map(|mut price| {
+        if price.currency.is_none() {
+            price.currency = currency.map(str::to_string);
+        }
+        price
+    })
+}
+
+fn price_from_value(value: &Value) -> Option<Price> {
+    match value {
+        Value::Number(number) => Some(Price {
+            amount: decimal_from_number(number)?,
+            currency: None,
+        }),
+        Value::String(text) => parse_price(text),
+        Value::Object(object) => {
+            let mut price = ["amount", "value", "price", "minPrice"]
+                .iter()
+                .find_map(|key| object.get(*key))
+                .and_then(price_from_value)?;
+            if let Some(currency) = currency_field(value) {
+                price.currency = Some(currency);
+            }
+            Some(price)
+        }
+        Value::Array(items) => items.iter().find_map(price_from_value),
+        _ => None,
+    }
+}
+
+fn rating_from_value(value: &Value) -> Option<Rating> {
+    match value {
+        Value::Number(number) => Some(Rating {
+            value: number.as_f64()?,
+            scale: None,
+            review_count: None,
+        }),
+        Value::String(text) => parse_rating(text),
+        Value::Object(object) => {
+            let mut rating = ["ratingValue", "value", "rating"]
+                .iter()
+                .find_map(|key| object.get(*key))
+                .and_then(rating_from_value)?;
+            if let Some(scale) = ["bestRating", "scale", "best"]
+                .iter()
+                .find_map(|key| object.get(*key))
+                .and_then(|scale| match scale {
+                    Value::String(text) => parse_decimal(text).map(to_f64),
+                    scale => scale.as_f64(),
+                })
+            {
+                rating.scale = Some(scale);
+            }
+            if let Some(count) = ["reviewCount", "review_count", "ratingCount"]
+                .iter()
+                .find_map(|key| object.get(*key))
+                .and_then(count_from_value)
+            {
+                rating.review_count = Some(count);
+            }
+            Some(rating)
+        }
+        _ => None,
+    }
+}
+
+fn availability_from_value(value: &Value) -> Option<Availability> {
+    match value {
+        Value::String(text) => parse_availability(text),
+        Value::Bool(true) => Some(Availability::InStock),
+        Value::Bool(false) => Some(Availability::OutOfStock),
+        _ => None,
+    }
+}
+
+/// Image URLs from a URL, a schema.org `ImageObject`, or a list of either, without duplicates
+pub fn image_urls(value: &Value) -> Vec<String> {
+    let mut images: Vec<String> = Vec::new();
+    for image in as_list(value) {
+        let url = match image {
+            Value::String(url) => Some(url.as_str()),
+            // schema.org ImageObject
+            image => ["url", "contentUrl"]
+                .iter()
+                .find_map(|key| image.get(*key))
+                .and_then(Value::as_str),
+        };
+        if let Some(url) = url.map(str::trim).filter(|url| !url.is_empty()) {
+            if !images.iter().any(|image| image == url) {
+                images.push(url.to_string());
+            }
+        }
+    }
+    images
+}
+
+fn count_from_value(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(number) => number
+            .as_u64()
+            .or_else(|| number.as_f64().map(|n| n as u64)),
+        Value::String(text) => parse_count(text),
+        _ => None,
+    }
+}
+
+/// A whole count, where commas and dots only ever separate thousands
+fn parse_count(text: &str) -> Option<u64> {
+    let (start, end) = number_spans(text).into_iter().next()?;
+    text[start..end]
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+/// Text of a string or number, or the `name` of an object (schema.org `Brand`, ...)
+fn text_from_value(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(text) => text.trim().to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::Object(_) => return value.get("name").and_then(text_from_value),
+        Value::Array(items) => return items.iter().find_map(text_from_value),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+fn text_field(value: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| value.get(*key))
+        .find_map(text_from_value)
+}
+
+fn currency_field(value: &Value) -> Option<String> {
+    // The field holds nothing but the currency, so any case is fine
+    text_field(value, &["currency", "priceCurrency"])
+        .and_then(|text| parse_currency(&text.to_ascii_uppercase()))
+}
+
+fn gtin_field(value: &Value) -> Option<String> {
+    text_field(
+        value,
+        &["gtin", "gtin13", "gtin12", "gtin14", "gtin8", "ean", "upc"],
+    )
+}
+
+fn first_field<'a>(value: &'a Value, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter()
+        .filter_map(|key| value.get(*key))
+        .find(|value| !value.is_null())
+}
+
+/// The product's offer: the first of `offers`, which may be one object or a list
+fn main_offer(value: &Value) -> Option<&Value> {
+    value
+        .get("offers")
+        .and_then(|offers| as_list(offers).into_iter().next())
+        .filter(|offer| offer.is_object())
+}
+
+fn as_list(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(items) => items.iter().collect(),
+        Value::Null => Vec::new(),
+        value => vec![value],
+    }
+}
+
+fn decimal_from_number(number: &serde_json::Number) -> Option<Decimal> {
+    // Going through the number's text keeps 19.99 exact instead of its f64 approximation
+    let text = number.to_string();
+    Decimal::from_str(&text)
+        .or_else(|_| Decimal::from_scientific(&text))
+        .ok()
+}
+
+fn to_f64(decimal: Decimal) -> f64 {
+    decimal.to_string().parse().unwrap_or_default()
+}
+
+/// Byte ranges of the numbers in `text`: digits with the separators between them
+fn number_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !c.is_ascii_digit() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, c)) = chars.peek() {
+            let separator = matches!(c, ',' | '.' | ' ' | '\u{a0}' | '\u{202f}')
+                && text[i + c.len_utf8()..]
+                    .chars()
+                    .next()
+                    .is_some_and(|next| next.is_ascii_digit())
+                // A space only separates thousands ("1 299"), not two numbers ("4.5 5")
+                && (!c.is_whitespace() || digit_group_follows(&text[i + c.len_utf8()..]));
+            if c.is_ascii_digit() || separator {
+                end = i + c.len_utf8();
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        spans.push((start, end));
+    }
+    spans
+}
+
+/// Whether `text` starts with exactly three digits
+fn digit_group_follows(text: &str) -> bool {
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    digits == 3
+}
+
+fn decimal_from_run(run: &str, dot_groups_thousands: bool) -> Option<Decimal> {
+    let run: String = run.chars().filter(|c| !c.is_whitespace()).collect();
+    let last_dot = run.rfind('.');
+    let last_comma = run.rfind(',');
+    let decimal_point = match (last_dot, last_comma) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(dot), None) => (run.matches('.').count() == 1
+            && !(dot_groups_thousands && run.len() - dot - 1 == 3))
+            .then_some('.'),
+        (None, Some(comma)) => {
+            (run.matches(',').count() == 1 && run.len() - comma - 1 != 3).then_some(',')
+        }
+        (None, None) => None,
+    };
+
+    let normalized: String = run
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_ascii_digit() => Some(c),
+            c if Some(c) == decimal_point => Some('.'),
+            _ => None,
+        })
+        .collect();
+    Decimal::from_str(&normalized).ok()
+}
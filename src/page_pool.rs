@@ -0,0 +1,324 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chromiumoxide::cdp::browser_protocol::network::DeleteCookiesParams;
+use chromiumoxide::page::Page;
+use serde::Serialize;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+const DEFAULT_MAX_PAGES: usize = 8;
+const DEFAULT_MAX_IDLE_PAGES: usize = 2;
+const DEFAULT_ACQUIRE_TIMEOUT_SECS: u64 = 30;
+
+/// A browser page that can be handed back to the pool and reused
+#[async_trait]
+pub trait PoolablePage: Send + Sync + 'static {
+    /// Wipe per-site state so the next user starts from a blank page
+    async fn reset(&self) -> Result<()>;
+
+    async fn close(self);
+}
+
+#[async_trait]
+impl PoolablePage for Page {
+    async fn reset(&self) -> Result<()> {
+        // Cookies belong to the whole browser context, so only those the page's own URLs
+        // can see are removed; other shared sessions keep cookies for other sites
+        let cookies = self
+            .get_cookies()
+            .await
+            .map_err(|e| anyhow!("Failed to read page cookies: {}", e))?;
+        if !cookies.is_empty() {
+            let cookies = cookies
+                .into_iter()
+                .map(|cookie| {
+                    let mut params = DeleteCookiesParams::new(cookie.name);
+                    params.domain = Some(cookie.domain);
+                    params.path = Some(cookie.path);
+                    params
+                })
+                .collect();
+            self.delete_cookies(cookies)
+                .await
+                .map_err(|e| anyhow!("Failed to clear page cookies: {}", e))?;
+        }
+
+        // Storage is per-origin, so clear it before leaving the page
+        self.evaluate(
+            "(() => { try { localStorage.clear(); sessionStorage.clear(); } catch (e) {} })()",
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to clear page storage: {}", e))?;
+        self.goto("about:blank")
+            .await
+            .map_err(|e| anyhow!("Failed to reset page: {}", e))?;
+        Ok(())
+    }
+
+    async fn close(self) {
+        if let Err(e) = Page::close(self).await {
+            warn!("Failed to close browser page: {}", e);
+        }
+    }
+}
+
+/// Raised when no page frees up within the acquire timeout
+#[derive(Debug, thiserror::Error)]
+#[error("Timed out after {0:?} waiting for a free browser page")]
+pub struct PoolTimeout(pub Duration);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagePoolConfig {
+    /// Pages that may be open at once, in use or idle
+    pub max_pages: usize,
+    /// Reset pages kept open for reuse
+    pub max_idle: usize,
+    /// How long `acquire` waits for a page before failing
+    pub acquire_timeout: Duration,
+}
+
+impl PagePoolConfig {
+    /// Configure from `BROWSER_MAX_PAGES` (default 8), `BROWSER_MAX_IDLE_PAGES` (default 2)
+    /// and `BROWSER_PAGE_TIMEOUT_SECS` (default 30)
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        Self {
+            max_pages: var("BROWSER_MAX_PAGES", DEFAULT_MAX_PAGES).max(1),
+            max_idle: var("BROWSER_MAX_IDLE_PAGES", DEFAULT_MAX_IDLE_PAGES),
+            acquire_timeout: Duration::from_secs(var(
+                "BROWSER_PAGE_TIMEOUT_SECS",
+                DEFAULT_ACQUIRE_TIMEOUT_SECS,
+            )),
+        }
+    }
+}
+
+impl Default for PagePoolConfig {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+/// Point-in-time pool counters, reported by `/health`
+#[derive(Debug, Clone, Serialize)]
+pub struct PagePoolStats {
+    pub max_pages: usize,
+    pub in_use: usize,
+    pub idle: usize,
+    pub waiting: usize,
+    pub created: u64,
+    pub reused: u64,
+    pub timeouts: u64,
+}
+
+/// Bounds the number of open browser pages and recycles released ones.
+///
+/// Callers beyond `max_pages` queue (fairly) until a page is released or the acquire timeout
+/// passes. Released pages are reset and kept for reuse up to `max_idle`; the rest are closed.
+/// Each page is tagged with the supervisor generation of the browser it lives in, so pages
+/// left over from a crashed browser are never handed out.
+pub struct PagePool<P: PoolablePage> {
+    config: PagePoolConfig,
+    permits: Arc<Semaphore>,
+    /// Reset pages with their browser generation
+    idle: Mutex<Vec<(u64, P)>>,
+    waiting: AtomicUsize,
+    created: AtomicU64,
+    reused: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl<P: PoolablePage> PagePool<P> {
+    pub fn new(config: PagePoolConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            permits: Arc::new(Semaphore::new(config.max_pages)),
+            idle: Mutex::new(Vec::new()),
+            waiting: AtomicUsize::new(0),
+            created: AtomicU64::new(0),
+            reused: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+        })
+    }
+
+    /// Take an idle page from browser `generation`, or open one with `create` when none is
+    /// idle. Idle pages from other generations belong to a dead browser and are closed.
+    pub async fn acquire<F, Fut>(
+        self: &Arc<Self>,
+        generation: u64,
+        create: F,
+    ) -> Result<PooledPage<P>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<P>>,
+    {
+        let permit = self.permit().await?;
+
+        let (reusable, stale) = {
+            let mut idle = self.idle.lock().unwrap();
+            let (current, stale): (Vec<_>, Vec<_>) = std::mem::take(&mut *idle)
+                .into_iter()
+                .partition(|(page_generation, _)| *page_generation == generation);
+            *idle = current;
+            (idle.pop().map(|(_, page)| page), stale)
+        };
+        for (_, page) in stale {
+            page.close().await;
+        }
+
+        let page = match reusable {
+            Some(page) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                page
+            }
+            None => {
+                let page = create().await?;
+                self.created.fetch_add(1, Ordering::Relaxed);
+                page
+            }
+        };
+
+        Ok(PooledPage {
+            page: Some(page),
+            generation,
+            permit: Some(permit),
+            pool: self.clone(),
+        })
+    }
+
+    /// Reserve room for a page the caller opens and closes itself, such as one in its own
+    /// browser context. Counts against `max_pages` until the slot is dropped.
+    pub async fn reserve(&self) -> Result<PageSlot> {
+        let permit = self.permit().await?;
+        self.created.fetch_add(1, Ordering::Relaxed);
+        Ok(PageSlot { _permit: permit })
+    }
+
+    /// Close every idle page
+    pub async fn clear_idle(&self) {
+        let idle = std::mem::take(&mut *self.idle.lock().unwrap());
+        for (_, page) in idle {
+            page.close().await;
+        }
+    }
+
+    pub fn stats(&self) -> PagePoolStats {
+        let idle = self.idle.lock().unwrap().len();
+        let free = self.permits.available_permits();
+        PagePoolStats {
+            max_pages: self.config.max_pages,
+            in_use: self.config.max_pages - free,
+            idle,
+            waiting: self.waiting.load(Ordering::Relaxed),
+            created: self.created.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn permit(&self) -> Result<OwnedSemaphorePermit> {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = tokio::time::timeout(
+            self.config.acquire_timeout,
+            self.permits.clone().acquire_owned(),
+        )
+        .await;
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+
+        match permit {
+            Ok(permit) => Ok(permit.expect("the pool semaphore is never closed")),
+            Err(_) => {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+                Err(PoolTimeout(self.config.acquire_timeout).into())
+            }
+        }
+    }
+
+    async fn release(&self, page: P, generation: u64) {
+        let has_room = self.idle.lock().unwrap().len() < self.config.max_idle;
+        if !has_room {
+            page.close().await;
+            return;
+        }
+
+        match page.reset().await {
+            Ok(()) => {
+                let mut idle = self.idle.lock().unwrap();
+                if idle.len() < self.config.max_idle {
+                    idle.push((generation, page));
+                    return;
+                }
+            }
+            Err(e) => warn!("Closing browser page that failed to reset: {}", e),
+        }
+        page.close().await;
+    }
+}
+
+/// A page checked out of the pool. Dropping it resets the page and returns it to the pool,
+/// or closes it.
+pub struct PooledPage<P: PoolablePage> {
+    page: Option<P>,
+    /// Supervisor generation of the browser the page lives in
+    generation: u64,
+    // Released only after the page is back in the pool or closed
+    permit: Option<OwnedSemaphorePermit>,
+    pool: Arc<PagePool<P>>,
+}
+
+impl<P: PoolablePage> Deref for PooledPage<P> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        self.page.as_ref().expect("page is present until drop")
+    }
+}
+
+impl<P: PoolablePage> DerefMut for PooledPage<P> {
+    fn deref_mut(&mut self) -> &mut P {
+        self.page.as_mut().expect("page is present until drop")
+    }
+}
+
+impl<P: PoolablePage> Drop for PooledPage<P> {
+    fn drop(&mut self) {
+        let (Some(page), Some(permit)) = (self.page.take(), self.permit.take()) else {
+            return;
+        };
+        let pool = self.pool.clone();
+        let generation = self.generation;
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    pool.release(page, generation).await;
+                    drop(permit);
+                });
+            }
+            // Without a runtime the page can't be closed cleanly; the browser reclaims it
+            Err(_) => info!("Dropping browser page outside the runtime"),
+        }
+    }
+}
+
+/// Room for one page outside the pool's reuse, held until dropped
+pub struct PageSlot {
+    _permit: OwnedSemaphorePermit,
+}
+
+/// The pool of pages in the shared browser
+pub fn browser_page_pool() -> &'static Arc<PagePool<Page>> {
+    static POOL: OnceLock<Arc<PagePool<Page>>> = OnceLock::new();
+    POOL.get_or_init(|| PagePool::new(PagePoolConfig::from_env()))
+}
@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Result};
+use chromiumoxide::cdp::browser_protocol::network::{
+    EventLoadingFailed, EventLoadingFinished, EventRequestWillBeSent,
+};
+use chromiumoxide::cdp::browser_protocol::page::EventFrameNavigated;
+use chromiumoxide::page::Page;
+use futures::{stream, StreamExt};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::browser::NavigationError;
+use crate::types::{WaitCondition, WaitOutcome, WaitUntil};
+
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Network requests a page has started but not finished
+#[derive(Debug)]
+pub struct InFlightRequests {
+    pending: HashSet<String>,
+    last_change: Instant,
+}
+
+impl InFlightRequests {
+    pub fn new() -> Self {
+        Self {
+            pending: HashSet::new(),
+            last_change: Instant::now(),
+        }
+    }
+
+    pub fn started(&mut self, request_id: &str) {
+        // Redirects reuse the request ID, so a repeat is still one request
+        self.pending.insert(request_id.to_string());
+        self.last_change = Instant::now();
+    }
+
+    pub fn finished(&mut self, request_id: &str) {
+        if self.pending.remove(request_id) {
+            self.last_change = Instant::now();
+        }
+    }
+
+    /// How long nothing has been in flight as of `now`; `None` while requests are pending
+    pub fn idle_for(&self, now: Instant) -> Option<Duration> {
+        self.pending
+            .is_empty()
+            .then(|| now.saturating_duration_since(self.last_change))
+    }
+}
+
+impl Default for InFlightRequests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Follows a page's network events. Start it before navigating so every request is seen.
+pub struct NetworkTracker {
+    requests: Arc<Mutex<InFlightRequests>>,
+    task: JoinHandle<()>,
+}
+
+impl NetworkTracker {
+    pub async fn start(page: &Page) -> Result<Self> {
+        let listen_error = |e| anyhow!("Failed to listen for network events: {}", e);
+        let sent = page
+            .event_listener::<EventRequestWillBeSent>()
+            .await
+            .map_err(listen_error)?;
+        let finished = page
+            .event_listener::<EventLoadingFinished>()
+            .await
+            .map_err(listen_error)?;
+        let failed = page
+            .event_listener::<EventLoadingFailed>()
+            .await
+            .map_err(listen_error)?;
+
+        // (request ID, whether it started)
+        let mut events = stream::select(
+            sent.map(|event| (event.request_id.inner().clone(), true)),
+            stream::select(
+                finished.map(|event| (event.request_id.inner().clone(), false)),
+                failed.map(|event| (event.request_id.inner().clone(), false)),
+            ),
+        );
+
+        let requests = Arc::new(Mutex::new(InFlightRequests::new()));
+        let task = tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Some((request_id, started)) = events.next().await {
+                    let mut requests = requests.lock().unwrap();
+                    if started {
+                        requests.started(&request_id);
+                    } else {
+                        requests.finished(&request_id);
+                    }
+                }
+            }
+        });
+
+        Ok(Self { requests, task })
+    }
+
+    async fn wait_idle(&self, idle: Duration) {
+        loop {
+            let idle_for = self.requests.lock().unwrap().idle_for(Instant::now());
+            if idle_for.is_some_and(|idle_for| idle_for >= idle) {
+                return;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+impl Drop for NetworkTracker {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Navigate to `url` and wait until `wait` holds or its timeout passes.
+///
+/// chromiumoxide only answers `Page.navigate` once the page's `load` event has fired, so
+/// the navigation runs alongside the wait instead of before it: conditions are checked as
+/// soon as the new document has replaced the old one, and can be met before `load`. A
+/// timeout before the new document commits is a `NavigationError::Timeout`; after that it
+/// is reported as an unsatisfied outcome.
+pub async fn navigate_and_wait(page: &Page, url: &str, wait: &WaitUntil) -> Result<WaitOutcome> {
+    let started = Instant::now();
+    let timeout = wait
+        .timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_WAIT_TIMEOUT);
+
+    // Both have to be followed from before the navigation starts
+    let network = match wait.condition {
+        WaitCondition::NetworkIdle { .. } => Some(NetworkTracker::start(page).await?),
+        _ => None,
+    };
+    let mut navigated = page
+        .event_listener::<EventFrameNavigated>()
+        .await
+        .map_err(|e| anyhow!("Failed to listen for navigation events: {}", e))?;
+
+    let committed = AtomicBool::new(false);
+    let condition = async {
+        // The old document would answer for the new one until the main frame commits
+        loop {
+            let Some(event) = navigated.next().await else {
+                return Err(anyhow!("Page closed during navigation"));
+            };
+            if event.frame.parent_id.is_some() {
+                continue;
+            }
+            if let Some(unreachable) = &event.frame.unreachable_url {
+                return Err(navigation_failed(
+                    url,
+                    format!("{} could not be reached", unreachable),
+                ));
+            }
+            break;
+        }
+        committed.store(true, Ordering::Relaxed);
+        wait_for_condition(page, &wait.condition, network.as_ref()).await;
+        Ok(())
+    };
+    let navigation = page.goto(url);
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(condition, navigation, deadline);
+
+    let mut loaded = false;
+    let satisfied = loop {
+        tokio::select! {
+            result = &mut navigation, if !loaded => match result {
+                Ok(_) => loaded = true,
+                Err(e) => return Err(navigation_failed(url, e.to_string())),
+            },
+            result = &mut condition => {
+                result?;
+                break true;
+            }
+            _ = &mut deadline => break false,
+        }
+    };
+
+    if !satisfied && !loaded && !committed.load(Ordering::Relaxed) {
+        return Err(NavigationError::Timeout {
+            url: url.to_string(),
+            timeout_ms: timeout.as_millis() as u64,
+        }
+        .into());
+    }
+
+    Ok(WaitOutcome {
+        condition: wait.condition.name().to_string(),
+        satisfied,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+fn navigation_failed(url: &str, reason: String) -> anyhow::Error {
+    NavigationError::Failed {
+        url: url.to_string(),
+        reason,
+    }
+    .into()
+}
+
+/// Return once `condition` holds on the current document. `network` is needed for
+/// `network_idle`, which never holds without it.
+async fn wait_for_condition(
+    page: &Page,
+    condition: &WaitCondition,
+    network: Option<&NetworkTracker>,
+) {
+    match condition {
+        WaitCondition::Load => poll_script(page, "document.readyState === 'complete'").await,
+        WaitCondition::DomContentLoaded => {
+            poll_script(page, "document.readyState !== 'loading'").await
+        }
+        WaitCondition::NetworkIdle { idle_ms } => match network {
+            Some(network) => network.wait_idle(Duration::from_millis(*idle_ms)).await,
+            None => std::future::pending().await,
+        },
+        WaitCondition::Selector { selector } => {
+            while page.find_element(selector.as_str()).await.is_err() {
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+        WaitCondition::Predicate { script } => {
+            poll_script(page, &format!("Boolean({})", script)).await
+        }
+    }
+}
+
+/// Evaluate `expression` until it returns true. Evaluation errors, such as the page's
+/// context being replaced mid-navigation, count as not yet.
+async fn poll_script(page: &Page, expression: &str) {
+    loop {
+        let done = page
+            .evaluate(expression)
+            .await
+            .ok()
+            .and_then(|result| result.into_value::<bool>().ok())
+            .unwrap_or(false);
+        if done {
+            return;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
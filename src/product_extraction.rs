@@ -0,0 +1,502 @@
+//! Product extraction that reads the page's own data first and asks the LLM only for the
+//! fields that are still missing
+//!
+//! Stages run in order of how much their values can be trusted: structured data (JSON-LD,
+//! microdata, RDFa, product OpenGraph), site-specific selectors, other OpenGraph tags, generic
+//! selectors, then the LLM. A stage only fills fields no earlier stage found, and every field
+//! records where it came from.
+
+use anyhow::Result;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::llama_client::LlamaClient;
+use crate::normalize;
+use crate::structured_data::{element_text, StructuredData, StructuredDataFormat};
+use crate::types::{FieldProvenance, FieldSource, ProductInfo};
+
+const JSON_LD_CONFIDENCE: f64 = 0.95;
+const MICRODATA_CONFIDENCE: f64 = 0.9;
+const RDFA_CONFIDENCE: f64 = 0.9;
+const OPEN_GRAPH_PRODUCT_CONFIDENCE: f64 = 0.8;
+// Without `og:type` saying product, the title may well be the site's rather than the product's
+const OPEN_GRAPH_CONFIDENCE: f64 = 0.6;
+const SITE_SELECTOR_CONFIDENCE: f64 = 0.75;
+const GENERIC_SELECTOR_CONFIDENCE: f64 = 0.5;
+const LLM_CONFIDENCE: f64 = 0.6;
+
+/// Fields worth an LLM call when the page's own data lacks them
+const LLM_FIELDS: &[&str] = &[
+    "name",
+    "price",
+    "availability",
+    "brand",
+    "description",
+    "rating",
+    "images",
+];
+
+/// Raised when no stage found any product data on the page
+#[derive(Debug, thiserror::Error)]
+#[error("No product information found on {url}")]
+pub struct NoProductData {
+    pub url: String,
+}
+
+/// A product field and the CSS selectors to try for it, in order
+pub type FieldSelectors = (&'static str, &'static [&'static str]);
+
+/// Selectors common across shops. The `extract_product_data` tool uses them too.
+pub const GENERIC_SELECTORS: &[FieldSelectors] = &[
+    (
+        "name",
+        &["#productTitle", "h1.a-size-large", ".product-title"],
+    ),
+    (
+        "price",
+        &[
+            "[data-testid='price']",
+            ".a-price-whole",
+            ".price",
+            ".current-price",
+            "[data-price]",
+        ],
+    ),
+    (
+        "description",
+        &[
+            "[data-feature-name='productDescription']",
+            ".product-description",
+            "#description",
+        ],
+    ),
+    (
+        "availability",
+        &["#availability span", ".availability", "#stock-status"],
+    ),
+    ("brand", &["[data-testid='brand']", ".brand", "#brand"]),
+    (
+        "rating",
+        &[
+            "[data-testid='rating']",
+            ".a-icon-alt",
+            ".rating",
+            ".star-rating",
+        ],
+    ),
+    (
+        "image",
+        &[
+            "[data-testid='image']",
+            "#landingImage",
+            ".product-image img",
+            ".main-image img",
+        ],
+    ),
+];
+
+/// Selectors for one shop or e-commerce platform
+struct SiteProfile {
+    name: &'static str,
+    /// Applies to hosts containing any of these
+    hosts: &'static [&'static str],
+    /// Or to pages with an element matching any of these
+    markers: &'static [&'static str],
+    selectors: &'static [FieldSelectors],
+}
+
+const SITE_PROFILES: &[SiteProfile] = &[
+    SiteProfile {
+        name: "amazon",
+        hosts: &["amazon."],
+        markers: &["#productTitle"],
+        selectors: &[
+            ("name", &["#productTitle"]),
+            (
+                "price",
+                &[
+                    "#corePrice_feature_div .a-offscreen",
+                    "#corePriceDisplay_desktop_feature_div .a-offscreen",
+                    "#priceblock_dealprice",
+                    "#priceblock_ourprice",
+                    ".a-price .a-offscreen",
+                ],
+            ),
+            ("availability", &["#availability span", "#availability"]),
+            ("brand", &["#bylineInfo"]),
+            ("rating", &["#acrPopover .a-icon-alt"]),
+            ("review_count", &["#acrCustomerReviewText"]),
+            ("description", &["#productDescription", "#feature-bullets"]),
+            ("image", &["#landingImage", "#imgBlkFront"]),
+            ("sku", &["input#ASIN"]),
+        ],
+    },
+    SiteProfile {
+        name: "shopify",
+        hosts: &["myshopify.com"],
+        markers: &[
+            "script[src*='cdn.shopify.com']",
+            "link[href*='cdn.shopify.com']",
+        ],
+        selectors: &[
+            (
+                "name",
+                &[
+                    ".product__title h1",
+                    ".product-single__title",
+                    ".product__title",
+                ],
+            ),
+            (
+                "price",
+                &[
+                    ".price__sale .price-item--sale",
+                    ".price-item--regular",
+                    ".product__price",
+                    "[data-product-price]",
+                ],
+            ),
+            (
+                "description",
+                &[".product__description", ".product-single__description"],
+            ),
+            ("sku", &[".product__sku", "[data-sku]"]),
+            (
+                "image",
+                &[".product__media img", ".product-single__photo img"],
+            ),
+        ],
+    },
+    SiteProfile {
+        name: "woocommerce",
+        hosts: &[],
+        markers: &["body.woocommerce", ".woocommerce-product-gallery"],
+        selectors: &[
+            ("name", &[".product_title"]),
+            (
+                "price",
+                &[
+                    ".summary .price ins .woocommerce-Price-amount",
+                    ".summary .price .woocommerce-Price-amount",
+                ],
+            ),
+            ("availability", &[".summary .stock"]),
+            ("sku", &[".sku"]),
+            (
+                "description",
+                &[".woocommerce-product-details__short-description"],
+            ),
+            ("rating", &[".woocommerce-product-rating .star-rating"]),
+            ("image", &[".woocommerce-product-gallery__image img"]),
+        ],
+    },
+    SiteProfile {
+        name: "magento",
+        hosts: &[],
+        markers: &[".product-info-main"],
+        selectors: &[
+            ("name", &[".page-title .base"]),
+            (
+                "price",
+                &[
+                    ".product-info-price [data-price-type='finalPrice'] .price",
+                    ".product-info-price .price",
+                ],
+            ),
+            ("availability", &[".product-info-stock-sku .stock"]),
+            ("sku", &[".product.attribute.sku .value"]),
+            ("description", &[".product.attribute.description .value"]),
+            ("image", &[".gallery-placeholder img"]),
+        ],
+    },
+];
+
+/// Extracts product information, using the LLM (when there is one) only for what the
+/// page's own data doesn't provide
+pub struct ProductExtractor {
+    llm: Option<Arc<LlamaClient>>,
+}
+
+impl ProductExtractor {
+    pub fn new(llm: Arc<LlamaClient>) -> Self {
+        Self { llm: Some(llm) }
+    }
+
+    /// Only the deterministic stages; whatever they miss stays empty
+    pub fn without_llm() -> Self {
+        Self { llm: None }
+    }
+
+    pub async fn extract(&self, url: &str, html_content: &str) -> Result<ProductInfo> {
+        let mut product = extract_from_page(url, html_content);
+
+        let missing: Vec<&str> = LLM_FIELDS
+            .iter()
+            .copied()
+            .filter(|field| !product.sources.contains_key(*field))
+            .collect();
+        let Some(llm) = self.llm.as_ref().filter(|_| !missing.is_empty()) else {
+            return found_or_error(product, url);
+        };
+
+        info!(
+            "Asking the LLM for product fields missing from the page data: {}",
+            missing.join(", ")
+        );
+        match llm
+            .extract_product_fields(url, html_content, &missing)
+            .await
+        {
+            Ok(Some(mut answer)) => {
+                product.raw_llm_response = answer.raw_llm_response.take();
+                merge_missing(
+                    &mut product,
+                    answer,
+                    provenance(FieldSource::Llm, LLM_CONFIDENCE),
+                );
+            }
+            Ok(None) => warn!("The LLM gave no product answer for {}", url),
+            // The page's own data is still worth returning
+            Err(e) if !product.sources.is_empty() => {
+                warn!(
+                    "LLM extraction failed for {}, using page data only: {}",
+                    url, e
+                )
+            }
+            Err(e) => return Err(e),
+        }
+
+        found_or_error(product, url)
+    }
+}
+
+fn found_or_error(product: ProductInfo, url: &str) -> Result<ProductInfo> {
+    if product.sources.is_empty() {
+        return Err(NoProductData {
+            url: url.to_string(),
+        }
+        .into());
+    }
+    Ok(product)
+}
+
+/// Run the deterministic stages: structured data, then site-specific and generic selectors
+pub fn extract_from_page(url: &str, html_content: &str) -> ProductInfo {
+    let document = Html::parse_document(html_content);
+    let mut product = ProductInfo::default();
+
+    let mut stages: Vec<Option<(ProductInfo, FieldProvenance)>> =
+        structured_data_products(&document)
+            .into_iter()
+            .map(Some)
+            .collect();
+    for profile in SITE_PROFILES {
+        if profile_applies(profile, url, &document) {
+            info!("Using {} selectors for {}", profile.name, url);
+            stages.push(selector_product(&document, profile.selectors).map(|found| {
+                (
+                    found,
+                    provenance(FieldSource::Selector, SITE_SELECTOR_CONFIDENCE),
+                )
+            }));
+        }
+    }
+    stages.push(selector_product(&document, GENERIC_SELECTORS).map(|found| {
+        (
+            found,
+            provenance(FieldSource::Selector, GENERIC_SELECTOR_CONFIDENCE),
+        )
+    }));
+
+    // OpenGraph without a product type is less trustworthy than site selectors; the sort is
+    // stable, so equally trusted stages keep their order
+    let mut stages: Vec<_> = stages.into_iter().flatten().collect();
+    stages.sort_by(|(_, a), (_, b)| b.confidence.total_cmp(&a.confidence));
+    for (found, provenance) in stages {
+        merge_missing(&mut product, found, provenance);
+    }
+
+    // Pages often link images relative to themselves
+    if let Ok(base) = Url::parse(url) {
+        for image in &mut product.images {
+            if let Ok(resolved) = base.join(image) {
+                *image = resolved.to_string();
+            }
+        }
+    }
+    product
+}
+
+fn provenance(source: FieldSource, confidence: f64) -> FieldProvenance {
+    FieldProvenance { source, confidence }
+}
+
+/// Fill the fields of `product` that are still empty from `found`
+fn merge_missing(product: &mut ProductInfo, found: ProductInfo, provenance: FieldProvenance) {
+    let sources = &mut product.sources;
+    fill(sources, "name", &mut product.name, found.name, provenance);
+    fill(
+        sources,
+        "description",
+        &mut product.description,
+        found.description,
+        provenance,
+    );
+    fill(
+        sources,
+        "price",
+        &mut product.price,
+        found.price,
+        provenance,
+    );
+    fill(
+        sources,
+        "availability",
+        &mut product.availability,
+        found.availability,
+        provenance,
+    );
+    fill(
+        sources,
+        "brand",
+        &mut product.brand,
+        found.brand,
+        provenance,
+    );
+    fill(
+        sources,
+        "rating",
+        &mut product.rating,
+        found.rating,
+        provenance,
+    );
+    fill(sources, "sku", &mut product.sku, found.sku, provenance);
+    fill(sources, "gtin", &mut product.gtin, found.gtin, provenance);
+    fill(sources, "mpn", &mut product.mpn, found.mpn, provenance);
+    if product.images.is_empty() && !found.images.is_empty() {
+        product.images = found.images;
+        sources.insert("images".to_string(), provenance);
+    }
+    if product.variants.is_empty() && !found.variants.is_empty() {
+        product.variants = found.variants;
+        sources.insert("variants".to_string(), provenance);
+    }
+}
+
+fn fill<T>(
+    sources: &mut BTreeMap<String, FieldProvenance>,
+    field: &str,
+    value: &mut Option<T>,
+    found: Option<T>,
+    provenance: FieldProvenance,
+) {
+    if value.is_none() && found.is_some() {
+        *value = found;
+        sources.insert(field.to_string(), provenance);
+    }
+}
+
+/// The first product in each structured data format, then the OpenGraph tags whatever
+/// their type, each with the provenance of its format
+fn structured_data_products(document: &Html) -> Vec<(ProductInfo, FieldProvenance)> {
+    let data = StructuredData::from_document(document);
+    let products = data.products();
+
+    let mut found = Vec::new();
+    for (format, source, confidence) in [
+        (
+            StructuredDataFormat::JsonLd,
+            FieldSource::JsonLd,
+            JSON_LD_CONFIDENCE,
+        ),
+        (
+            StructuredDataFormat::Microdata,
+            FieldSource::Microdata,
+            MICRODATA_CONFIDENCE,
+        ),
+        (
+            StructuredDataFormat::Rdfa,
+            FieldSource::Rdfa,
+            RDFA_CONFIDENCE,
+        ),
+    ] {
+        if let Some(entity) = products.iter().find(|entity| entity.format == format) {
+            found.push((
+                normalize::product_from_json(&entity.to_json()),
+                provenance(source, confidence),
+            ));
+        }
+    }
+
+    let open_graph = data
+        .entities
+        .iter()
+        .find(|entity| entity.format == StructuredDataFormat::OpenGraph);
+    if let Some(entity) = open_graph {
+        let confidence = if entity.is_product() {
+            OPEN_GRAPH_PRODUCT_CONFIDENCE
+        } else {
+            OPEN_GRAPH_CONFIDENCE
+        };
+        found.push((
+            normalize::product_from_json(&entity.to_json()),
+            provenance(FieldSource::OpenGraph, confidence),
+        ));
+    }
+    found
+}
+
+fn profile_applies(profile: &SiteProfile, url: &str, document: &Html) -> bool {
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .unwrap_or_default();
+    profile.hosts.iter().any(|pattern| host.contains(pattern))
+        || profile.markers.iter().any(|marker| {
+            Selector::parse(marker)
+                .is_ok_and(|selector| document.select(&selector).next().is_some())
+        })
+}
+
+/// The first non-empty match of each field's selectors, normalized like any other product JSON
+fn selector_product(document: &Html, selectors: &[FieldSelectors]) -> Option<ProductInfo> {
+    let mut fields = Map::new();
+    for (field, candidates) in selectors {
+        let value = candidates
+            .iter()
+            .filter_map(|candidate| Selector::parse(candidate).ok())
+            .find_map(|selector| {
+                document
+                    .select(&selector)
+                    .find_map(|element| selector_value(element, field))
+            });
+        if let Some(value) = value {
+            fields.insert(field.to_string(), Value::String(value));
+        }
+    }
+    (!fields.is_empty()).then(|| normalize::product_from_json(&Value::Object(fields)))
+}
+
+fn selector_value(element: ElementRef, field: &str) -> Option<String> {
+    let node = element.value();
+    let value = if field == "image" {
+        // Lazy-loading and zoom images keep the real URL in a data attribute
+        ["data-old-hires", "data-src", "src", "content", "href"]
+            .iter()
+            .find_map(|attribute| node.attr(attribute))
+            .map(str::to_string)
+    } else if node.name() == "input" {
+        node.attr("value").map(str::to_string)
+    } else if let Some(content) = node.attr("content") {
+        Some(content.to_string())
+    } else {
+        Some(element_text(element))
+    };
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
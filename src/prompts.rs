@@ -0,0 +1,371 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// A named argument a prompt template accepts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// One message of a template. `text` may reference arguments as `{{name}}`, and wrap text
+/// that only applies when an optional argument is given in `{{#name}}...{{/name}}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptMessageTemplate {
+    pub role: String,
+    pub text: String,
+}
+
+/// A reusable prompt, served over MCP `prompts/*` and used by the built-in LLM client.
+///
+/// Templates are also loaded from JSON files of this shape in `PROMPTS_DIR`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+    /// Instructions for the model. MCP has no system role, so clients receive this as a
+    /// leading user message.
+    #[serde(default)]
+    pub system: Option<String>,
+    pub messages: Vec<PromptMessageTemplate>,
+}
+
+/// A template with its arguments filled in
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedPrompt {
+    pub system: Option<String>,
+    /// `(role, text)` pairs
+    pub messages: Vec<(String, String)>,
+}
+
+impl PromptTemplate {
+    /// Fill in `{{name}}` placeholders. Missing optional arguments render as empty strings, and
+    /// drop their `{{#name}}...{{/name}}` sections.
+    pub fn render(&self, arguments: &Map<String, Value>) -> Result<RenderedPrompt, String> {
+        let mut values = Vec::new();
+        for argument in &self.arguments {
+            let value = match arguments.get(&argument.name) {
+                Some(Value::String(value)) => value.clone(),
+                Some(Value::Null) | None if argument.required => {
+                    return Err(format!(
+                        "Missing required argument '{}' for prompt '{}'",
+                        argument.name, self.name
+                    ));
+                }
+                Some(Value::Null) | None => String::new(),
+                Some(other) => other.to_string(),
+            };
+            values.push((argument.name.as_str(), value));
+        }
+
+        let fill = |text: &str| {
+            values.iter().fold(text.to_string(), |text, (name, value)| {
+                fill_sections(&text, name, !value.is_empty())
+                    .replace(&format!("{{{{{}}}}}", name), value)
+            })
+        };
+
+        Ok(RenderedPrompt {
+            system: self.system.as_deref().map(fill),
+            messages: self
+                .messages
+                .iter()
+                .map(|message| (message.role.clone(), fill(&message.text)))
+                .collect(),
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("prompt name must not be empty".to_string());
+        }
+        if self.messages.is_empty() {
+            return Err(format!("prompt '{}' has no messages", self.name));
+        }
+        for message in &self.messages {
+            if message.role != "user" && message.role != "assistant" {
+                return Err(format!(
+                    "prompt '{}' has a message with role '{}'; use 'user' or 'assistant'",
+                    self.name, message.role
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keep the text of each `{{#name}}...{{/name}}` section when the argument is `present`,
+/// otherwise remove the section
+fn fill_sections(text: &str, name: &str, present: bool) -> String {
+    let open = format!("{{{{#{}}}}}", name);
+    let close = format!("{{{{/{}}}}}", name);
+    let mut text = text.to_string();
+    while let Some(start) = text.find(&open) {
+        let Some(end) = text[start..].find(&close).map(|end| start + end) else {
+            break;
+        };
+        let section = if present {
+            text[start + open.len()..end].to_string()
+        } else {
+            String::new()
+        };
+        text.replace_range(start..end + close.len(), &section);
+    }
+    text
+}
+
+/// The prompt templates known to this server, by name
+#[derive(Debug, Clone)]
+pub struct PromptLibrary {
+    prompts: BTreeMap<String, PromptTemplate>,
+}
+
+impl PromptLibrary {
+    /// Only the built-in templates
+    pub fn builtin() -> Self {
+        let mut library = Self {
+            prompts: BTreeMap::new(),
+        };
+        for prompt in builtin_prompts() {
+            library.insert(prompt);
+        }
+        library
+    }
+
+    /// Built-in templates plus any in `PROMPTS_DIR`. A directory that fails to load is
+    /// logged and ignored.
+    pub fn from_env() -> Self {
+        let mut library = Self::builtin();
+        if let Ok(dir) = std::env::var("PROMPTS_DIR") {
+            match library.load_dir(&dir) {
+                Ok(count) => info!("Loaded {} prompt templates from {}", count, dir),
+                Err(e) => warn!("Failed to load prompt templates from {}: {}", dir, e),
+            }
+        }
+        library
+    }
+
+    /// Load every `*.json` file in `dir`, each holding one template or an array of them.
+    /// A template with the same name as an existing one replaces it.
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> anyhow::Result<usize> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir.as_ref())?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+
+        let mut loaded = Vec::new();
+        for path in paths {
+            let text = std::fs::read_to_string(&path)?;
+            let value: Value = serde_json::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+            let templates: Vec<PromptTemplate> = match value {
+                Value::Array(_) => serde_json::from_value(value),
+                _ => serde_json::from_value(value).map(|template| vec![template]),
+            }
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+
+            for template in templates {
+                template
+                    .validate()
+                    .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+                loaded.push(template);
+            }
+        }
+
+        // Only touch the library once every file has parsed
+        let count = loaded.len();
+        for template in loaded {
+            if self.prompts.contains_key(&template.name) {
+                info!("Prompt template '{}' overrides the built-in", template.name);
+            }
+            self.insert(template);
+        }
+        Ok(count)
+    }
+
+    pub fn insert(&mut self, template: PromptTemplate) {
+        self.prompts.insert(template.name.clone(), template);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PromptTemplate> {
+        self.prompts.get(name)
+    }
+
+    /// Templates sorted by name
+    pub fn list(&self) -> Vec<&PromptTemplate> {
+        self.prompts.values().collect()
+    }
+
+    pub fn render(
+        &self,
+        name: &str,
+        arguments: &Map<String, Value>,
+    ) -> Result<RenderedPrompt, String> {
+        self.get(name)
+            .ok_or_else(|| format!("Unknown prompt: {}", name))?
+            .render(arguments)
+    }
+}
+
+impl Default for PromptLibrary {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// Build an argument map for `render` from string pairs
+pub fn prompt_arguments<'a>(
+    pairs: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Map<String, Value> {
+    pairs
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), json!(value)))
+        .collect()
+}
+
+pub const PRODUCT_EXTRACTION_PROMPT: &str = "product_extraction";
+
+fn argument(name: &str, description: &str, required: bool) -> PromptArgument {
+    PromptArgument {
+        name: name.to_string(),
+        description: description.to_string(),
+        required,
+    }
+}
+
+fn user(text: &str) -> PromptMessageTemplate {
+    PromptMessageTemplate {
+        role: "user".to_string(),
+        text: text.to_string(),
+    }
+}
+
+fn builtin_prompts() -> Vec<PromptTemplate> {
+    let url = || argument("url", "URL of the page", true);
+    let content_ref = || {
+        argument(
+            "content_ref",
+            "content_ref of the stored page, passed to the HTML tools",
+            true,
+        )
+    };
+
+    vec![
+        PromptTemplate {
+            name: PRODUCT_EXTRACTION_PROMPT.to_string(),
+            description: "Extract structured product information from an e-commerce page"
+                .to_string(),
+            arguments: vec![url(), content_ref()],
+            system: Some(
+                "You are an expert web scraping assistant with access to specialized HTML parsing tools. \
+Your job is to extract product information from e-commerce websites using the available tools.
+
+Available tools:
+- analyze_page_structure: Identifies the type of e-commerce platform and suggests extraction strategies
+- extract_product_data: Uses CSS selectors and the page's structured data to extract product information
+- extract_structured_data: Lists the schema.org entities in the page's JSON-LD, microdata, RDFa and OpenGraph tags
+- extract_clean_text: Removes clutter and extracts clean, readable content
+- extract_by_selectors: Extract specific data using custom CSS selectors
+
+Best practices:
+1. Always start by analyzing the page structure to understand the website type
+2. Use extract_product_data for comprehensive product extraction
+3. If extract_product_data doesn't work well, use extract_by_selectors with specific selectors
+4. Focus on extracting: name, price and currency, description, availability, brand, rating and \
+review count, image URLs, SKU/GTIN/MPN and variants
+5. Return results as one JSON object, e.g. {\"name\": ..., \"price\": \"$19.99\", \"currency\": \"USD\", \
+\"availability\": \"in stock\", \"rating\": \"4.5 out of 5\", \"review_count\": 120, \"images\": [...], \
+\"sku\": ..., \"gtin\": ..., \"mpn\": ..., \"variants\": [{\"name\", \"sku\", \"price\", \"availability\", \
+\"attributes\"}]}. Copy values as the page shows them; they are normalized afterwards
+
+Work step by step and use the most appropriate tools for each task."
+                    .to_string(),
+            ),
+            messages: vec![user(
+                "I need to extract product information from this web page. The URL is: {{url}}
+
+The page is stored with content_ref \"{{content_ref}}\" and is passed to every tool automatically. \
+Please use the appropriate tools to:
+1. First analyze the page structure to understand what kind of site this is
+2. Extract clean, structured product data
+3. Return the product information in a clear format
+
+Start by analyzing the page structure.",
+            )],
+        },
+        PromptTemplate {
+            name: "article_summary".to_string(),
+            description: "Summarize the main content of an article or blog post".to_string(),
+            arguments: vec![
+                url(),
+                content_ref(),
+                argument("max_words", "Rough length limit for the summary", false),
+            ],
+            system: Some(
+                "You summarize web articles. Use extract_clean_text to read the page without \
+navigation, ads and other clutter, then summarize only what the article itself says."
+                    .to_string(),
+            ),
+            messages: vec![user(
+                "Summarize the article at {{url}} (content_ref \"{{content_ref}}\"). \
+{{#max_words}}Keep the summary under {{max_words}} words. {{/max_words}}\
+Start with the title and author if the page names them, then the key points as a short list.",
+            )],
+        },
+        PromptTemplate {
+            name: "form_filling".to_string(),
+            description: "Plan the browser steps that fill in and submit a form".to_string(),
+            arguments: vec![
+                url(),
+                argument(
+                    "fields",
+                    "The values to enter, e.g. as JSON or 'label: value' lines",
+                    true,
+                ),
+                argument("submit", "Whether to submit the form afterwards (yes/no)", false),
+            ],
+            system: Some(
+                "You are a web automation assistant. Fill in forms with the browser tools: find \
+each input by its label, name or placeholder, prefer ID and name selectors, and wait for \
+elements that load late. Never invent values that were not provided."
+                    .to_string(),
+            ),
+            messages: vec![user(
+                "Open {{url}} and fill in the form with these values:\n{{fields}}\n\n\
+{{#submit}}Submit the form when done: {{submit}}. {{/submit}}Report any field you could not find.",
+            )],
+        },
+        PromptTemplate {
+            name: "table_extraction".to_string(),
+            description: "Extract a table from a page as JSON rows".to_string(),
+            arguments: vec![
+                url(),
+                content_ref(),
+                argument(
+                    "table",
+                    "Which table to extract, e.g. its caption or a CSS selector",
+                    false,
+                ),
+            ],
+            system: Some(
+                "You extract tabular data from web pages. Use analyze_page_structure to find the \
+tables, then extract_by_selectors to read the header and body cells."
+                    .to_string(),
+            ),
+            messages: vec![user(
+                "Extract the table{{#table}} {{table}}{{/table}} from {{url}} (content_ref \"{{content_ref}}\"). \
+Return a JSON array with one object per row, keyed by the column headers.",
+            )],
+        },
+    ]
+}
@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::browser::BrowserSessions;
+use crate::mcp_resources::SessionEvents;
+
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 600;
+const DEFAULT_MAX_LIFETIME_SECS: u64 = 3600;
+const DEFAULT_REAP_INTERVAL_SECS: u64 = 30;
+
+fn env_secs(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// When a browser session expires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionTimeouts {
+    /// Time since the session was last used
+    pub idle: Duration,
+    /// Time since the session was created, regardless of use
+    pub max_lifetime: Option<Duration>,
+}
+
+/// Why the reaper closed a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionExpiry {
+    Idle,
+    MaxLifetime,
+}
+
+impl SessionTimeouts {
+    /// Configure from `BROWSER_SESSION_IDLE_TIMEOUT_SECS` (default 600) and
+    /// `BROWSER_SESSION_MAX_LIFETIME_SECS` (default 3600, 0 for no limit)
+    pub fn from_env() -> Self {
+        let max_lifetime = env_secs(
+            "BROWSER_SESSION_MAX_LIFETIME_SECS",
+            DEFAULT_MAX_LIFETIME_SECS,
+        );
+        Self {
+            idle: Duration::from_secs(env_secs(
+                "BROWSER_SESSION_IDLE_TIMEOUT_SECS",
+                DEFAULT_IDLE_TIMEOUT_SECS,
+            )),
+            max_lifetime: (max_lifetime > 0).then(|| Duration::from_secs(max_lifetime)),
+        }
+    }
+
+    /// Override the idle timeout, e.g. with a `timeout_seconds` a client asked for
+    pub fn with_idle_seconds(mut self, seconds: Option<u64>) -> Self {
+        if let Some(seconds) = seconds {
+            self.idle = Duration::from_secs(seconds);
+        }
+        self
+    }
+
+    pub fn with_max_lifetime_seconds(mut self, seconds: Option<u64>) -> Self {
+        if let Some(seconds) = seconds {
+            self.max_lifetime = Some(Duration::from_secs(seconds));
+        }
+        self
+    }
+
+    /// Whether a session created at `created_at` and last used at `last_used_at` has expired
+    pub fn expiry(
+        &self,
+        created_at: DateTime<Utc>,
+        last_used_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Option<SessionExpiry> {
+        let elapsed = |since: DateTime<Utc>| (now - since).to_std().unwrap_or_default();
+
+        if self
+            .max_lifetime
+            .is_some_and(|max_lifetime| elapsed(created_at) >= max_lifetime)
+        {
+            Some(SessionExpiry::MaxLifetime)
+        } else if elapsed(last_used_at) >= self.idle {
+            Some(SessionExpiry::Idle)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for SessionTimeouts {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+/// Periodically closes browser sessions that have passed their idle or lifetime timeout,
+/// so a long-running server doesn't accumulate Chromium tabs
+pub struct SessionReaper {
+    sessions: BrowserSessions,
+    events: SessionEvents,
+    interval: Duration,
+}
+
+impl SessionReaper {
+    /// Reaps every `BROWSER_SESSION_REAP_INTERVAL_SECS` (default 30)
+    pub fn new(sessions: BrowserSessions, events: SessionEvents) -> Self {
+        Self {
+            sessions,
+            events,
+            interval: Duration::from_secs(
+                env_secs(
+                    "BROWSER_SESSION_REAP_INTERVAL_SECS",
+                    DEFAULT_REAP_INTERVAL_SECS,
+                )
+                .max(1),
+            ),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Close the sessions that have expired by now
+    pub async fn reap(&self) -> Vec<(String, SessionExpiry)> {
+        let now = Utc::now();
+        let mut sessions = self.sessions.write().await;
+        let expired: Vec<(String, SessionExpiry)> = sessions
+            .iter()
+            .filter_map(|(session_id, session)| {
+                // A session that is locked is in use, so not idle
+                let session = session.try_lock().ok()?;
+                session
+                    .expiry(now)
+                    .map(|expiry| (session_id.clone(), expiry))
+            })
+            .collect();
+
+        for (session_id, expiry) in &expired {
+            // Dropping the session hands its page back to the pool
+            sessions.remove(session_id);
+            self.events.closed(session_id);
+            info!(
+                "Closed browser session {} ({:?} timeout)",
+                session_id, expiry
+            );
+        }
+        expired
+    }
+
+    /// Reap in the background until the returned task is aborted
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(self.interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                self.reap().await;
+            }
+        })
+    }
+}
@@ -0,0 +1,540 @@
+//! Structured data embedded in pages (JSON-LD, microdata, RDFa, and OpenGraph/Twitter card
+//! tags), read into one list of schema.org-style entities
+//!
+//! Whatever the syntax, an entity's properties use schema.org names without a vocabulary
+//! prefix, and nested entities are JSON objects with an `@type`. JSON-LD `@graph` containers
+//! are flattened and `@id` references replaced with the nodes they point to.
+
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+/// How many `@id` references deep to follow, which also stops reference cycles
+const MAX_REFERENCE_DEPTH: usize = 4;
+/// How deep to look inside other entities for nested ones (`mainEntity`, `itemOffered`)
+const MAX_NESTING_DEPTH: usize = 3;
+
+const PRODUCT_TYPES: &[&str] = &[
+    "Product",
+    "ProductGroup",
+    "IndividualProduct",
+    "ProductModel",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StructuredDataFormat {
+    JsonLd,
+    Microdata,
+    Rdfa,
+    /// OpenGraph and Twitter card `<meta>` tags, read as one entity
+    OpenGraph,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entity {
+    pub format: StructuredDataFormat,
+    /// schema.org type names, e.g. `["Product"]`
+    pub types: Vec<String>,
+    pub id: Option<String>,
+    pub properties: Map<String, Value>,
+}
+
+impl Entity {
+    pub fn is_a(&self, type_name: &str) -> bool {
+        self.types.iter().any(|t| t == type_name)
+    }
+
+    pub fn is_product(&self) -> bool {
+        PRODUCT_TYPES.iter().any(|t| self.is_a(t))
+    }
+
+    /// The entity as one JSON object, with its types under `@type`
+    pub fn to_json(&self) -> Value {
+        let mut object = self.properties.clone();
+        object.insert("@type".to_string(), types_value(&self.types));
+        if let Some(id) = &self.id {
+            object.insert("@id".to_string(), json!(id));
+        }
+        Value::Object(object)
+    }
+}
+
+/// Every entity found on a page, in the order JSON-LD, microdata, RDFa, OpenGraph
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StructuredData {
+    pub entities: Vec<Entity>,
+}
+
+impl StructuredData {
+    pub fn from_html(html_content: &str) -> Self {
+        Self::from_document(&Html::parse_document(html_content))
+    }
+
+    pub fn from_document(document: &Html) -> Self {
+        let mut entities = json_ld_entities(document);
+        entities.extend(attribute_entities(document, &MICRODATA));
+        entities.extend(attribute_entities(document, &RDFA));
+        entities.extend(open_graph_entity(document));
+        Self { entities }
+    }
+
+    /// Product entities, including ones nested in others such as a `WebPage`'s
+    /// `mainEntity` or an `Offer`'s `itemOffered`
+    pub fn products(&self) -> Vec<Entity> {
+        self.find(PRODUCT_TYPES)
+    }
+
+    /// Entities of any of `types`, top-level or nested inside other entities. Resolved
+    /// `@id` references copy a node into each place it is used, so nodes with an `@id`
+    /// are only returned once.
+    pub fn find(&self, types: &[&str]) -> Vec<Entity> {
+        let mut found = Vec::new();
+        for entity in &self.entities {
+            if types.iter().any(|type_name| entity.is_a(type_name)) {
+                found.push(entity.clone());
+            } else {
+                for value in entity.properties.values() {
+                    find_nested(entity.format, value, types, 0, &mut found);
+                }
+            }
+        }
+        let mut seen = HashSet::new();
+        found.retain(|entity| match &entity.id {
+            Some(id) => seen.insert(id.clone()),
+            None => true,
+        });
+        found
+    }
+}
+
+fn find_nested(
+    format: StructuredDataFormat,
+    value: &Value,
+    types: &[&str],
+    depth: usize,
+    found: &mut Vec<Entity>,
+) {
+    if depth >= MAX_NESTING_DEPTH {
+        return;
+    }
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                find_nested(format, item, types, depth, found);
+            }
+        }
+        Value::Object(object) => {
+            let entity = entity_from_object(format, object.clone());
+            if types.iter().any(|type_name| entity.is_a(type_name)) {
+                found.push(entity);
+            } else {
+                for value in object.values() {
+                    find_nested(format, value, types, depth + 1, found);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn entity_from_object(format: StructuredDataFormat, mut object: Map<String, Value>) -> Entity {
+    let types = object.remove("@type").map(type_names).unwrap_or_default();
+    let id = object
+        .remove("@id")
+        .and_then(|id| id.as_str().map(str::to_string));
+    Entity {
+        format,
+        types,
+        id,
+        properties: object,
+    }
+}
+
+/// `@type` as a list of bare names: "Product", ["Product", "Thing"] and
+/// "http://schema.org/Product" all contain "Product"
+fn type_names(value: Value) -> Vec<String> {
+    match value {
+        Value::String(types) => types.split_whitespace().map(term).collect(),
+        Value::Array(types) => types
+            .into_iter()
+            .filter_map(|t| t.as_str().map(term))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn types_value(types: &[String]) -> Value {
+    match types {
+        [single] => json!(single),
+        types => json!(types),
+    }
+}
+
+/// A property or type name without its vocabulary: "schema:name", "http://schema.org/name"
+/// and "name" are all "name"
+fn term(name: &str) -> String {
+    let name = name.trim();
+    if name.starts_with('@') {
+        return name.to_string();
+    }
+    let name = if name.contains("://") {
+        name.rsplit(['/', '#']).next().unwrap_or(name)
+    } else {
+        name.rsplit(':').next().unwrap_or(name)
+    };
+    name.to_string()
+}
+
+fn json_ld_entities(document: &Html) -> Vec<Entity> {
+    let Ok(selector) = Selector::parse("script[type='application/ld+json']") else {
+        return Vec::new();
+    };
+
+    let mut nodes = Vec::new();
+    for script in document.select(&selector) {
+        let text = script.text().collect::<String>();
+        match serde_json::from_str::<Value>(strip_script_wrappers(&text)) {
+            Ok(json) => collect_json_ld_nodes(normalize_json_ld(json), &mut nodes),
+            Err(e) => debug!("Skipping invalid JSON-LD block: {}", e),
+        }
+    }
+
+    let by_id: HashMap<String, Value> = nodes
+        .iter()
+        .filter(|node| node.len() > 1)
+        .filter_map(|node| {
+            let id = node.get("@id")?.as_str()?;
+            Some((id.to_string(), Value::Object(node.clone())))
+        })
+        .collect();
+
+    nodes
+        .into_iter()
+        .filter(|node| node.contains_key("@type"))
+        .filter_map(
+            |node| match resolve_references(Value::Object(node), &by_id, 0) {
+                Value::Object(node) => Some(entity_from_object(StructuredDataFormat::JsonLd, node)),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+/// Some sites wrap JSON-LD in HTML comments or CDATA markers
+fn strip_script_wrappers(text: &str) -> &str {
+    let mut text = text.trim();
+    for (open, close) in [
+        ("<!--", "-->"),
+        ("<![CDATA[", "]]>"),
+        ("//<![CDATA[", "//]]>"),
+    ] {
+        if let Some(inner) = text
+            .strip_prefix(open)
+            .and_then(|inner| inner.strip_suffix(close))
+        {
+            text = inner.trim();
+        }
+    }
+    text
+}
+
+/// The top-level nodes of a JSON-LD document: arrays are flattened and `@graph` members
+/// become nodes of their own
+fn collect_json_ld_nodes(value: Value, nodes: &mut Vec<Map<String, Value>>) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                collect_json_ld_nodes(item, nodes);
+            }
+        }
+        Value::Object(mut object) => {
+            if let Some(graph) = object.remove("@graph") {
+                collect_json_ld_nodes(graph, nodes);
+            }
+            if object.contains_key("@type") || object.len() > 1 {
+                nodes.push(object);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Drop `@context`, strip vocabulary prefixes from keys and types, and unwrap `@value`
+fn normalize_json_ld(value: Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.into_iter().map(normalize_json_ld).collect()),
+        Value::Object(mut object) => {
+            if let Some(value) = object.remove("@value") {
+                return normalize_json_ld(value);
+            }
+            if let Some(list) = object.remove("@list") {
+                return normalize_json_ld(list);
+            }
+            object.remove("@context");
+            Value::Object(
+                object
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let value = if key == "@type" {
+                            types_value(&type_names(value))
+                        } else {
+                            normalize_json_ld(value)
+                        };
+                        (term(&key), value)
+                    })
+                    .collect(),
+            )
+        }
+        value => value,
+    }
+}
+
+/// Replace `{"@id": ...}` references with the node they name
+fn resolve_references(value: Value, by_id: &HashMap<String, Value>, depth: usize) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| resolve_references(item, by_id, depth))
+                .collect(),
+        ),
+        Value::Object(object) => {
+            let is_reference = object.keys().all(|key| key == "@id" || key == "@type");
+            let target = object
+                .get("@id")
+                .and_then(Value::as_str)
+                .and_then(|id| by_id.get(id));
+            match target {
+                Some(target) if is_reference && depth < MAX_REFERENCE_DEPTH => {
+                    resolve_references(target.clone(), by_id, depth + 1)
+                }
+                _ => Value::Object(
+                    object
+                        .into_iter()
+                        .map(|(key, value)| (key, resolve_references(value, by_id, depth)))
+                        .collect(),
+                ),
+            }
+        }
+        value => value,
+    }
+}
+
+/// The HTML attributes that mark up entities in microdata or RDFa
+struct AttributeSyntax {
+    format: StructuredDataFormat,
+    /// Starts a new entity
+    scope: &'static str,
+    types: &'static str,
+    property: &'static str,
+    id: &'static str,
+}
+
+const MICRODATA: AttributeSyntax = AttributeSyntax {
+    format: StructuredDataFormat::Microdata,
+    scope: "itemscope",
+    types: "itemtype",
+    property: "itemprop",
+    id: "itemid",
+};
+
+const RDFA: AttributeSyntax = AttributeSyntax {
+    format: StructuredDataFormat::Rdfa,
+    scope: "typeof",
+    types: "typeof",
+    property: "property",
+    id: "resource",
+};
+
+fn attribute_entities(document: &Html, syntax: &AttributeSyntax) -> Vec<Entity> {
+    let Ok(selector) = Selector::parse(&format!("[{}]", syntax.scope)) else {
+        return Vec::new();
+    };
+    document
+        .select(&selector)
+        .filter(|element| is_top_level(*element, syntax))
+        .map(|element| entity_from_object(syntax.format, attribute_item(element, syntax)))
+        .collect()
+}
+
+/// Whether an entity stands on its own rather than being a property of an enclosing one
+fn is_top_level(element: ElementRef, syntax: &AttributeSyntax) -> bool {
+    element.value().attr(syntax.property).is_none()
+        || !element
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .any(|ancestor| ancestor.value().attr(syntax.scope).is_some())
+}
+
+/// An entity's type, ID and properties. Nested entities become objects; repeated
+/// properties, arrays.
+fn attribute_item(element: ElementRef, syntax: &AttributeSyntax) -> Map<String, Value> {
+    let mut item = Map::new();
+    if let Some(types) = element.value().attr(syntax.types) {
+        let types = type_names(json!(types));
+        if !types.is_empty() {
+            item.insert("@type".to_string(), types_value(&types));
+        }
+    }
+    if let Some(id) = element.value().attr(syntax.id) {
+        item.insert("@id".to_string(), json!(id));
+    }
+    collect_properties(element, syntax, &mut item);
+    item
+}
+
+fn collect_properties(
+    element: ElementRef,
+    syntax: &AttributeSyntax,
+    item: &mut Map<String, Value>,
+) {
+    for child in element.children().filter_map(ElementRef::wrap) {
+        let nested = child.value().attr(syntax.scope).is_some();
+        let Some(names) = child.value().attr(syntax.property) else {
+            // An entity that isn't one of our properties is a top-level entity of its own
+            if !nested {
+                collect_properties(child, syntax, item);
+            }
+            continue;
+        };
+
+        let value = if nested {
+            Value::Object(attribute_item(child, syntax))
+        } else {
+            Value::String(property_value(child))
+        };
+        for name in names.split_whitespace().map(term) {
+            match item.get_mut(&name) {
+                Some(Value::Array(values)) => values.push(value.clone()),
+                Some(existing) => *existing = json!([existing.take(), value.clone()]),
+                None => {
+                    item.insert(name, value.clone());
+                }
+            }
+        }
+        if !nested {
+            collect_properties(child, syntax, item);
+        }
+    }
+}
+
+/// A property's value, following the microdata rules for each element. `content` wins
+/// everywhere, as in RDFa and as many sites use it with microdata too.
+fn property_value(element: ElementRef) -> String {
+    let node = element.value();
+    let attribute = match node.name() {
+        "img" | "audio" | "video" | "source" | "embed" | "iframe" => Some("src"),
+        "a" | "area" | "link" => Some("href"),
+        "data" | "meter" => Some("value"),
+        "time" => Some("datetime"),
+        _ => None,
+    };
+    node.attr("content")
+        .or_else(|| attribute.and_then(|attribute| node.attr(attribute)))
+        .or_else(|| node.attr("resource"))
+        .map(|value| value.trim().to_string())
+        .unwrap_or_else(|| element_text(element))
+}
+
+/// OpenGraph tags, the `product:` namespace and Twitter cards as one schema.org-style entity
+fn open_graph_entity(document: &Html) -> Option<Entity> {
+    let selector = Selector::parse("meta[property], meta[name]").ok()?;
+    let tags: Vec<(String, String)> = document
+        .select(&selector)
+        .filter_map(|meta| {
+            let meta = meta.value();
+            let key = meta.attr("property").or_else(|| meta.attr("name"))?;
+            let content = meta.attr("content")?.trim();
+            (!content.is_empty()).then(|| (key.to_lowercase(), content.to_string()))
+        })
+        .filter(|(key, _)| {
+            ["og:", "product:", "twitter:"]
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+        })
+        .collect();
+    if tags.is_empty() {
+        return None;
+    }
+    let first = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| tags.iter().find(|(tag, _)| tag == key))
+            .map(|(_, content)| json!(content))
+    };
+
+    let mut properties = Map::new();
+    let mut set = |name: &str, value: Option<Value>| {
+        if let Some(value) = value {
+            properties.insert(name.to_string(), value);
+        }
+    };
+    set("name", first(&["og:title", "twitter:title"]));
+    set(
+        "description",
+        first(&["og:description", "twitter:description"]),
+    );
+    set("url", first(&["og:url"]));
+    set("brand", first(&["product:brand", "og:brand"]));
+    set("sku", first(&["product:retailer_item_id"]));
+    let price = first(&["product:price:amount", "og:price:amount"]);
+    let availability = first(&["product:availability", "og:availability"]);
+    if price.is_some() || availability.is_some() {
+        let mut offer = Map::new();
+        offer.insert("@type".to_string(), json!("Offer"));
+        for (name, value) in [
+            ("price", price),
+            (
+                "priceCurrency",
+                first(&["product:price:currency", "og:price:currency"]),
+            ),
+            ("availability", availability),
+        ] {
+            if let Some(value) = value {
+                offer.insert(name.to_string(), value);
+            }
+        }
+        set("offers", Some(Value::Object(offer)));
+    }
+    let images: Vec<Value> = tags
+        .iter()
+        .filter(|(tag, _)| {
+            matches!(
+                tag.as_str(),
+                "og:image" | "og:image:url" | "og:image:secure_url" | "twitter:image"
+            )
+        })
+        .map(|(_, content)| json!(content))
+        .collect();
+    if !images.is_empty() {
+        set("image", Some(Value::Array(images)));
+    }
+
+    let og_type = first(&["og:type"])
+        .and_then(|og_type| og_type.as_str().map(str::to_lowercase))
+        .unwrap_or_default();
+    let entity_type = if og_type.contains("product") {
+        "Product"
+    } else if og_type.contains("article") {
+        "Article"
+    } else {
+        "WebPage"
+    };
+    Some(Entity {
+        format: StructuredDataFormat::OpenGraph,
+        types: vec![entity_type.to_string()],
+        id: None,
+        properties,
+    })
+}
+
+/// The element's text with whitespace collapsed
+pub(crate) fn element_text(element: ElementRef) -> String {
+    element
+        .text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
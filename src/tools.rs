@@ -0,0 +1,208 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::llm::Tool;
+use crate::mcp_client::{ExternalTool, McpClient, McpServerConfig};
+use crate::mcp_server::ToolInfo;
+
+/// Per-call details supplied by the transport
+#[derive(Debug, Clone, Default)]
+pub struct ToolContext {
+    /// The MCP session the call arrived on, if the transport has sessions
+    pub session_id: Option<String>,
+}
+
+impl ToolContext {
+    pub fn for_session(session_id: impl Into<String>) -> Self {
+        Self {
+            session_id: Some(session_id.into()),
+        }
+    }
+}
+
+/// Base64-encoded image returned alongside a tool's JSON result
+#[derive(Debug, Clone)]
+pub struct ImageContent {
+    pub data: String,
+    pub mime_type: String,
+}
+
+/// What a tool produced: a JSON value, plus any images (such as screenshots) for the client
+#[derive(Debug, Clone, Default)]
+pub struct ToolOutput {
+    pub value: Value,
+    pub images: Vec<ImageContent>,
+}
+
+impl From<Value> for ToolOutput {
+    fn from(value: Value) -> Self {
+        Self {
+            value,
+            images: Vec::new(),
+        }
+    }
+}
+
+/// A tool call made on the model's behalf failed
+#[derive(Debug, thiserror::Error)]
+#[error("MCP tool error in {tool}: {message}")]
+pub struct ToolFailed {
+    pub tool: String,
+    pub message: String,
+}
+
+/// A tool that is listed by our MCP server and offered to the LLM
+#[async_trait]
+pub trait McpTool: Send + Sync {
+    fn info(&self) -> &ToolInfo;
+
+    async fn call(&self, arguments: &Value, context: &ToolContext) -> Result<ToolOutput, String>;
+
+    /// Called when an MCP session ends, so tools can release per-session state
+    fn session_closed(&self, _session_id: &str) {}
+}
+
+type ToolHandler = Box<dyn Fn(&Value) -> Result<Value, String> + Send + Sync>;
+
+type SessionClosedHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Tool implemented by a synchronous function in this process
+pub struct LocalTool {
+    info: ToolInfo,
+    handler: ToolHandler,
+}
+
+impl LocalTool {
+    pub fn new(
+        name: &str,
+        description: &str,
+        input_schema: Value,
+        handler: impl Fn(&Value) -> Result<Value, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            info: ToolInfo {
+                name: name.to_string(),
+                description: description.to_string(),
+                input_schema,
+            },
+            handler: Box::new(handler),
+        }
+    }
+}
+
+#[async_trait]
+impl McpTool for LocalTool {
+    fn info(&self) -> &ToolInfo {
+        &self.info
+    }
+
+    async fn call(&self, arguments: &Value, _context: &ToolContext) -> Result<ToolOutput, String> {
+        (self.handler)(arguments).map(ToolOutput::from)
+    }
+}
+
+/// The set of tools shared by the MCP endpoint and the LLM tool loop
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn McpTool>>,
+    session_hooks: Vec<SessionClosedHook>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tool. The first tool registered under a name wins; later duplicates are skipped.
+    pub fn register(&mut self, tool: Arc<dyn McpTool>) {
+        if self.get(&tool.info().name).is_some() {
+            warn!(
+                "Tool '{}' is already registered, skipping duplicate",
+                tool.info().name
+            );
+            return;
+        }
+        self.tools.push(tool);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn McpTool>> {
+        self.tools.iter().find(|tool| tool.info().name == name)
+    }
+
+    pub fn definitions(&self) -> Vec<ToolInfo> {
+        self.tools.iter().map(|tool| tool.info().clone()).collect()
+    }
+
+    /// The registered tools in the function-calling format the LLM providers expect
+    pub fn llm_tools(&self) -> Vec<Tool> {
+        self.tools
+            .iter()
+            .map(|tool| {
+                let info = tool.info();
+                Tool::function(&info.name, &info.description, info.input_schema.clone())
+            })
+            .collect()
+    }
+
+    pub async fn call(
+        &self,
+        name: &str,
+        arguments: &Value,
+        context: &ToolContext,
+    ) -> Result<ToolOutput, String> {
+        let tool = self
+            .get(name)
+            .ok_or_else(|| format!("Unknown tool: {}", name))?;
+        tool.call(arguments, context).await
+    }
+
+    /// Run `hook` whenever an MCP session ends, for state shared by several tools
+    pub fn on_session_closed(&mut self, hook: impl Fn(&str) + Send + Sync + 'static) {
+        self.session_hooks.push(Arc::new(hook));
+    }
+
+    pub fn session_closed(&self, session_id: &str) {
+        for tool in &self.tools {
+            tool.session_closed(session_id);
+        }
+        for hook in &self.session_hooks {
+            hook(session_id);
+        }
+    }
+
+    /// Connect to an external MCP server and register its tools as `{server}__{tool}`
+    pub async fn register_mcp_server(&mut self, config: &McpServerConfig) -> anyhow::Result<usize> {
+        let client = Arc::new(McpClient::connect(config).await?);
+        let tools = client
+            .list_tools()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list tools of '{}': {}", config.name, e))?;
+
+        let before = self.tools.len();
+        for tool in tools {
+            self.register(Arc::new(ExternalTool::new(client.clone(), tool)));
+        }
+        let registered = self.tools.len() - before;
+
+        info!(
+            "Loaded {} tools from MCP server '{}'",
+            registered, config.name
+        );
+        Ok(registered)
+    }
+
+    /// Register the servers configured by `MCP_SERVERS_FILE` and `MCP_REMOTE_ENDPOINTS`.
+    /// Servers that fail to start or respond are logged and skipped so they never block startup.
+    pub async fn register_mcp_servers_from_env(&mut self) {
+        for config in McpServerConfig::from_env() {
+            if let Err(e) = self.register_mcp_server(&config).await {
+                warn!(
+                    "Could not load tools from MCP server '{}': {}",
+                    config.name, e
+                );
+            }
+        }
+    }
+}
@@ -3,23 +3,69 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 // Request/Response types for API endpoints
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionCreateRequest {
+    /// Close the session after this long without use (default `BROWSER_SESSION_IDLE_TIMEOUT_SECS`)
     pub timeout_seconds: Option<u64>,
+    /// Close the session this long after creation, even while in use
+    /// (default `BROWSER_SESSION_MAX_LIFETIME_SECS`)
+    pub max_lifetime_seconds: Option<u64>,
+    #[serde(default)]
+    pub isolation: SessionIsolation,
+}
+
+/// How a browser session's cookies, cache and storage relate to other sessions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionIsolation {
+    /// A tab in the browser's default context, sharing its state with other shared sessions
+    #[default]
+    Shared,
+    /// A dedicated incognito browser context, disposed of with the session
+    Isolated,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionResponse {
     pub session_id: String,
     pub active: bool,
+    /// The session is running an action, so the remaining fields are not reported
+    #[serde(default)]
+    pub busy: bool,
+    #[serde(default)]
+    pub isolation: SessionIsolation,
     pub current_url: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// URLs the session has visited, oldest first
+    #[serde(default)]
+    pub history: Vec<String>,
+    #[serde(default)]
+    pub last_action: Option<SessionAction>,
     pub created_at: Option<String>,
+    #[serde(default)]
+    pub last_used_at: Option<String>,
+}
+
+/// The most recent action performed in a browser session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionAction {
+    /// `Navigate`, or the `BrowserAction` type such as `Click`
+    pub action: String,
+    pub success: bool,
+    pub at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionListResponse {
+    pub sessions: Vec<SessionResponse>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,6 +77,9 @@ pub struct CreateSessionResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProductInformationRequest {
     pub url: String,
+    /// When the page counts as loaded (default: the load event)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait_until: Option<WaitUntil>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,12 +93,84 @@ pub struct SessionStatusResponse {
 pub struct NavigateRequest {
     pub session_id: String,
     pub url: String,
+    /// When the page counts as loaded (default: the load event)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait_until: Option<WaitUntil>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NavigateResponse {
     pub success: bool,
     pub current_url: String,
+    #[serde(default)]
+    pub wait: Option<WaitOutcome>,
+}
+
+/// What navigation waits for before the page counts as loaded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WaitCondition {
+    /// The load event: the document and its subresources have loaded
+    Load,
+    /// The document has been parsed; images and stylesheets may still be loading
+    #[serde(rename = "domcontentloaded")]
+    DomContentLoaded,
+    /// No network requests in flight for `idle_ms`
+    NetworkIdle {
+        #[serde(default = "default_network_idle_ms")]
+        idle_ms: u64,
+    },
+    /// An element matching the CSS selector exists
+    Selector { selector: String },
+    /// A JavaScript expression evaluates to a truthy value
+    Predicate { script: String },
+}
+
+fn default_network_idle_ms() -> u64 {
+    500
+}
+
+impl WaitCondition {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WaitCondition::Load => "load",
+            WaitCondition::DomContentLoaded => "domcontentloaded",
+            WaitCondition::NetworkIdle { .. } => "network_idle",
+            WaitCondition::Selector { .. } => "selector",
+            WaitCondition::Predicate { .. } => "predicate",
+        }
+    }
+}
+
+/// A wait condition with its timeout, e.g. `{"type": "selector", "selector": "#price", "timeout_ms": 5000}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaitUntil {
+    #[serde(flatten)]
+    pub condition: WaitCondition,
+    /// How long after navigation starts to keep waiting (default 30 seconds). The navigation
+    /// still succeeds when it passes; the outcome reports the condition as unmet.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+impl Default for WaitUntil {
+    fn default() -> Self {
+        Self {
+            condition: WaitCondition::Load,
+            timeout_ms: None,
+        }
+    }
+}
+
+/// How waiting for a page went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaitOutcome {
+    /// The condition waited for, e.g. `network_idle`
+    pub condition: String,
+    /// False if the timeout passed first
+    pub satisfied: bool,
+    /// Time from the start of navigation until the condition held or the timeout passed
+    pub elapsed_ms: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -82,15 +203,72 @@ pub struct AutomationRequest {
     pub task_description: String,
     pub target_url: Option<String>,
     pub context: Option<HashMap<String, Value>>,
+    /// When true, only plan the task and return the `TaskPlan` without running it
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AutomationResponse {
     pub success: bool,
     pub task_id: String,
+    pub plan: TaskPlan,
     pub results: Vec<TaskResult>,
 }
 
+// Closed-loop agent types
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentRequest {
+    pub session_id: String,
+    pub goal: String,
+    pub target_url: Option<String>,
+    /// Maximum number of browser actions the agent may take
+    pub max_steps: Option<usize>,
+    /// Wall-clock budget for the whole run, in seconds
+    pub max_duration_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentStatus {
+    /// The model declared the goal achieved
+    Completed,
+    /// The model declared the goal unachievable
+    GaveUp,
+    StepBudgetExhausted,
+    TimeBudgetExhausted,
+    /// The model could not be reached or kept returning unusable output
+    ModelError,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageObservation {
+    pub url: String,
+    pub title: Option<String>,
+    pub dom_summary: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStep {
+    pub step: usize,
+    pub reasoning: Option<String>,
+    pub action: BrowserAction,
+    pub result: TaskResult,
+    pub observation: Option<PageObservation>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentResponse {
+    pub success: bool,
+    pub task_id: String,
+    pub status: AgentStatus,
+    pub summary: Option<String>,
+    pub steps: Vec<AgentStep>,
+    pub elapsed_ms: u64,
+}
+
 // Browser action types
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -121,6 +299,103 @@ pub enum BrowserAction {
     },
 }
 
+impl BrowserAction {
+    /// The action's `type` tag, e.g. `Click`
+    pub fn name(&self) -> &'static str {
+        match self {
+            BrowserAction::Click { .. } => "Click",
+            BrowserAction::Type { .. } => "Type",
+            BrowserAction::Wait { .. } => "Wait",
+            BrowserAction::WaitForElement { .. } => "WaitForElement",
+            BrowserAction::Scroll { .. } => "Scroll",
+            BrowserAction::Screenshot => "Screenshot",
+            BrowserAction::GetPageSource => "GetPageSource",
+            BrowserAction::ExecuteScript { .. } => "ExecuteScript",
+        }
+    }
+
+    /// Whether performing the action can change the page or its screenshot
+    pub fn changes_page(&self) -> bool {
+        !matches!(self, BrowserAction::GetPageSource)
+    }
+
+    /// JSON schema matching the serde representation of `BrowserAction`
+    /// (`{"type": "Click", "params": {...}}`), for use in LLM tool definitions.
+    pub fn json_schema() -> Value {
+        fn variant(name: &str, params: Option<Value>) -> Value {
+            match params {
+                Some(params) => serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "type": {"type": "string", "enum": [name]},
+                        "params": params
+                    },
+                    "required": ["type", "params"]
+                }),
+                None => serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "type": {"type": "string", "enum": [name]}
+                    },
+                    "required": ["type"]
+                }),
+            }
+        }
+
+        serde_json::json!({
+            "oneOf": [
+                variant("Click", Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "selector": {"type": "string", "description": "CSS selector of the element to click"}
+                    },
+                    "required": ["selector"]
+                }))),
+                variant("Type", Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "selector": {"type": "string", "description": "CSS selector of the input"},
+                        "text": {"type": "string", "description": "Text to type"}
+                    },
+                    "required": ["selector", "text"]
+                }))),
+                variant("Wait", Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "duration_ms": {"type": "integer", "minimum": 0}
+                    },
+                    "required": ["duration_ms"]
+                }))),
+                variant("WaitForElement", Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "selector": {"type": "string"},
+                        "timeout_ms": {"type": "integer", "minimum": 0}
+                    },
+                    "required": ["selector"]
+                }))),
+                variant("Scroll", Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "direction": {"type": "string", "enum": ["Up", "Down", "Left", "Right"]},
+                        "pixels": {"type": "integer"}
+                    },
+                    "required": ["direction"]
+                }))),
+                variant("Screenshot", None),
+                variant("GetPageSource", None),
+                variant("ExecuteScript", Some(serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "script": {"type": "string", "description": "JavaScript expression to evaluate"}
+                    },
+                    "required": ["script"]
+                }))),
+            ]
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ScrollDirection {
     Up,
@@ -169,19 +444,90 @@ pub struct ProductExtractionResponse {
     pub extraction_time_ms: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ProductInfo {
     pub name: Option<String>,
     pub description: Option<String>,
-    pub price: Option<String>,
-    pub availability: Option<String>,
+    pub price: Option<Price>,
+    pub availability: Option<Availability>,
     pub brand: Option<String>,
-    pub rating: Option<String>,
-    pub image_url: Option<String>,
+    pub rating: Option<Rating>,
+    #[serde(default)]
+    pub images: Vec<String>,
+    pub sku: Option<String>,
+    pub gtin: Option<String>,
+    pub mpn: Option<String>,
+    #[serde(default)]
+    pub variants: Vec<ProductVariant>,
+    /// Where each field that has a value came from, keyed by field name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sources: BTreeMap<String, FieldProvenance>,
     pub raw_data: Option<String>, // For debugging - contains the raw HTML that was analyzed
     pub raw_llm_response: Option<String>, // Raw response from the LLM before parsing
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Price {
+    /// Exact amount, serialized as a string ("1299.00") so no precision is lost
+    pub amount: Decimal,
+    /// ISO 4217 code, when the page states or implies one
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rating {
+    pub value: f64,
+    /// Best possible rating, e.g. 5 for "4.5 out of 5"
+    pub scale: Option<f64>,
+    pub review_count: Option<u64>,
+}
+
+/// Stock status, named after schema.org's `ItemAvailability` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Availability {
+    InStock,
+    OutOfStock,
+    SoldOut,
+    PreOrder,
+    BackOrder,
+    LimitedAvailability,
+    OnlineOnly,
+    InStoreOnly,
+    Discontinued,
+}
+
+/// The extraction stage a product field was taken from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldSource {
+    JsonLd,
+    Microdata,
+    Rdfa,
+    OpenGraph,
+    Selector,
+    Llm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FieldProvenance {
+    pub source: FieldSource,
+    /// How far the value can be trusted, from 0 to 1
+    pub confidence: f64,
+}
+
+/// One purchasable option of a product, such as a size or color
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProductVariant {
+    pub name: Option<String>,
+    pub sku: Option<String>,
+    pub gtin: Option<String>,
+    pub price: Option<Price>,
+    pub availability: Option<Availability>,
+    /// Distinguishing attributes, e.g. `{"color": "red", "size": "M"}`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub attributes: HashMap<String, String>,
+}
+
 // Debug endpoint types
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DebugPageRequest {
@@ -207,6 +553,41 @@ pub enum AppError {
     #[error("Session not found: {0}")]
     SessionNotFound(String),
 
+    /// The session's page was lost when the browser crashed and was relaunched
+    #[error("Session {0} was lost when the browser restarted")]
+    SessionInvalidated(String),
+
+    #[error("{0}")]
+    ElementNotFound(String),
+
+    #[error("{0}")]
+    NavigationTimeout(String),
+
+    /// The URL could not be loaded: malformed, unresolvable, refused, ...
+    #[error("{0}")]
+    NavigationFailed(String),
+
+    /// Every browser page stayed busy for the whole acquire timeout
+    #[error("{0}")]
+    BrowserBusy(String),
+
+    #[error("{0}")]
+    LlmUnavailable(String),
+
+    /// The LLM server answered with an error status
+    #[error("{0}")]
+    LlmError(String),
+
+    #[error("{0}")]
+    LlmOutputUnparseable(String),
+
+    #[error("{0}")]
+    ToolFailed(String),
+
+    /// Neither the page's data nor the LLM yielded any product fields
+    #[error("{0}")]
+    ProductNotFound(String),
+
     #[error("MCP error: {0}")]
     MCPError(String),
 
@@ -217,24 +598,129 @@ pub enum AppError {
     InternalError(String),
 }
 
+impl AppError {
+    /// Classify an error from a browser session operation. Untyped failures are reported
+    /// as `browser_error`.
+    pub fn from_browser(error: anyhow::Error) -> Self {
+        Self::classify(&error).unwrap_or_else(|| AppError::BrowserError(error.to_string()))
+    }
+
+    /// The typed error inside `error`, if it carries one
+    fn classify(error: &anyhow::Error) -> Option<Self> {
+        use crate::browser::{BrowserRestarted, ElementNotFound, NavigationError};
+        use crate::llm::LlmError;
+        use crate::page_pool::PoolTimeout;
+        use crate::product_extraction::NoProductData;
+        use crate::tools::ToolFailed;
+
+        let message = error.to_string();
+        if let Some(restarted) = error.downcast_ref::<BrowserRestarted>() {
+            return Some(AppError::SessionInvalidated(restarted.session_id.clone()));
+        }
+        if error.downcast_ref::<ElementNotFound>().is_some() {
+            return Some(AppError::ElementNotFound(message));
+        }
+        if let Some(navigation) = error.downcast_ref::<NavigationError>() {
+            return Some(match navigation {
+                NavigationError::Timeout { .. } => AppError::NavigationTimeout(message),
+                NavigationError::Failed { .. } => AppError::NavigationFailed(message),
+            });
+        }
+        if error.downcast_ref::<PoolTimeout>().is_some() {
+            return Some(AppError::BrowserBusy(message));
+        }
+        if let Some(llm) = error.downcast_ref::<LlmError>() {
+            return Some(match llm {
+                LlmError::Unreachable { .. } => AppError::LlmUnavailable(message),
+                LlmError::Api { .. } => AppError::LlmError(message),
+                LlmError::UnparseableOutput(_) => AppError::LlmOutputUnparseable(message),
+            });
+        }
+        if error.downcast_ref::<ToolFailed>().is_some() {
+            return Some(AppError::ToolFailed(message));
+        }
+        if error.downcast_ref::<NoProductData>().is_some() {
+            return Some(AppError::ProductNotFound(message));
+        }
+        None
+    }
+
+    /// Stable, machine-readable identifier for the kind of failure
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BrowserError(_) => "browser_error",
+            AppError::SessionNotFound(_) => "session_not_found",
+            AppError::SessionInvalidated(_) => "browser_crashed",
+            AppError::ElementNotFound(_) => "element_not_found",
+            AppError::NavigationTimeout(_) => "navigation_timeout",
+            AppError::NavigationFailed(_) => "navigation_failed",
+            AppError::BrowserBusy(_) => "browser_busy",
+            AppError::LlmUnavailable(_) => "llm_unavailable",
+            AppError::LlmError(_) => "llm_error",
+            AppError::LlmOutputUnparseable(_) => "llm_output_unparseable",
+            AppError::ToolFailed(_) => "tool_failed",
+            AppError::ProductNotFound(_) => "product_not_found",
+            AppError::MCPError(_) => "mcp_error",
+            AppError::SerializationError(_) => "invalid_json",
+            AppError::InternalError(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BrowserError(_) => StatusCode::BAD_REQUEST,
+            AppError::SessionNotFound(_) => StatusCode::NOT_FOUND,
+            AppError::SessionInvalidated(_) => StatusCode::GONE,
+            AppError::ElementNotFound(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::NavigationTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::NavigationFailed(_) => StatusCode::BAD_REQUEST,
+            AppError::BrowserBusy(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::LlmUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::LlmError(_) => StatusCode::BAD_GATEWAY,
+            AppError::LlmOutputUnparseable(_) => StatusCode::BAD_GATEWAY,
+            AppError::ToolFailed(_) => StatusCode::BAD_GATEWAY,
+            AppError::ProductNotFound(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::MCPError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::SerializationError(_) => StatusCode::BAD_REQUEST,
+            AppError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Typed errors keep their code; anything else is an internal error
+impl From<anyhow::Error> for AppError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::classify(&error).unwrap_or_else(|| AppError::InternalError(error.to_string()))
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::BrowserError(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::SessionNotFound(session_id) => (
-                StatusCode::NOT_FOUND,
-                format!("Session {session_id} not found"),
-            ),
-            AppError::MCPError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            AppError::SerializationError(err) => (
-                StatusCode::BAD_REQUEST,
-                format!("Serialization error: {err}"),
+        let status = self.status();
+        let code = self.code();
+        let error_message = match self {
+            AppError::SessionNotFound(session_id) => format!("Session {session_id} not found"),
+            AppError::SessionInvalidated(session_id) => format!(
+                "Session {session_id} is no longer valid because the browser restarted; create a new session"
             ),
-            AppError::InternalError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            AppError::SerializationError(err) => format!("Serialization error: {err}"),
+            AppError::BrowserError(msg)
+            | AppError::ElementNotFound(msg)
+            | AppError::NavigationTimeout(msg)
+            | AppError::NavigationFailed(msg)
+            | AppError::BrowserBusy(msg)
+            | AppError::LlmUnavailable(msg)
+            | AppError::LlmError(msg)
+            | AppError::LlmOutputUnparseable(msg)
+            | AppError::ToolFailed(msg)
+            | AppError::ProductNotFound(msg)
+            | AppError::MCPError(msg)
+            | AppError::InternalError(msg) => msg,
         };
 
         let body = Json(serde_json::json!({
             "error": error_message,
+            "code": code,
             "status": status.as_u16(),
         }));
 
@@ -1,4 +1,3 @@
-use reqwest;
 use serde_json::Value;
 
 // Server URL for external Docker server
@@ -8,7 +7,7 @@ pub const SERVER_URL: &str = "http://localhost:3000";
 pub async fn create_session() -> Result<String, String> {
     let client = reqwest::Client::new();
     let response = client
-        .post(&format!("{}/browser/session", SERVER_URL))
+        .post(format!("{}/browser/session", SERVER_URL))
         .send()
         .await
         .map_err(|e| format!("Failed to send session creation request: {}", e))?;
@@ -38,7 +37,7 @@ pub async fn create_session() -> Result<String, String> {
 pub async fn check_server_health() -> Result<(), String> {
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("{}/health", SERVER_URL))
+        .get(format!("{}/health", SERVER_URL))
         .send()
         .await
         .map_err(|e| format!("Failed to connect to server: {}", e))?;
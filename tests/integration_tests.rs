@@ -16,7 +16,7 @@ async fn test_health_endpoint() {
 
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("{}/health", SERVER_URL))
+        .get(format!("{}/health", SERVER_URL))
         .send()
         .await
         .expect("Health request should succeed");
@@ -40,7 +40,7 @@ async fn test_navigation_api_contract() {
 
     // Test with invalid session ID
     let response = client
-        .post(&format!("{}/browser/navigate", SERVER_URL))
+        .post(format!("{}/browser/navigate", SERVER_URL))
         .json(&json!({
             "session_id": "invalid-session-id",
             "url": "https://httpbin.org/get"
@@ -62,7 +62,7 @@ async fn test_browser_session_creation_api_only() {
     // Just test the API response without actually using the browser
     let client = reqwest::Client::new();
     let response = client
-        .post(&format!("{}/browser/session", SERVER_URL))
+        .post(format!("{}/browser/session", SERVER_URL))
         .send()
         .await
         .expect("Session creation should succeed");
@@ -83,7 +83,7 @@ async fn test_browser_session_creation_api_only() {
 
     // Clean up this specific session
     let _ = client
-        .delete(&format!("{}/browser/session/{}", SERVER_URL, session_id))
+        .delete(format!("{}/browser/session/{}", SERVER_URL, session_id))
         .send()
         .await;
 
@@ -100,7 +100,7 @@ async fn test_wait_action() {
     let start = std::time::Instant::now();
 
     let response = client
-        .post(&format!("{}/browser/interact", SERVER_URL))
+        .post(format!("{}/browser/interact", SERVER_URL))
         .json(&json!({
             "session_id": session_id,
             "action": {
@@ -134,20 +134,20 @@ async fn test_wait_action() {
 
     // Clean up this specific session
     let _ = client
-        .delete(&format!("{}/browser/session/{}", SERVER_URL, session_id))
+        .delete(format!("{}/browser/session/{}", SERVER_URL, session_id))
         .send()
         .await;
 }
 
 #[tokio::test]
-async fn test_automation_task_fallback() {
+async fn test_automation_task() {
     let session_id = create_session()
         .await
         .expect("Browser session creation must succeed for this test");
 
     let client = reqwest::Client::new();
     let response = client
-        .post(&format!("{}/automation/task", SERVER_URL))
+        .post(format!("{}/automation/task", SERVER_URL))
         .json(&json!({
             "session_id": session_id,
             "task_description": "Take a screenshot of the page",
@@ -157,25 +157,73 @@ async fn test_automation_task_fallback() {
         .await
         .expect("Automation task request should succeed");
 
+    // Without a reachable LLM there is no plan to run
+    if response.status() == StatusCode::SERVICE_UNAVAILABLE {
+        let body: Value = response.json().await.expect("Response should be JSON");
+        assert_eq!(body["code"], "llm_unavailable");
+        println!("✅ Automation task test passed (LLM unavailable)");
+    } else {
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: Value = response.json().await.expect("Response should be JSON");
+        assert_eq!(body["success"], true);
+        assert!(body["task_id"].is_string(), "Should have a task ID");
+        assert!(body["results"].is_array(), "Should have results array");
+
+        let results = body["results"].as_array().expect("Results should be array");
+        assert!(!results.is_empty(), "Should have at least one result");
+        println!("✅ Automation task test passed ({} steps)", results.len());
+    }
+
+    // Clean up this specific session
+    let _ = client
+        .delete(format!("{}/browser/session/{}", SERVER_URL, session_id))
+        .send()
+        .await;
+}
+
+#[tokio::test]
+async fn test_automation_task_dry_run() {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/automation/task", SERVER_URL))
+        .json(&json!({
+            "session_id": "unused-in-dry-run",
+            "task_description": "Take a screenshot of the page",
+            "dry_run": true
+        }))
+        .send()
+        .await
+        .expect("Automation task request should succeed");
+
     assert_eq!(response.status(), StatusCode::OK);
 
     let body: Value = response.json().await.expect("Response should be JSON");
-    assert_eq!(body["success"], true);
-    assert!(body["task_id"].is_string(), "Should have a task ID");
-    assert!(body["results"].is_array(), "Should have results array");
+    assert!(body["description"].is_string(), "Should return the plan");
+    assert!(body["steps"].is_array(), "Plan should have steps");
+    assert!(body.get("results").is_none(), "Dry run should not execute");
 
-    let results = body["results"].as_array().expect("Results should be array");
-    assert!(!results.is_empty(), "Should have at least one result");
-    println!(
-        "✅ Automation task fallback test passed ({} steps)",
-        results.len()
-    );
+    println!("✅ Automation task dry run test passed");
+}
 
-    // Clean up this specific session
-    let _ = client
-        .delete(&format!("{}/browser/session/{}", SERVER_URL, session_id))
+#[tokio::test]
+async fn test_agent_task_invalid_session() {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/automation/agent", SERVER_URL))
+        .json(&json!({
+            "session_id": "invalid-session-id",
+            "goal": "Find the page title"
+        }))
         .send()
-        .await;
+        .await
+        .expect("Agent task request should complete");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body: Value = response.json().await.expect("Response should be JSON");
+    assert!(body["error"].as_str().unwrap().contains("Session"));
+
+    println!("✅ Agent task invalid session test passed");
 }
 
 // Tests that require actual browser navigation
@@ -190,7 +238,7 @@ async fn test_real_browser_navigation() {
 
     let client = reqwest::Client::new();
     let response = client
-        .post(&format!("{}/browser/navigate", SERVER_URL))
+        .post(format!("{}/browser/navigate", SERVER_URL))
         .json(&json!({
             "session_id": session_id,
             "url": test_url
@@ -214,7 +262,7 @@ async fn test_real_browser_navigation() {
 
     // Verify we can get page source and it contains expected content
     let source_response = client
-        .post(&format!("{}/browser/interact", SERVER_URL))
+        .post(format!("{}/browser/interact", SERVER_URL))
         .json(&json!({
             "session_id": session_id,
             "action": {
@@ -254,7 +302,7 @@ async fn test_real_browser_navigation() {
 
     // Clean up this specific session
     let _ = client
-        .delete(&format!("{}/browser/session/{}", SERVER_URL, session_id))
+        .delete(format!("{}/browser/session/{}", SERVER_URL, session_id))
         .send()
         .await;
 }
@@ -268,7 +316,7 @@ async fn test_browser_screenshot() {
     // Navigate to a simple page first
     let client = reqwest::Client::new();
     let nav_response = client
-        .post(&format!("{}/browser/navigate", SERVER_URL))
+        .post(format!("{}/browser/navigate", SERVER_URL))
         .json(&json!({
             "session_id": session_id,
             "url": "https://httpbin.org/html"
@@ -288,7 +336,7 @@ async fn test_browser_screenshot() {
 
     // Take screenshot
     let response = client
-        .post(&format!("{}/browser/interact", SERVER_URL))
+        .post(format!("{}/browser/interact", SERVER_URL))
         .json(&json!({
             "session_id": session_id,
             "action": {
@@ -325,7 +373,7 @@ async fn test_browser_screenshot() {
 
     // Clean up this specific session
     let _ = client
-        .delete(&format!("{}/browser/session/{}", SERVER_URL, session_id))
+        .delete(format!("{}/browser/session/{}", SERVER_URL, session_id))
         .send()
         .await;
 }
@@ -334,7 +382,7 @@ async fn test_browser_screenshot() {
 async fn test_invalid_session_error() {
     let client = reqwest::Client::new();
     let response = client
-        .post(&format!("{}/browser/navigate", SERVER_URL))
+        .post(format!("{}/browser/navigate", SERVER_URL))
         .json(&json!({
             "session_id": "invalid-session-id",
             "url": "https://httpbin.org/get"
@@ -351,6 +399,29 @@ async fn test_invalid_session_error() {
     println!("✅ Invalid session error handling test passed");
 }
 
+#[tokio::test]
+async fn test_interact_invalid_session_error() {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/browser/interact", SERVER_URL))
+        .json(&json!({
+            "session_id": "invalid-session-id",
+            "action": {
+                "type": "Screenshot"
+            }
+        }))
+        .send()
+        .await
+        .expect("Request should complete");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body: Value = response.json().await.expect("Response should be JSON");
+    assert!(body["error"].as_str().unwrap().contains("Session"));
+    assert_eq!(body["status"], 404);
+
+    println!("✅ Interact invalid session error handling test passed");
+}
+
 // ===== PRODUCT EXTRACTION TESTS =====
 
 #[tokio::test]
@@ -359,7 +430,7 @@ async fn test_product_extraction_without_session() {
 
     // Test without session ID (should create temporary session)
     let response = client
-        .post(&format!("{}/product/information", SERVER_URL))
+        .post(format!("{}/product/information", SERVER_URL))
         .json(&json!({
             "url": "https://httpbin.org/html"
         }))
@@ -381,7 +452,12 @@ async fn test_product_extraction_without_session() {
     // For httpbin, we won't get real product info, but the structure should be there
     assert!(product["name"].is_string() || product["name"].is_null());
     assert!(product["description"].is_string() || product["description"].is_null());
-    assert!(product["price"].is_string() || product["price"].is_null());
+    assert!(product["price"].is_object() || product["price"].is_null());
+    if product["price"].is_object() {
+        assert!(product["price"]["amount"].is_string());
+    }
+    assert!(product["rating"].is_object() || product["rating"].is_null());
+    assert!(product["images"].is_array());
 
     println!(
         "✅ Product extraction test (no session) passed in {}ms",
@@ -390,7 +466,7 @@ async fn test_product_extraction_without_session() {
 
     // Clean up any browser sessions created during this test
     let _ = client
-        .post(&format!("{}/browser/sessions/cleanup", SERVER_URL))
+        .post(format!("{}/browser/sessions/cleanup", SERVER_URL))
         .send()
         .await;
 }
@@ -405,7 +481,7 @@ async fn test_product_extraction_with_existing_session() {
 
     // Test with existing session
     let response = client
-        .post(&format!("{}/product/information", SERVER_URL))
+        .post(format!("{}/product/information", SERVER_URL))
         .json(&json!({
             "url": "https://httpbin.org/html",
             "session_id": session_id
@@ -431,7 +507,7 @@ async fn test_product_extraction_with_existing_session() {
 
     // Clean up this specific session
     let _ = client
-        .delete(&format!("{}/browser/session/{}", SERVER_URL, session_id))
+        .delete(format!("{}/browser/session/{}", SERVER_URL, session_id))
         .send()
         .await;
 }
@@ -444,7 +520,7 @@ async fn test_product_extraction_amazon_url() {
     let amazon_url = "https://www.amazon.com/Star-Wars-Echo-Dot-bundle/dp/B0DZQ92XQZ/?_encoding=UTF8&pd_rd_w=J2REa&content-id=amzn1.sym.facdd3a9-7c82-4bfb-a2c8-ce73833c9be4&pf_rd_p=facdd3a9-7c82-4bfb-a2c8-ce73833c9be4&pf_rd_r=NGBMAN14SM5N4SCFJXGT&pd_rd_wg=5je2T&pd_rd_r=4ed5974f-7ae0-4192-9993-eaf90ae98cce&ref_=pd_hp_d_atf_dealz_sv&th=1";
 
     let response = client
-        .post(&format!("{}/product/information", SERVER_URL))
+        .post(format!("{}/product/information", SERVER_URL))
         .json(&json!({
             "url": amazon_url
         }))
@@ -475,8 +551,9 @@ async fn test_product_extraction_amazon_url() {
     if let Some(description) = product["description"].as_str() {
         println!("   Description: {}", description);
     }
-    if let Some(price) = product["price"].as_str() {
-        println!("   Price: {}", price);
+    if let Some(amount) = product["price"]["amount"].as_str() {
+        let currency = product["price"]["currency"].as_str().unwrap_or("");
+        println!("   Price: {} {}", amount, currency);
     }
     if let Some(availability) = product["availability"].as_str() {
         println!("   Availability: {}", availability);
@@ -484,11 +561,14 @@ async fn test_product_extraction_amazon_url() {
     if let Some(brand) = product["brand"].as_str() {
         println!("   Brand: {}", brand);
     }
-    if let Some(rating) = product["rating"].as_str() {
-        println!("   Rating: {}", rating);
+    if let Some(rating) = product["rating"]["value"].as_f64() {
+        match product["rating"]["scale"].as_f64() {
+            Some(scale) => println!("   Rating: {}/{}", rating, scale),
+            None => println!("   Rating: {}", rating),
+        }
     }
-    if let Some(image_url) = product["image_url"].as_str() {
-        println!("   Image URL: {}", image_url);
+    for image in product["images"].as_array().into_iter().flatten() {
+        println!("   Image URL: {}", image.as_str().unwrap_or_default());
     }
 
     // Verify that we got at least some product information
@@ -497,7 +577,7 @@ async fn test_product_extraction_amazon_url() {
     assert!(
         product["name"].is_string()
             || product["description"].is_string()
-            || product["price"].is_string(),
+            || product["price"]["amount"].is_string(),
         "Should extract at least one piece of product information from Amazon page"
     );
 
@@ -508,7 +588,7 @@ async fn test_product_extraction_amazon_url() {
 
     // Clean up any temporary sessions (this test doesn't use a specific session)
     let _ = client
-        .post(&format!("{}/browser/sessions/cleanup", SERVER_URL))
+        .post(format!("{}/browser/sessions/cleanup", SERVER_URL))
         .send()
         .await;
 }
@@ -519,7 +599,7 @@ async fn test_product_extraction_invalid_session() {
 
     // Test with invalid session ID
     let response = client
-        .post(&format!("{}/product/information", SERVER_URL))
+        .post(format!("{}/product/information", SERVER_URL))
         .json(&json!({
             "url": "https://httpbin.org/html",
             "session_id": "invalid-session-id"
@@ -537,7 +617,7 @@ async fn test_product_extraction_invalid_session() {
 
     // Clean up browser sessions (just in case)
     let _ = client
-        .post(&format!("{}/browser/sessions/cleanup", SERVER_URL))
+        .post(format!("{}/browser/sessions/cleanup", SERVER_URL))
         .send()
         .await;
 }
@@ -548,7 +628,7 @@ async fn test_product_extraction_malformed_request() {
 
     // Test with missing URL
     let response = client
-        .post(&format!("{}/product/information", SERVER_URL))
+        .post(format!("{}/product/information", SERVER_URL))
         .json(&json!({
             "session_id": "some-session"
         }))
@@ -564,7 +644,7 @@ async fn test_product_extraction_malformed_request() {
 
     // Test with empty request
     let response2 = client
-        .post(&format!("{}/product/information", SERVER_URL))
+        .post(format!("{}/product/information", SERVER_URL))
         .json(&json!({}))
         .send()
         .await
@@ -579,7 +659,7 @@ async fn test_product_extraction_malformed_request() {
 
     // Clean up browser sessions (just in case)
     let _ = client
-        .post(&format!("{}/browser/sessions/cleanup", SERVER_URL))
+        .post(format!("{}/browser/sessions/cleanup", SERVER_URL))
         .send()
         .await;
 }
@@ -1,5 +1,43 @@
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use llm_web_agent::agent::{replies_to, summarize_dom, AgentBudget};
+use llm_web_agent::browser::{
+    browser_supervisor, find_session, BrowserRestarted, BrowserSession, BrowserSessions,
+    ElementNotFound, NavigationError,
+};
+use llm_web_agent::browser_supervisor::{BrowserLauncher, BrowserSupervisor, LaunchedBrowser};
+use llm_web_agent::browser_tools::register_browser_tools;
+use llm_web_agent::content_store::ContentStore;
+use llm_web_agent::llama_client::{parse_task_plan, LlamaClient};
+use llm_web_agent::llm::mock::{MockProvider, ScriptedProvider};
+use llm_web_agent::llm::openai::chat_completions_url;
+use llm_web_agent::llm::{ChatResponse, LlmConfig, LlmError, LlmProvider, Message, ProviderKind};
+use llm_web_agent::mcp_client::{McpServerConfig, McpTransportConfig};
+use llm_web_agent::mcp_http::origin_allowed;
+use llm_web_agent::mcp_resources::{
+    browser_resource_uri, parse_browser_uri, BrowserResourceKind, SessionEvent, SessionEvents,
+};
+use llm_web_agent::mcp_server::{
+    builtin_tools, create_mcp_router, dispatch_request, MCPRequest, MCPServerState,
+    McpSessionLimits,
+};
+use llm_web_agent::mcp_stdio;
+use llm_web_agent::normalize;
+use llm_web_agent::page_pool::{PagePool, PagePoolConfig, PoolTimeout, PoolablePage};
+use llm_web_agent::page_wait::InFlightRequests;
+use llm_web_agent::product_extraction::{extract_from_page, NoProductData, ProductExtractor};
+use llm_web_agent::prompts::{prompt_arguments, PromptLibrary, PRODUCT_EXTRACTION_PROMPT};
+use llm_web_agent::session_reaper::{SessionExpiry, SessionReaper, SessionTimeouts};
+use llm_web_agent::structured_data::{StructuredData, StructuredDataFormat};
+use llm_web_agent::tools::{
+    ImageContent, LocalTool, McpTool, ToolContext, ToolFailed, ToolOutput, ToolRegistry,
+};
 use llm_web_agent::types::*;
+use llm_web_agent::{create_router, AgentServices, AppState};
 use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[test]
 fn test_browser_action_serialization() {
@@ -110,6 +148,7 @@ fn test_navigation_request_serialization() {
     let nav_request = NavigateRequest {
         session_id: "test-session".to_string(),
         url: "https://example.com".to_string(),
+        wait_until: None,
     };
 
     let serialized = serde_json::to_string(&nav_request).expect("Should serialize");
@@ -155,6 +194,7 @@ fn test_automation_request_serialization() {
             ("email".to_string(), json!("test@example.com")),
             ("name".to_string(), json!("Test User")),
         ])),
+        dry_run: false,
     };
 
     let serialized = serde_json::to_string(&auto_request).expect("Should serialize");
@@ -168,6 +208,25 @@ fn test_automation_request_serialization() {
         Some("https://example.com".to_string())
     );
     assert!(deserialized.context.is_some());
+    assert!(!deserialized.dry_run);
+}
+
+#[test]
+fn test_automation_request_dry_run_defaults_to_false() {
+    let request: AutomationRequest = serde_json::from_value(json!({
+        "session_id": "test-session",
+        "task_description": "Take a screenshot"
+    }))
+    .expect("Should deserialize without dry_run");
+    assert!(!request.dry_run);
+
+    let request: AutomationRequest = serde_json::from_value(json!({
+        "session_id": "test-session",
+        "task_description": "Take a screenshot",
+        "dry_run": true
+    }))
+    .expect("Should deserialize with dry_run");
+    assert!(request.dry_run);
 }
 
 #[test]
@@ -198,6 +257,192 @@ fn test_task_plan_creation() {
     assert_eq!(task_plan.steps[1].id, "step2");
 }
 
+#[test]
+fn test_parse_task_plan_accepts_valid_plan() {
+    let plan = parse_task_plan(&json!({
+        "description": "Search for shoes",
+        "steps": [
+            {
+                "id": "type_query",
+                "action": {"type": "Type", "params": {"selector": "#search", "text": "shoes"}},
+                "description": "Enter the search query"
+            },
+            {
+                "id": "submit",
+                "action": {"type": "Click", "params": {"selector": "#search-button"}},
+                "description": "Submit the search",
+                "expected_outcome": "Results page loads"
+            },
+            {
+                "id": "capture",
+                "action": {"type": "Screenshot"},
+                "description": "Capture the results"
+            }
+        ]
+    }))
+    .expect("Plan should be valid");
+
+    assert_eq!(plan.description, "Search for shoes");
+    assert_eq!(plan.steps.len(), 3);
+    assert!(matches!(plan.steps[1].action, BrowserAction::Click { .. }));
+    assert_eq!(
+        plan.steps[1].expected_outcome.as_deref(),
+        Some("Results page loads")
+    );
+}
+
+#[test]
+fn test_parse_task_plan_reports_every_problem() {
+    let errors = parse_task_plan(&json!({
+        "description": "Broken plan",
+        "steps": [
+            {
+                "id": "navigate",
+                "action": {"type": "Navigate", "params": {"url": "https://example.com"}},
+                "description": "Not a real action"
+            },
+            {
+                "id": "click",
+                "action": {"type": "Click", "params": {"selector": ""}},
+                "description": "Empty selector"
+            },
+            {
+                "id": "click",
+                "action": {"type": "Screenshot"},
+                "description": "Duplicate id"
+            }
+        ]
+    }))
+    .expect_err("Plan should be rejected");
+
+    assert_eq!(errors.len(), 3, "Unexpected errors: {errors:?}");
+    assert!(errors[0].contains("step 1") && errors[0].contains("invalid action"));
+    assert!(errors[1].contains("selector must not be empty"));
+    assert!(errors[2].contains("duplicate step id"));
+}
+
+#[test]
+fn test_parse_task_plan_rejects_missing_steps() {
+    let errors = parse_task_plan(&json!({"description": "No steps"})).expect_err("Should fail");
+    assert!(errors
+        .iter()
+        .any(|e| e.contains("`steps` must be an array")));
+
+    let errors =
+        parse_task_plan(&json!({"steps": "[]"})).expect_err("Empty stringified steps should fail");
+    assert!(errors.iter().any(|e| e.contains("`description`")));
+    assert!(errors.iter().any(|e| e.contains("at least one step")));
+}
+
+#[test]
+fn test_browser_action_schema_covers_every_variant() {
+    let schema = BrowserAction::json_schema();
+    let variants: Vec<&str> = schema["oneOf"]
+        .as_array()
+        .expect("Schema should be a oneOf")
+        .iter()
+        .map(|variant| variant["properties"]["type"]["enum"][0].as_str().unwrap())
+        .collect();
+
+    assert_eq!(
+        variants,
+        vec![
+            "Click",
+            "Type",
+            "Wait",
+            "WaitForElement",
+            "Scroll",
+            "Screenshot",
+            "GetPageSource",
+            "ExecuteScript"
+        ]
+    );
+}
+
+#[test]
+fn test_summarize_dom_lists_interactive_elements_and_text() {
+    let html = r#"
+        <html>
+            <head><title>Shop</title><script>var tracking = "hidden";</script></head>
+            <body>
+                <h1>Blue Widget</h1>
+                <input type="hidden" name="csrf" value="secret">
+                <input type="text" name="q" placeholder="Search products">
+                <button id="add-to-cart">Add to cart</button>
+                <a class="nav-link" href="/next">Next page</a>
+                <p>Only 3 left in stock.</p>
+            </body>
+        </html>
+    "#;
+
+    let summary = summarize_dom(html, 4000);
+
+    assert!(summary.contains("- Blue Widget"));
+    assert!(summary.contains(r#"[input type=text] "Search products" selector: input[name="q"]"#));
+    assert!(summary.contains(r#"[button] "Add to cart" selector: #add-to-cart"#));
+    assert!(summary.contains(r#"href=/next selector: a[href="/next"]"#));
+    assert!(summary.contains("Only 3 left in stock."));
+    assert!(!summary.contains("csrf"), "Hidden inputs should be skipped");
+    assert!(
+        !summary.contains("tracking"),
+        "Script text should be skipped"
+    );
+}
+
+#[test]
+fn test_summarize_dom_truncates() {
+    let html = format!("<html><body><p>{}</p></body></html>", "word ".repeat(1000));
+    let summary = summarize_dom(&html, 100);
+    assert!(summary.ends_with("...[truncated]"));
+    assert!(summary.chars().count() <= 100 + "...[truncated]".len());
+}
+
+#[test]
+fn test_agent_answers_every_tool_call() {
+    let mut response = ChatResponse::tool_call("perform_action", json!({}));
+    let mut second = ChatResponse::tool_call("finish", json!({}))
+        .tool_calls
+        .unwrap();
+    second[0].id = Some("call_2".to_string());
+    let calls = response.tool_calls.as_mut().unwrap();
+    calls[0].id = Some("call_1".to_string());
+    calls.extend(second);
+
+    let replies = replies_to(&response, "Missing action.", "Not run.");
+    assert_eq!(replies.len(), 2);
+    assert!(replies.iter().all(|message| message.role == "tool"));
+    assert_eq!(replies[0].tool_call_id.as_deref(), Some("call_1"));
+    assert_eq!(replies[0].content, "Missing action.");
+    assert_eq!(replies[1].tool_call_id.as_deref(), Some("call_2"));
+    assert_eq!(replies[1].content, "Not run.");
+
+    // A plain text answer is followed up by a user message
+    let replies = replies_to(&ChatResponse::text("thinking..."), "Missing action.", "");
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].role, "user");
+}
+
+#[test]
+fn test_agent_budget_defaults_and_limits() {
+    let mut request = AgentRequest {
+        session_id: "test-session".to_string(),
+        goal: "Find the price".to_string(),
+        target_url: None,
+        max_steps: None,
+        max_duration_seconds: None,
+    };
+
+    let budget = AgentBudget::from_request(&request);
+    assert_eq!(budget.max_steps, 15);
+    assert_eq!(budget.max_duration.as_secs(), 120);
+
+    request.max_steps = Some(10_000);
+    request.max_duration_seconds = Some(0);
+    let budget = AgentBudget::from_request(&request);
+    assert_eq!(budget.max_steps, 50);
+    assert_eq!(budget.max_duration.as_secs(), 1);
+}
+
 #[test]
 fn test_task_result_creation() {
     let task_result = TaskResult {
@@ -271,3 +516,2670 @@ fn test_app_error_display() {
     assert!(mcp_error.to_string().contains("MCP error"));
     assert!(mcp_error.to_string().contains("API key invalid"));
 }
+
+#[test]
+fn test_app_error_classifies_typed_errors() {
+    let cases: Vec<(anyhow::Error, &str, u16)> = vec![
+        (
+            ElementNotFound {
+                selector: "#buy".to_string(),
+            }
+            .into(),
+            "element_not_found",
+            422,
+        ),
+        (
+            NavigationError::Timeout {
+                url: "https://slow.example".to_string(),
+                timeout_ms: 30_000,
+            }
+            .into(),
+            "navigation_timeout",
+            504,
+        ),
+        (
+            NavigationError::Failed {
+                url: "not a url".to_string(),
+                reason: "invalid URL".to_string(),
+            }
+            .into(),
+            "navigation_failed",
+            400,
+        ),
+        (
+            BrowserRestarted {
+                session_id: "session-1".to_string(),
+            }
+            .into(),
+            "browser_crashed",
+            410,
+        ),
+        (
+            PoolTimeout(Duration::from_secs(30)).into(),
+            "browser_busy",
+            503,
+        ),
+        (
+            LlmError::Unreachable {
+                endpoint: "http://localhost:11434".to_string(),
+                reason: "connection refused".to_string(),
+            }
+            .into(),
+            "llm_unavailable",
+            503,
+        ),
+        (
+            LlmError::Api {
+                status: 429,
+                message: "rate limited".to_string(),
+            }
+            .into(),
+            "llm_error",
+            502,
+        ),
+        (
+            LlmError::UnparseableOutput("not JSON".to_string()).into(),
+            "llm_output_unparseable",
+            502,
+        ),
+        (
+            ToolFailed {
+                tool: "extract_clean_text".to_string(),
+                message: "boom".to_string(),
+            }
+            .into(),
+            "tool_failed",
+            502,
+        ),
+    ];
+
+    for (error, code, status) in cases {
+        // Context added on the way up must not hide the typed error
+        let error = error.context("Request failed");
+        let app_error = AppError::from(error);
+        assert_eq!(app_error.code(), code);
+        assert_eq!(app_error.status().as_u16(), status);
+    }
+
+    // Untyped errors fall back to the layer's generic code
+    assert_eq!(
+        AppError::from(anyhow::anyhow!("something odd")).code(),
+        "internal_error"
+    );
+    assert_eq!(
+        AppError::from_browser(anyhow::anyhow!("something odd")).code(),
+        "browser_error"
+    );
+}
+
+#[tokio::test]
+async fn test_app_error_body_carries_code() {
+    let response = AppError::ElementNotFound("Element not found: #buy".to_string()).into_response();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        body,
+        json!({
+            "error": "Element not found: #buy",
+            "code": "element_not_found",
+            "status": 422,
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_llm_providers_report_unreachable_servers() {
+    // Bind then drop a listener so the port is known to be closed
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let endpoint = format!("http://127.0.0.1:{}", port);
+
+    for provider in ["ollama", "openai"] {
+        let config = config_from(&[
+            ("LLM_PROVIDER", provider),
+            ("LLM_ENDPOINT", endpoint.as_str()),
+        ]);
+        let error = config
+            .build_provider()
+            .unwrap()
+            .chat(&[Message::user("hello")], &[])
+            .await
+            .expect_err("Nothing is listening");
+        assert!(
+            matches!(
+                error.downcast_ref::<LlmError>(),
+                Some(LlmError::Unreachable { .. })
+            ),
+            "{provider}: {error}"
+        );
+        assert_eq!(AppError::from(error).code(), "llm_unavailable");
+    }
+
+    // Task planning reports the outage instead of falling back to a stand-in plan
+    let provider = config_from(&[("LLM_PROVIDER", "ollama"), ("LLM_ENDPOINT", &endpoint)])
+        .build_provider()
+        .unwrap();
+    let content = Arc::new(ContentStore::default());
+    let client =
+        LlamaClient::with_provider(provider, Arc::new(builtin_tools(content.clone())), content);
+    let error = client
+        .process_automation_request(&AutomationRequest {
+            session_id: "test-session".to_string(),
+            task_description: "Click the buy button".to_string(),
+            target_url: None,
+            context: None,
+            dry_run: true,
+        })
+        .await
+        .expect_err("Nothing is listening");
+    assert_eq!(AppError::from(error).code(), "llm_unavailable");
+}
+
+fn usd(amount: &str) -> Price {
+    Price {
+        amount: amount.parse().unwrap(),
+        currency: Some("USD".to_string()),
+    }
+}
+
+#[test]
+fn test_parse_price_formats() {
+    let cases = [
+        ("$19.99", "19.99", Some("USD")),
+        ("$1,299.00", "1299.00", Some("USD")),
+        ("1.299,00 €", "1299.00", Some("EUR")),
+        ("1 299,50 EUR", "1299.50", Some("EUR")),
+        ("£5", "5", Some("GBP")),
+        ("CA$ 24.50", "24.50", Some("CAD")),
+        ("USD 1,000", "1000", Some("USD")),
+        ("19,99 zł", "19.99", Some("PLN")),
+        ("$10 - $20", "10", Some("USD")),
+        ("1.299 €", "1299", Some("EUR")),
+        ("€1.299", "1299", Some("EUR")),
+        ("€12.999", "12999", Some("EUR")),
+        ("€1.29", "1.29", Some("EUR")),
+        ("42.00", "42.00", None),
+        ("1.299", "1.299", None),
+    ];
+    for (text, amount, currency) in cases {
+        assert_eq!(
+            normalize::parse_price(text),
+            Some(Price {
+                amount: amount.parse().unwrap(),
+                currency: currency.map(str::to_string),
+            }),
+            "{text}"
+        );
+    }
+    assert_eq!(normalize::parse_price("Price on request"), None);
+}
+
+#[test]
+fn test_parse_currency_ignores_lowercase_words() {
+    // English words that happen to spell currency codes are not currencies
+    assert_eq!(
+        normalize::parse_currency("Try it for $19.99").as_deref(),
+        Some("USD")
+    );
+    for text in ["rub on 5.00", "nok 10", "sek 10", "cad 10"] {
+        assert_eq!(normalize::parse_currency(text), None, "{text}");
+    }
+    assert_eq!(normalize::parse_currency("TRY 10").as_deref(), Some("TRY"));
+
+    // An explicit currency field may use any case
+    let product = normalize::product_from_json(&json!({
+        "name": "Widget",
+        "offers": {"price": "10", "priceCurrency": "sek"}
+    }));
+    assert_eq!(product.price.unwrap().currency.as_deref(), Some("SEK"));
+}
+
+#[test]
+fn test_parse_rating_formats() {
+    let rating = |value, scale, review_count| {
+        Some(Rating {
+            value,
+            scale,
+            review_count,
+        })
+    };
+    assert_eq!(
+        normalize::parse_rating("4.5 out of 5 stars"),
+        rating(4.5, Some(5.0), None)
+    );
+    assert_eq!(
+        normalize::parse_rating("4.2/5 (1,234 reviews)"),
+        rating(4.2, Some(5.0), Some(1234))
+    );
+    assert_eq!(
+        normalize::parse_rating("Rated 4 out of 5 based on 87 ratings"),
+        rating(4.0, Some(5.0), Some(87))
+    );
+    assert_eq!(
+        normalize::parse_rating("3.8 (52)"),
+        rating(3.8, None, Some(52))
+    );
+    assert_eq!(normalize::parse_rating("4.7"), rating(4.7, None, None));
+    assert_eq!(normalize::parse_rating("No reviews yet"), None);
+}
+
+#[test]
+fn test_parse_availability_maps_to_schema_org() {
+    let cases = [
+        ("https://schema.org/InStock", Some(Availability::InStock)),
+        (
+            "http://schema.org/OutOfStock",
+            Some(Availability::OutOfStock),
+        ),
+        ("In Stock", Some(Availability::InStock)),
+        ("Currently unavailable.", Some(Availability::OutOfStock)),
+        ("Sold out", Some(Availability::SoldOut)),
+        ("Available for pre-order", Some(Availability::PreOrder)),
+        (
+            "Only 3 left in stock",
+            Some(Availability::LimitedAvailability),
+        ),
+        ("Backordered", Some(Availability::BackOrder)),
+        ("Call us", None),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(normalize::parse_availability(text), expected, "{text}");
+    }
+}
+
+#[test]
+fn test_product_from_schema_org_json_ld() {
+    let product = normalize::product_from_json(&json!({
+        "@type": "Product",
+        "name": "Trail Shoe",
+        "brand": {"@type": "Brand", "name": "Acme"},
+        "image": [{"@type": "ImageObject", "url": "https://shop.example/1.jpg"}, "https://shop.example/2.jpg"],
+        "gtin13": "0123456789012",
+        "mpn": "TS-100",
+        "offers": [
+            {"@type": "Offer", "sku": "TS-100-9", "price": 119.99, "priceCurrency": "USD",
+             "availability": "https://schema.org/InStock"},
+            {"@type": "Offer", "sku": "TS-100-10", "price": "124.99", "priceCurrency": "USD",
+             "availability": "https://schema.org/OutOfStock"}
+        ],
+        "aggregateRating": {"ratingValue": 4.6, "bestRating": "5", "reviewCount": "1,024"}
+    }));
+
+    assert_eq!(product.name.as_deref(), Some("Trail Shoe"));
+    assert_eq!(product.brand.as_deref(), Some("Acme"));
+    assert_eq!(product.price, Some(usd("119.99")));
+    assert_eq!(product.availability, Some(Availability::InStock));
+    assert_eq!(
+        product.rating,
+        Some(Rating {
+            value: 4.6,
+            scale: Some(5.0),
+            review_count: Some(1024),
+        })
+    );
+    assert_eq!(
+        product.images,
+        vec!["https://shop.example/1.jpg", "https://shop.example/2.jpg"]
+    );
+    assert_eq!(product.gtin.as_deref(), Some("0123456789012"));
+    assert_eq!(product.mpn.as_deref(), Some("TS-100"));
+    assert_eq!(product.sku.as_deref(), Some("TS-100-9"));
+
+    // Several offers become variants
+    assert_eq!(product.variants.len(), 2);
+    assert_eq!(product.variants[1].sku.as_deref(), Some("TS-100-10"));
+    assert_eq!(product.variants[1].price, Some(usd("124.99")));
+    assert_eq!(
+        product.variants[1].availability,
+        Some(Availability::OutOfStock)
+    );
+
+    // Amounts serialize as exact decimal strings, availability by its schema.org name
+    let serialized = serde_json::to_value(&product).unwrap();
+    assert_eq!(
+        serialized["price"],
+        json!({"amount": "119.99", "currency": "USD"})
+    );
+    assert_eq!(serialized["availability"], "InStock");
+}
+
+#[test]
+fn test_product_variants_carry_attributes() {
+    let product = normalize::product_from_json(&json!({
+        "name": "Tee",
+        "hasVariant": [
+            {"name": "Tee - Red / M", "sku": "TEE-R-M", "color": "Red", "size": "M",
+             "offers": {"price": "15.00", "priceCurrency": "GBP"}},
+            {"name": "Tee - Blue / L", "attributes": {"color": "Blue", "size": "L"}, "price": "£17"}
+        ]
+    }));
+
+    let red = &product.variants[0];
+    assert_eq!(red.attributes.get("color").map(String::as_str), Some("Red"));
+    assert_eq!(red.attributes.get("size").map(String::as_str), Some("M"));
+    assert_eq!(red.price.as_ref().unwrap().currency.as_deref(), Some("GBP"));
+
+    let blue = &product.variants[1];
+    assert_eq!(blue.attributes.get("size").map(String::as_str), Some("L"));
+    assert_eq!(blue.price.as_ref().unwrap().amount, "17".parse().unwrap());
+}
+
+fn config_from(vars: &[(&str, &str)]) -> LlmConfig {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    LlmConfig::from_lookup(|key| vars.get(key).cloned())
+}
+
+#[test]
+fn test_llm_config_defaults_to_local_ollama() {
+    let config = config_from(&[]);
+    assert_eq!(config.provider, ProviderKind::Ollama);
+    assert_eq!(config.endpoint, "http://localhost:11434");
+    assert_eq!(config.model, "llama3.2:latest");
+    assert!(config.api_key.is_none());
+}
+
+#[test]
+fn test_llm_config_honors_mistral_mode() {
+    let local = config_from(&[
+        ("MISTRAL_MODE", "local"),
+        ("MISTRAL_LOCAL_ENDPOINT", "http://mistral-local:11434"),
+    ]);
+    assert_eq!(local.provider, ProviderKind::Ollama);
+    assert_eq!(local.endpoint, "http://mistral-local:11434");
+
+    let cloud = config_from(&[("MISTRAL_MODE", "cloud"), ("MISTRAL_API_KEY", "secret")]);
+    assert_eq!(cloud.provider, ProviderKind::OpenAi);
+    assert_eq!(cloud.endpoint, "https://api.mistral.ai/v1/chat/completions");
+    assert_eq!(cloud.model, "mistral-large-latest");
+    assert_eq!(cloud.api_key.as_deref(), Some("secret"));
+}
+
+#[test]
+fn test_llm_config_provider_override_wins() {
+    let config = config_from(&[
+        ("LLM_PROVIDER", "openai"),
+        ("MISTRAL_MODE", "local"),
+        ("LLM_ENDPOINT", "http://vllm:8000/v1"),
+        ("LLM_MODEL", "qwen2.5"),
+        ("LLM_API_KEY", "token"),
+    ]);
+    assert_eq!(config.provider, ProviderKind::OpenAi);
+    assert_eq!(config.endpoint, "http://vllm:8000/v1");
+    assert_eq!(config.model, "qwen2.5");
+    assert_eq!(config.api_key.as_deref(), Some("token"));
+
+    let mock = config_from(&[("LLM_PROVIDER", "mock")]);
+    assert_eq!(mock.provider, ProviderKind::Mock);
+}
+
+#[test]
+fn test_chat_completions_url_normalization() {
+    assert_eq!(
+        chat_completions_url("https://api.mistral.ai/v1/chat/completions"),
+        "https://api.mistral.ai/v1/chat/completions"
+    );
+    assert_eq!(
+        chat_completions_url("http://vllm:8000/v1/"),
+        "http://vllm:8000/v1/chat/completions"
+    );
+    assert_eq!(
+        chat_completions_url("http://llama-cpp:8080"),
+        "http://llama-cpp:8080/v1/chat/completions"
+    );
+}
+
+#[tokio::test]
+async fn test_mock_provider_is_deterministic() {
+    let provider = MockProvider::new(r#"{"name": "Widget"}"#);
+    let messages = vec![Message::user("Extract the product")];
+
+    let first = provider
+        .chat(&messages, &[])
+        .await
+        .expect("Mock should answer");
+    let second = provider
+        .chat(&messages, &[])
+        .await
+        .expect("Mock should answer");
+
+    assert_eq!(provider.name(), "mock");
+    assert_eq!(first.content.as_deref(), Some(r#"{"name": "Widget"}"#));
+    assert_eq!(first.content, second.content);
+    assert!(first.tool_calls.is_none());
+}
+
+#[tokio::test]
+async fn test_scripted_provider_replays_turns_in_order() {
+    let provider = ScriptedProvider::from_json(json!({
+        "turns": [
+            {"tool_calls": [{"function": {"name": "analyze_page_structure", "arguments": {}}}]},
+            {"content": "done"}
+        ]
+    }))
+    .expect("Script should load");
+    let messages = vec![Message::user("Extract the product")];
+
+    let first = provider.chat(&messages, &[]).await.unwrap();
+    let second = provider.chat(&messages, &[]).await.unwrap();
+
+    assert_eq!(
+        first.tool_calls.unwrap()[0].function.name,
+        "analyze_page_structure"
+    );
+    assert_eq!(second.content.as_deref(), Some("done"));
+    assert_eq!(provider.remaining(), 0);
+    assert_eq!(provider.requests().len(), 2);
+    assert!(provider.chat(&messages, &[]).await.is_err());
+}
+
+const PRODUCT_PAGE: &str = r#"<html><head><title>Widget</title></head>
+<body><h1 class="product-title">Acme Widget</h1><span class="price">$19.99</span></body></html>"#;
+
+#[tokio::test]
+async fn test_builtin_tool_registry_runs_tools_in_process() {
+    let mut registry = builtin_tools(Arc::new(ContentStore::default()));
+    let names: Vec<String> = registry.definitions().into_iter().map(|t| t.name).collect();
+    assert_eq!(
+        names,
+        vec![
+            "extract_clean_text",
+            "extract_product_data",
+            "extract_structured_data",
+            "extract_by_selectors",
+            "analyze_page_structure"
+        ]
+    );
+
+    // Duplicate names are ignored rather than shadowing the built-in tool
+    registry.register(Arc::new(LocalTool::new(
+        "extract_clean_text",
+        "Shadow",
+        json!({}),
+        |_| Ok(json!("shadow")),
+    )));
+    assert_eq!(registry.definitions().len(), 5);
+    assert_eq!(registry.llm_tools().len(), 5);
+
+    let result = registry
+        .call(
+            "extract_clean_text",
+            &json!({ "html_content": PRODUCT_PAGE }),
+            &ToolContext::default(),
+        )
+        .await
+        .expect("Built-in tool should run");
+    assert_eq!(result.value["clean_text"], "Acme Widget $19.99");
+
+    let error = registry
+        .call("no_such_tool", &json!({}), &ToolContext::default())
+        .await
+        .unwrap_err();
+    assert_eq!(error, "Unknown tool: no_such_tool");
+}
+
+#[test]
+fn test_content_store_resolves_ids_and_uris() {
+    let store = ContentStore::new(Duration::from_secs(60), 1024);
+    let page = store.insert("https://shop.example/widget", PRODUCT_PAGE.to_string());
+
+    assert_eq!(page.uri(), format!("resource://content/{}", page.id));
+    assert_eq!(store.get(&page.id).unwrap().html, PRODUCT_PAGE);
+    assert_eq!(
+        store.get(&page.uri()).unwrap().url,
+        "https://shop.example/widget"
+    );
+    assert!(store.get("missing").is_none());
+    assert_eq!(store.total_bytes(), PRODUCT_PAGE.len());
+}
+
+#[test]
+fn test_content_store_evicts_oldest_over_memory_cap() {
+    let store = ContentStore::new(Duration::from_secs(60), 25);
+    let first = store.insert("https://a.example", "a".repeat(10));
+    let second = store.insert("https://b.example", "b".repeat(10));
+    let third = store.insert("https://c.example", "c".repeat(10));
+
+    assert!(store.get(&first.id).is_none());
+    assert!(store.get(&second.id).is_some());
+    assert!(store.get(&third.id).is_some());
+    assert_eq!(store.total_bytes(), 20);
+
+    // An oversized page replaces everything rather than being dropped
+    let huge = store.insert("https://d.example", "d".repeat(100));
+    assert_eq!(store.list().len(), 1);
+    assert_eq!(store.list()[0].id, huge.id);
+}
+
+#[test]
+fn test_content_store_expires_after_ttl() {
+    let store = ContentStore::new(Duration::from_millis(20), 1024);
+    let page = store.insert("https://shop.example", PRODUCT_PAGE.to_string());
+    assert!(store.get(&page.id).is_some());
+
+    std::thread::sleep(Duration::from_millis(40));
+    assert!(store.get(&page.id).is_none());
+    assert_eq!(store.total_bytes(), 0);
+}
+
+#[tokio::test]
+async fn test_builtin_tools_accept_content_ref() {
+    let content = Arc::new(ContentStore::default());
+    let registry = builtin_tools(content.clone());
+    let page = content.insert("https://shop.example/widget", PRODUCT_PAGE.to_string());
+
+    let by_ref = registry
+        .call(
+            "extract_clean_text",
+            &json!({ "content_ref": page.uri() }),
+            &ToolContext::default(),
+        )
+        .await
+        .expect("content_ref should resolve");
+    assert_eq!(by_ref.value["clean_text"], "Acme Widget $19.99");
+
+    let error = registry
+        .call(
+            "extract_clean_text",
+            &json!({ "content_ref": "expired" }),
+            &ToolContext::default(),
+        )
+        .await
+        .unwrap_err();
+    assert!(error.contains("Unknown or expired content_ref"));
+}
+
+fn scripted_client(turns: Vec<ChatResponse>) -> (LlamaClient, Arc<ScriptedProvider>) {
+    let provider = Arc::new(ScriptedProvider::new(turns));
+    let content = Arc::new(ContentStore::default());
+    let client = LlamaClient::with_provider(
+        provider.clone(),
+        Arc::new(builtin_tools(content.clone())),
+        content,
+    );
+    (client, provider)
+}
+
+#[tokio::test]
+async fn test_task_planning_answers_the_rejected_tool_call() {
+    let mut invalid = ChatResponse::tool_call("create_task_plan", json!({"steps": []}));
+    invalid.tool_calls.as_mut().unwrap()[0].id = Some("call_1".to_string());
+    let valid = ChatResponse::tool_call(
+        "create_task_plan",
+        json!({
+            "description": "Take a screenshot",
+            "steps": [{"id": "step_1", "action": {"type": "Screenshot"}, "description": "Capture"}]
+        }),
+    );
+    let (client, provider) = scripted_client(vec![invalid, valid]);
+
+    let plan = client
+        .process_automation_request(&AutomationRequest {
+            session_id: "test-session".to_string(),
+            task_description: "Take a screenshot".to_string(),
+            target_url: None,
+            context: None,
+            dry_run: true,
+        })
+        .await
+        .unwrap();
+    assert_eq!(plan.steps.len(), 1);
+
+    // The retry answers the assistant's tool call before anything else
+    let requests = provider.requests();
+    let retry = &requests[1];
+    let call = retry
+        .iter()
+        .position(|message| message.tool_calls.is_some())
+        .unwrap();
+    let reply = &retry[call + 1];
+    assert_eq!(reply.role, "tool");
+    assert_eq!(reply.tool_call_id.as_deref(), Some("call_1"));
+    assert!(reply.content.contains("invalid"));
+    assert_eq!(retry.len(), call + 2);
+}
+
+#[tokio::test]
+async fn test_automation_task_rejects_unknown_session_before_planning() {
+    let (client, provider) = scripted_client(vec![]);
+    let state = AppState {
+        browser_sessions: Default::default(),
+        llama_client: Arc::new(client),
+        mcp_state: Arc::new(MCPServerState::new()),
+        session_events: SessionEvents::new(),
+    };
+    let app = create_router().with_state(state);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}/api", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let response = reqwest::Client::new()
+        .post(format!("{base}/automation/task"))
+        .json(&json!({ "session_id": "no-such-session", "task_description": "Click buy" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "session_not_found");
+    assert!(provider.requests().is_empty());
+}
+
+#[tokio::test]
+async fn test_extraction_loop_dispatches_tools_then_parses_json() {
+    let (client, provider) = scripted_client(vec![
+        ChatResponse::tool_call("analyze_page_structure", json!({})),
+        ChatResponse::tool_call("extract_product_data", json!({})),
+        ChatResponse::text(
+            r#"Here you go: {"name": "Acme Widget", "price": "$19.99", "brand": "Acme"}"#,
+        ),
+    ]);
+
+    let product = client
+        .extract_product_information("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .expect("Extraction should succeed");
+
+    assert_eq!(product.name.as_deref(), Some("Acme Widget"));
+    assert_eq!(product.price, Some(usd("19.99")));
+    assert_eq!(product.brand.as_deref(), Some("Acme"));
+    assert_eq!(provider.remaining(), 0);
+
+    // The final request carries both tool results, produced by the real MCP tools
+    let requests = provider.requests();
+    let last = requests.last().unwrap();
+    let tool_results: Vec<_> = last.iter().filter(|m| m.role == "tool").collect();
+    assert_eq!(tool_results.len(), 2);
+    assert!(tool_results[1].content.contains("Acme Widget"));
+
+    // The model is given a content_ref, never the page itself
+    assert!(last[1].content.contains("content_ref"));
+    assert!(!last.iter().any(|m| m.content.contains("<h1")));
+}
+
+#[tokio::test]
+async fn test_extraction_loop_parses_plain_text_answer() {
+    let (client, _) = scripted_client(vec![ChatResponse::text(
+        "Name: Acme Widget\nPrice: $19.99\nBrand: Acme",
+    )]);
+
+    let product = client
+        .extract_product_information("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .unwrap();
+
+    assert_eq!(product.name.as_deref(), Some("Acme Widget"));
+    assert_eq!(product.price, Some(usd("19.99")));
+    assert_eq!(product.brand.as_deref(), Some("Acme"));
+}
+
+#[tokio::test]
+async fn test_extraction_loop_keeps_numeric_and_structured_values() {
+    let (client, _) = scripted_client(vec![ChatResponse::text(
+        r#"{"name": "Acme Widget", "price": 1299, "currency": "eur",
+            "rating": {"ratingValue": "4.5", "bestRating": 5, "reviewCount": 87},
+            "availability": "Only 2 left", "images": ["/a.jpg", "/b.jpg"], "sku": "AW-1"}"#,
+    )]);
+
+    let product = client
+        .extract_product_information("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        product.price,
+        Some(Price {
+            amount: "1299".parse().unwrap(),
+            currency: Some("EUR".to_string()),
+        })
+    );
+    assert_eq!(
+        product.rating,
+        Some(Rating {
+            value: 4.5,
+            scale: Some(5.0),
+            review_count: Some(87),
+        })
+    );
+    assert_eq!(
+        product.availability,
+        Some(Availability::LimitedAvailability)
+    );
+    assert_eq!(product.images, vec!["/a.jpg", "/b.jpg"]);
+    assert_eq!(product.sku.as_deref(), Some("AW-1"));
+}
+
+#[tokio::test]
+async fn test_extraction_loop_falls_back_after_turn_limit() {
+    let turns = (0..5)
+        .map(|_| ChatResponse::tool_call("extract_clean_text", json!({})))
+        .collect();
+    let (client, provider) = scripted_client(turns);
+
+    let product = client
+        .extract_product_information("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        product.name.as_deref(),
+        Some("Unable to extract product name with MCP tools")
+    );
+    assert_eq!(provider.requests().len(), 5);
+}
+
+#[tokio::test]
+async fn test_extraction_loop_surfaces_tool_and_provider_errors() {
+    let (client, _) = scripted_client(vec![ChatResponse::tool_call("no_such_tool", json!({}))]);
+    let error = client
+        .extract_product_information("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .expect_err("Unknown tools should fail");
+    assert!(error.to_string().contains("MCP tool error"));
+    assert_eq!(AppError::from(error).code(), "tool_failed");
+
+    // An exhausted script behaves like an unreachable model
+    let (client, _) = scripted_client(Vec::new());
+    assert!(client
+        .extract_product_information("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .is_err());
+}
+
+const JSON_LD_PAGE: &str = r#"<html><head>
+<script type="application/ld+json">{"@context": "https://schema.org", "@type": "Product",
+  "name": "Trail Shoe", "description": "A light trail running shoe", "brand": {"name": "Acme"},
+  "image": "/img/shoe.jpg", "sku": "TS-100",
+  "offers": {"price": 119.99, "priceCurrency": "USD", "availability": "https://schema.org/InStock"},
+  "aggregateRating": {"ratingValue": 4.6, "reviewCount": 210}}</script>
+</head><body><h1 class="product-title">Trail Shoe (selector)</h1></body></html>"#;
+
+fn source(product: &ProductInfo, field: &str) -> (FieldSource, f64) {
+    let provenance = product.sources[field];
+    (provenance.source, provenance.confidence)
+}
+
+#[tokio::test]
+async fn test_product_pipeline_skips_llm_when_page_data_is_complete() {
+    let (client, provider) = scripted_client(Vec::new());
+    let product = ProductExtractor::new(Arc::new(client))
+        .extract("https://shop.example/p/shoe", JSON_LD_PAGE)
+        .await
+        .unwrap();
+
+    assert!(provider.requests().is_empty());
+    assert_eq!(product.name.as_deref(), Some("Trail Shoe"));
+    assert_eq!(product.price, Some(usd("119.99")));
+    assert_eq!(product.images, vec!["https://shop.example/img/shoe.jpg"]);
+    assert_eq!(source(&product, "name"), (FieldSource::JsonLd, 0.95));
+    assert_eq!(source(&product, "sku"), (FieldSource::JsonLd, 0.95));
+    assert!(!product.sources.contains_key("gtin"));
+}
+
+#[test]
+fn test_product_pipeline_reads_microdata_and_open_graph() {
+    let page = r#"<html><head>
+<meta property="og:type" content="product">
+<meta property="og:title" content="Mug | Example Shop">
+<meta property="og:image" content="https://cdn.example/mug.jpg">
+<meta property="product:price:amount" content="12.50">
+<meta property="product:price:currency" content="EUR">
+</head><body>
+<div itemscope itemtype="https://schema.org/Product">
+  <h1 itemprop="name">Enamel Mug</h1>
+  <div itemprop="offers" itemscope itemtype="https://schema.org/Offer">
+    <link itemprop="availability" href="https://schema.org/OutOfStock">
+  </div>
+  <div itemprop="aggregateRating" itemscope itemtype="https://schema.org/AggregateRating">
+    <span itemprop="ratingValue">4.1</span> / <span itemprop="bestRating">5</span>
+  </div>
+</div>
+</body></html>"#;
+
+    let product = extract_from_page("https://shop.example/mug", page);
+    assert_eq!(product.name.as_deref(), Some("Enamel Mug"));
+    assert_eq!(source(&product, "name"), (FieldSource::Microdata, 0.9));
+    assert_eq!(product.availability, Some(Availability::OutOfStock));
+    assert_eq!(product.rating.as_ref().unwrap().scale, Some(5.0));
+
+    // Microdata had no price or image, so OpenGraph supplies them
+    assert_eq!(
+        product.price,
+        Some(Price {
+            amount: "12.50".parse().unwrap(),
+            currency: Some("EUR".to_string()),
+        })
+    );
+    assert_eq!(source(&product, "price"), (FieldSource::OpenGraph, 0.8));
+    assert_eq!(product.images, vec!["https://cdn.example/mug.jpg"]);
+}
+
+#[test]
+fn test_product_pipeline_prefers_site_selectors_over_generic_open_graph() {
+    let page = r#"<html><head>
+<meta property="og:title" content="Amazon.com: Online Shopping">
+<meta property="og:image" content="https://m.media-amazon.com/logo.png">
+</head><body><span id="productTitle">Real Widget</span></body></html>"#;
+
+    let product = extract_from_page("https://www.amazon.com/dp/B000000001", page);
+    assert_eq!(product.name.as_deref(), Some("Real Widget"));
+    assert_eq!(source(&product, "name"), (FieldSource::Selector, 0.75));
+
+    // OpenGraph still fills what the site selectors lack
+    assert_eq!(product.images, vec!["https://m.media-amazon.com/logo.png"]);
+    assert_eq!(source(&product, "images"), (FieldSource::OpenGraph, 0.6));
+}
+
+#[test]
+fn test_product_pipeline_prefers_site_selectors_over_generic_ones() {
+    let page = r#"<html><body class="woocommerce">
+<h1 class="product_title">Canvas Tote</h1>
+<div class="summary"><p class="price"><del><span class="woocommerce-Price-amount">£30.00</span></del>
+<ins><span class="woocommerce-Price-amount">£24.00</span></ins></p>
+<p class="stock in-stock">3 in stock</p></div>
+<span class="sku">TOTE-1</span>
+<div class="brand">Example Goods</div>
+</body></html>"#;
+
+    let product = extract_from_page("https://totes.example/tote", page);
+    assert_eq!(product.name.as_deref(), Some("Canvas Tote"));
+    assert_eq!(
+        product.price,
+        Some(Price {
+            amount: "24.00".parse().unwrap(),
+            currency: Some("GBP".to_string()),
+        })
+    );
+    assert_eq!(product.availability, Some(Availability::InStock));
+    assert_eq!(source(&product, "price"), (FieldSource::Selector, 0.75));
+    assert_eq!(product.sku.as_deref(), Some("TOTE-1"));
+
+    // Fields the platform's selectors don't cover fall through to the generic ones
+    assert_eq!(product.brand.as_deref(), Some("Example Goods"));
+    assert_eq!(source(&product, "brand"), (FieldSource::Selector, 0.5));
+}
+
+#[tokio::test]
+async fn test_product_pipeline_asks_llm_only_for_missing_fields() {
+    let (client, provider) = scripted_client(vec![ChatResponse::text(
+        r#"{"name": "Something else", "brand": "Acme", "availability": "in stock"}"#,
+    )]);
+    let product = ProductExtractor::new(Arc::new(client))
+        .extract("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .unwrap();
+
+    // The selectors found the name and price; the model can't override them
+    assert_eq!(product.name.as_deref(), Some("Acme Widget"));
+    assert_eq!(source(&product, "name"), (FieldSource::Selector, 0.5));
+    assert_eq!(product.price, Some(usd("19.99")));
+    assert_eq!(product.brand.as_deref(), Some("Acme"));
+    assert_eq!(source(&product, "brand"), (FieldSource::Llm, 0.6));
+    assert_eq!(product.availability, Some(Availability::InStock));
+
+    let request = &provider.requests()[0];
+    let ask = &request.last().unwrap().content;
+    assert!(ask.contains("Only look for"));
+    assert!(ask.contains("brand"));
+    assert!(!ask.contains("price"));
+}
+
+#[tokio::test]
+async fn test_product_pipeline_survives_llm_failures() {
+    // An unreachable model still leaves the page's own data
+    let (client, _) = scripted_client(Vec::new());
+    let product = ProductExtractor::new(Arc::new(client))
+        .extract("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .unwrap();
+    assert_eq!(product.name.as_deref(), Some("Acme Widget"));
+    assert!(product.brand.is_none());
+
+    // A model that never answers gives an error instead of a made-up product
+    let turns = (0..5)
+        .map(|_| ChatResponse::tool_call("extract_clean_text", json!({})))
+        .collect();
+    let (client, _) = scripted_client(turns);
+    let error = ProductExtractor::new(Arc::new(client))
+        .extract("https://shop.example/blank", "<html><body></body></html>")
+        .await
+        .unwrap_err();
+    assert!(error.downcast_ref::<NoProductData>().is_some());
+    assert_eq!(AppError::from(error).code(), "product_not_found");
+
+    // Without an LLM only the deterministic stages run
+    let product = ProductExtractor::without_llm()
+        .extract("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .unwrap();
+    assert_eq!(product.price, Some(usd("19.99")));
+}
+
+const GRAPH_PAGE: &str = r##"<html><head>
+<script type="application/ld+json"><!--
+{"@context": "https://schema.org", "@graph": [
+  {"@type": "Organization", "@id": "#acme", "name": "Acme"},
+  {"@type": ["Product", "Thing"], "@id": "#boot", "schema:name": "Hiking Boot",
+   "brand": {"@id": "#acme"}, "image": {"@type": "ImageObject", "url": "https://shop.example/boot.jpg"},
+   "offers": {"@type": "AggregateOffer", "lowPrice": "89.00", "highPrice": "99.00", "priceCurrency": "EUR",
+     "offers": [
+       {"@type": "Offer", "sku": "BOOT-41", "price": "89.00", "availability": "http://schema.org/InStock"},
+       {"@type": "Offer", "sku": "BOOT-46", "price": "99.00", "availability": "http://schema.org/BackOrder"}
+     ]}},
+  {"@type": "WebPage", "@id": "#page", "mainEntity": {"@id": "#boot"}}
+]}
+--></script>
+</head><body></body></html>"##;
+
+#[test]
+fn test_structured_data_flattens_json_ld_graphs() {
+    let data = StructuredData::from_html(GRAPH_PAGE);
+    assert_eq!(data.entities.len(), 3);
+    assert!(data
+        .entities
+        .iter()
+        .all(|entity| entity.format == StructuredDataFormat::JsonLd));
+
+    // The WebPage's mainEntity resolves to the same product, which is only found once
+    let products = data.products();
+    assert_eq!(products.len(), 1);
+    let boot = &products[0];
+    assert_eq!(boot.types, vec!["Product", "Thing"]);
+    assert_eq!(boot.id.as_deref(), Some("#boot"));
+    assert_eq!(boot.properties["name"], "Hiking Boot");
+    assert_eq!(boot.properties["brand"]["name"], "Acme");
+
+    let product = normalize::product_from_json(&boot.to_json());
+    assert_eq!(product.brand.as_deref(), Some("Acme"));
+    assert_eq!(
+        product.price,
+        Some(Price {
+            amount: "89.00".parse().unwrap(),
+            currency: Some("EUR".to_string()),
+        })
+    );
+    assert_eq!(product.images, vec!["https://shop.example/boot.jpg"]);
+    // The AggregateOffer's offers are the variants
+    assert_eq!(product.variants.len(), 2);
+    assert_eq!(
+        product.variants[1].availability,
+        Some(Availability::BackOrder)
+    );
+    assert_eq!(
+        product.variants[1]
+            .price
+            .as_ref()
+            .unwrap()
+            .currency
+            .as_deref(),
+        Some("EUR")
+    );
+}
+
+#[test]
+fn test_structured_data_reads_microdata_rdfa_and_open_graph() {
+    let page = r#"<html><head>
+<meta property="og:type" content="og:product">
+<meta property="og:title" content="Lamp">
+<meta name="twitter:image" content="https://shop.example/lamp.jpg">
+<meta property="product:price:amount" content="45">
+<meta property="product:price:currency" content="USD">
+</head><body>
+<div itemscope itemtype="http://schema.org/Product" itemid="urn:sku:LAMP-1">
+  <span itemprop="name">Desk Lamp</span>
+  <img itemprop="image" src="/lamp-1.jpg"><img itemprop="image" src="/lamp-2.jpg">
+  <div itemprop="offers" itemscope itemtype="http://schema.org/Offer">
+    <meta itemprop="priceCurrency" content="USD"><span itemprop="price" content="45.00">$45</span>
+  </div>
+</div>
+<div itemscope itemtype="http://schema.org/Review"><span itemprop="author">Sam</span></div>
+<div vocab="https://schema.org/" typeof="Product">
+  <span property="name">Floor Lamp</span>
+  <div property="offers" typeof="Offer">
+    <span property="price" content="120.00">$120</span>
+    <link property="availability" href="https://schema.org/InStock">
+  </div>
+</div>
+</body></html>"#;
+
+    let data = StructuredData::from_html(page);
+    let formats: Vec<StructuredDataFormat> =
+        data.entities.iter().map(|entity| entity.format).collect();
+    assert_eq!(
+        formats,
+        vec![
+            StructuredDataFormat::Microdata,
+            StructuredDataFormat::Microdata,
+            StructuredDataFormat::Rdfa,
+            StructuredDataFormat::OpenGraph,
+        ]
+    );
+
+    let desk = &data.entities[0];
+    assert_eq!(desk.types, vec!["Product"]);
+    assert_eq!(desk.id.as_deref(), Some("urn:sku:LAMP-1"));
+    assert_eq!(
+        desk.properties["image"],
+        json!(["/lamp-1.jpg", "/lamp-2.jpg"])
+    );
+    assert_eq!(
+        desk.properties["offers"],
+        json!({"@type": "Offer", "priceCurrency": "USD", "price": "45.00"})
+    );
+    assert!(data.entities[1].is_a("Review"));
+
+    let floor = normalize::product_from_json(&data.entities[2].to_json());
+    assert_eq!(floor.name.as_deref(), Some("Floor Lamp"));
+    assert_eq!(floor.price.unwrap().amount, "120.00".parse().unwrap());
+    assert_eq!(floor.availability, Some(Availability::InStock));
+
+    let open_graph = &data.entities[3];
+    assert!(open_graph.is_product());
+    let tagged = normalize::product_from_json(&open_graph.to_json());
+    assert_eq!(tagged.name.as_deref(), Some("Lamp"));
+    assert_eq!(tagged.price, Some(usd("45")));
+    assert_eq!(tagged.images, vec!["https://shop.example/lamp.jpg"]);
+
+    assert_eq!(data.products().len(), 3);
+}
+
+#[tokio::test]
+async fn test_structured_data_tool_filters_by_type() {
+    let registry = builtin_tools(Arc::new(ContentStore::default()));
+    let result = registry
+        .call(
+            "extract_structured_data",
+            &json!({ "html_content": GRAPH_PAGE, "types": ["Organization"] }),
+            &ToolContext::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(result.value["entities"].as_array().unwrap().len(), 1);
+    assert_eq!(result.value["entities"][0]["properties"]["name"], "Acme");
+    assert_eq!(result.value["counts"]["json_ld"], 1);
+
+    // Product data now comes from @graph containers too
+    let result = registry
+        .call(
+            "extract_product_data",
+            &json!({ "html_content": GRAPH_PAGE }),
+            &ToolContext::default(),
+        )
+        .await
+        .unwrap();
+    let extracted = &result.value["extracted_data"];
+    assert_eq!(extracted["name"], "Hiking Boot");
+    assert_eq!(extracted["price"], "89.00");
+    assert_eq!(extracted["high_price"], "99.00");
+    assert_eq!(extracted["brand"], "Acme");
+}
+
+#[test]
+fn test_product_pipeline_tags_rdfa_fields() {
+    let page = r#"<html><body><div vocab="https://schema.org/" typeof="Product">
+<h1 property="name">Floor Lamp</h1><span property="sku">FL-2</span></div></body></html>"#;
+    let product = extract_from_page("https://shop.example/lamp", page);
+    assert_eq!(product.sku.as_deref(), Some("FL-2"));
+    assert_eq!(source(&product, "name"), (FieldSource::Rdfa, 0.9));
+}
+
+#[tokio::test]
+async fn test_mcp_stdio_answers_requests_line_by_line() {
+    let input = [
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}).to_string(),
+        json!({"jsonrpc": "2.0", "method": "notifications/initialized"}).to_string(),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}).to_string(),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "tools/call",
+            "params": {
+                "name": "extract_clean_text",
+                "arguments": {"html_content": PRODUCT_PAGE}
+            }
+        })
+        .to_string(),
+        "not json".to_string(),
+    ]
+    .join("\n");
+
+    let state = MCPServerState::new();
+    let mut output = Vec::new();
+    mcp_stdio::serve(&state, input.as_bytes(), &mut output)
+        .await
+        .expect("Loop should end cleanly at end of input");
+
+    let responses: Vec<serde_json::Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Every line should be JSON"))
+        .collect();
+
+    // The notification gets no reply
+    assert_eq!(responses.len(), 4);
+    assert_eq!(responses[0]["id"], 1);
+    assert!(responses[0].get("error").is_none());
+    assert_eq!(responses[1]["result"]["tools"].as_array().unwrap().len(), 5);
+    assert_eq!(responses[2]["result"]["isError"], false);
+    assert_eq!(
+        responses[2]["result"]["structuredContent"]["clean_text"],
+        "Acme Widget $19.99"
+    );
+    assert_eq!(responses[2]["result"]["content"][0]["type"], "text");
+    assert_eq!(responses[3]["error"]["code"], -32700);
+}
+
+#[test]
+fn test_mcp_stdio_binary_keeps_logs_off_stdout() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_llm-web-agent"))
+        .arg("--mcp-stdio")
+        .env("RUST_LOG", "info")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Binary should start");
+
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"});
+    writeln!(child.stdin.take().unwrap(), "{}", request).unwrap();
+
+    let output = child.wait_with_output().expect("Binary should exit at EOF");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "stdout must only carry protocol messages");
+    let response: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    let tools = response["result"]["tools"].as_array().unwrap();
+    assert!(tools.iter().any(|tool| tool["name"] == "fetch_page"));
+
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Serving MCP over stdio"));
+}
+
+/// Serve the MCP routes on an ephemeral local port and return the `/mcp` URL
+async fn spawn_mcp_server(state: Arc<MCPServerState>) -> String {
+    let router = create_mcp_router::<Arc<MCPServerState>>().with_state(state);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Should bind a local port");
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    format!("http://{}/mcp", addr)
+}
+
+#[tokio::test]
+async fn test_mcp_http_session_handshake_and_batches() {
+    let state = Arc::new(MCPServerState::new());
+    let url = spawn_mcp_server(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(&url)
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {"protocolVersion": "2025-03-26", "capabilities": {}}
+        }))
+        .send()
+        .await
+        .unwrap();
+    let session_id = response
+        .headers()
+        .get("mcp-session-id")
+        .expect("initialize should assign a session")
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["result"]["protocolVersion"], "2025-03-26");
+
+    let initialized = client
+        .post(&url)
+        .header("mcp-session-id", &session_id)
+        .json(&json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(initialized.status(), reqwest::StatusCode::ACCEPTED);
+    assert!(state.session(&session_id).unwrap().is_initialized());
+
+    let batch: serde_json::Value = client
+        .post(&url)
+        .header("mcp-session-id", &session_id)
+        .json(&json!([
+            {"jsonrpc": "2.0", "id": 2, "method": "tools/list"},
+            {"jsonrpc": "2.0", "method": "notifications/progress"},
+            {"jsonrpc": "2.0", "id": 3, "method": "tools/call",
+             "params": {"name": "extract_clean_text", "arguments": {}}}
+        ]))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let batch = batch.as_array().unwrap();
+    assert_eq!(batch.len(), 2);
+    assert_eq!(batch[0]["id"], 2);
+    // Tool failures come back as results flagged with isError
+    assert_eq!(batch[1]["result"]["isError"], true);
+
+    let unknown = client
+        .post(&url)
+        .header("mcp-session-id", "not-a-session")
+        .json(&json!({"jsonrpc": "2.0", "id": 4, "method": "ping"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unknown.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let deleted = client
+        .delete(&url)
+        .header("mcp-session-id", &session_id)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(deleted.status(), reqwest::StatusCode::NO_CONTENT);
+    assert!(state.session(&session_id).is_none());
+}
+
+#[tokio::test]
+async fn test_mcp_http_rejects_requests_before_initialized() {
+    let state = Arc::new(MCPServerState::new());
+    let url = spawn_mcp_server(state.clone()).await;
+    let session = state.open_session("2025-06-18");
+    let client = reqwest::Client::new();
+    let post = |body: serde_json::Value| {
+        client
+            .post(&url)
+            .header("mcp-session-id", &session.id)
+            .json(&body)
+            .send()
+    };
+
+    let early = post(json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+        .await
+        .unwrap();
+    assert_eq!(early.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = early.json().await.unwrap();
+    assert_eq!(body["id"], 1);
+    assert_eq!(body["error"]["code"], -32600);
+
+    // Pings are always answered
+    let ping: serde_json::Value = post(json!({"jsonrpc": "2.0", "id": 2, "method": "ping"}))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(ping.get("error").is_none());
+
+    // A batch may carry the initialized notification ahead of its requests
+    let batch: serde_json::Value = post(json!([
+        {"jsonrpc": "2.0", "method": "notifications/initialized"},
+        {"jsonrpc": "2.0", "id": 3, "method": "tools/list"}
+    ]))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert!(batch[0]["result"]["tools"].is_array());
+}
+
+#[tokio::test]
+async fn test_mcp_sessions_expire_and_are_capped() {
+    let state = MCPServerState::new().with_session_limits(McpSessionLimits {
+        idle_timeout: Duration::from_millis(100),
+        max_sessions: 2,
+    });
+
+    let first = state.open_session("2025-06-18");
+    let second = state.open_session("2025-06-18");
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(state.session(&first.id).is_some());
+
+    // At the cap, the least recently used session makes room
+    let third = state.open_session("2025-06-18");
+    assert!(state.session(&second.id).is_none());
+    assert!(state.session(&first.id).is_some());
+
+    // A session with an open notification stream is in use even when idle
+    let _stream = third.subscribe();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(state.session(&first.id).is_none());
+    assert!(state.session(&third.id).is_some());
+}
+
+#[tokio::test]
+async fn test_mcp_http_streams_notifications_over_sse() {
+    let state = Arc::new(MCPServerState::new());
+    let url = spawn_mcp_server(state.clone()).await;
+    let session = state.open_session("2025-06-18");
+
+    let mut stream = reqwest::Client::new()
+        .get(&url)
+        .header("accept", "text/event-stream")
+        .header("mcp-session-id", &session.id)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stream.status(), reqwest::StatusCode::OK);
+
+    session.notify(
+        "notifications/message",
+        json!({"level": "info", "data": "hi"}),
+    );
+    let chunk = tokio::time::timeout(Duration::from_secs(5), stream.chunk())
+        .await
+        .expect("Notification should arrive")
+        .unwrap()
+        .unwrap();
+    let chunk = String::from_utf8_lossy(&chunk);
+    assert!(chunk.contains("event: message"));
+    assert!(chunk.contains("notifications/message"));
+}
+
+#[test]
+fn test_mcp_origin_allow_list() {
+    assert!(origin_allowed(None, "http://localhost:5173"));
+    assert!(origin_allowed(None, "http://127.0.0.1"));
+    assert!(!origin_allowed(None, "https://evil.example"));
+
+    let allowed = vec!["https://app.example".to_string()];
+    assert!(origin_allowed(Some(&allowed), "https://app.example"));
+    assert!(!origin_allowed(Some(&allowed), "http://localhost:5173"));
+    assert!(origin_allowed(
+        Some(&["*".to_string()]),
+        "https://any.example"
+    ));
+}
+
+#[tokio::test]
+async fn test_agent_services_list_llm_tools_before_browser_tools() {
+    let services = AgentServices::from_env().await;
+    let names = |registry: &ToolRegistry| -> Vec<String> {
+        registry
+            .definitions()
+            .into_iter()
+            .map(|tool| tool.name)
+            .collect()
+    };
+
+    // HTTP and stdio both serve this registry: the LLM's tools first, then browser control
+    let llm_tools = names(&services.llm_tools);
+    let mcp_tools = names(&services.mcp_state.registry);
+    assert_eq!(&mcp_tools[..llm_tools.len()], llm_tools.as_slice());
+    assert!(mcp_tools[llm_tools.len()..].contains(&"browser_open".to_string()));
+    assert!(!llm_tools.contains(&"browser_open".to_string()));
+}
+
+#[tokio::test]
+async fn test_browser_tools_require_an_open_page_per_session() {
+    let content = Arc::new(ContentStore::default());
+    let mut registry = ToolRegistry::new();
+    register_browser_tools(
+        &mut registry,
+        content.clone(),
+        Default::default(),
+        Default::default(),
+    );
+
+    let names: Vec<String> = registry.definitions().into_iter().map(|t| t.name).collect();
+    for expected in [
+        "fetch_page",
+        "browser_open",
+        "browser_navigate",
+        "browser_click",
+        "browser_type",
+        "browser_screenshot",
+        "browser_get_text",
+        "browser_close",
+    ] {
+        assert!(
+            names.contains(&expected.to_string()),
+            "missing {}",
+            expected
+        );
+    }
+
+    let error = registry
+        .call(
+            "browser_click",
+            &json!({ "selector": "#buy" }),
+            &ToolContext::for_session("session-a"),
+        )
+        .await
+        .unwrap_err();
+    assert!(error.contains("call browser_open first"));
+
+    let closed = registry
+        .call(
+            "browser_close",
+            &json!({}),
+            &ToolContext::for_session("session-a"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(closed.value["closed"], false);
+}
+
+struct FakeScreenshotTool {
+    info: llm_web_agent::mcp_server::ToolInfo,
+}
+
+#[async_trait::async_trait]
+impl McpTool for FakeScreenshotTool {
+    fn info(&self) -> &llm_web_agent::mcp_server::ToolInfo {
+        &self.info
+    }
+
+    async fn call(
+        &self,
+        _arguments: &serde_json::Value,
+        context: &ToolContext,
+    ) -> Result<ToolOutput, String> {
+        Ok(ToolOutput {
+            value: json!({ "session": context.session_id }),
+            images: vec![ImageContent {
+                data: "iVBORw0KGgo=".to_string(),
+                mime_type: "image/png".to_string(),
+            }],
+        })
+    }
+}
+
+#[test]
+fn test_tool_registry_runs_session_closed_hooks_once() {
+    let closed = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut registry = ToolRegistry::new();
+    let seen = closed.clone();
+    registry.on_session_closed(move |session_id| seen.lock().unwrap().push(session_id.to_string()));
+
+    registry.session_closed("session-a");
+    assert_eq!(*closed.lock().unwrap(), vec!["session-a".to_string()]);
+}
+
+#[tokio::test]
+async fn test_mcp_tool_results_carry_images_and_session() {
+    let mut registry = ToolRegistry::new();
+    registry.register(Arc::new(FakeScreenshotTool {
+        info: llm_web_agent::mcp_server::ToolInfo {
+            name: "fake_screenshot".to_string(),
+            description: "Test tool".to_string(),
+            input_schema: json!({ "type": "object" }),
+        },
+    }));
+    let state =
+        MCPServerState::with_registry(Arc::new(registry), Arc::new(ContentStore::default()));
+
+    let request = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": { "name": "fake_screenshot", "arguments": {} }
+    }))
+    .unwrap();
+    let response = dispatch_request(&state, Some("session-a"), request)
+        .await
+        .unwrap();
+    let result = response.result.unwrap();
+
+    assert_eq!(result["content"][0]["type"], "image");
+    assert_eq!(result["content"][0]["mimeType"], "image/png");
+    assert_eq!(result["content"][1]["type"], "text");
+    assert_eq!(result["structuredContent"]["session"], "session-a");
+}
+
+#[test]
+fn test_browser_resource_uris_round_trip() {
+    let uri = browser_resource_uri("abc-123", BrowserResourceKind::Screenshot);
+    assert_eq!(uri, "browser://sessions/abc-123/screenshot");
+    assert_eq!(
+        parse_browser_uri(&uri),
+        Some(("abc-123", BrowserResourceKind::Screenshot))
+    );
+
+    assert_eq!(
+        parse_browser_uri("browser://sessions/abc-123/cookies"),
+        None
+    );
+    assert_eq!(parse_browser_uri("browser://sessions//html"), None);
+    assert_eq!(parse_browser_uri("resource://content/abc-123"), None);
+}
+
+fn mcp_request(id: i64, method: &str, params: serde_json::Value) -> MCPRequest {
+    serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_browser_resources_list_and_read() {
+    let events = SessionEvents::new();
+    let state = MCPServerState::new().with_browser_sessions(Default::default(), &events);
+    state
+        .content
+        .insert("https://example.com", PRODUCT_PAGE.to_string());
+
+    let response = dispatch_request(&state, None, mcp_request(1, "resources/list", json!({})))
+        .await
+        .unwrap();
+    // No browser sessions are open, so only the stored page is listed
+    let resources = response.result.unwrap()["resources"].clone();
+    assert_eq!(resources.as_array().unwrap().len(), 1);
+
+    let response = dispatch_request(
+        &state,
+        None,
+        mcp_request(2, "resources/templates/list", json!({})),
+    )
+    .await
+    .unwrap();
+    let templates = response.result.unwrap()["resourceTemplates"].clone();
+    assert!(templates
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|t| t["uriTemplate"] == "browser://sessions/{session_id}/html"));
+
+    let uri = browser_resource_uri("missing", BrowserResourceKind::Html);
+    let response = dispatch_request(
+        &state,
+        None,
+        mcp_request(3, "resources/read", json!({ "uri": uri })),
+    )
+    .await
+    .unwrap();
+    let error = response.error.unwrap();
+    assert_eq!(error.code, -32002);
+    assert!(error.message.contains("missing"));
+}
+
+#[tokio::test]
+async fn test_resource_subscriptions_receive_session_events() {
+    let events = SessionEvents::new();
+    let state = MCPServerState::new().with_browser_sessions(Default::default(), &events);
+    let uri = browser_resource_uri("page-1", BrowserResourceKind::Html);
+
+    // Without a session there is nowhere to deliver updates
+    let response = dispatch_request(
+        &state,
+        None,
+        mcp_request(1, "resources/subscribe", json!({ "uri": uri })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.error.unwrap().code, -32600);
+
+    let session = state.open_session("2025-06-18");
+    let mut notifications = session.subscribe();
+    let response = dispatch_request(
+        &state,
+        Some(&session.id),
+        mcp_request(2, "resources/subscribe", json!({ "uri": uri })),
+    )
+    .await
+    .unwrap();
+    assert!(response.error.is_none());
+
+    let mut next = || {
+        let notification = notifications.try_recv();
+        notification.expect("Notification should have been queued")
+    };
+
+    // Changes to other browser sessions are not delivered
+    events.changed("page-2");
+    events.changed("page-1");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let notification = next();
+    assert_eq!(notification["method"], "notifications/resources/updated");
+    assert_eq!(notification["params"]["uri"], uri);
+
+    events.opened("page-3");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(next()["method"], "notifications/resources/list_changed");
+
+    let response = dispatch_request(
+        &state,
+        Some(&session.id),
+        mcp_request(3, "resources/unsubscribe", json!({ "uri": uri })),
+    )
+    .await
+    .unwrap();
+    assert!(response.error.is_none());
+    assert!(!session.is_subscribed(&uri));
+}
+
+#[test]
+fn test_prompt_templates_render_arguments() {
+    let prompts = PromptLibrary::builtin();
+    let names: Vec<&str> = prompts.list().iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "article_summary",
+            "form_filling",
+            "product_extraction",
+            "table_extraction"
+        ]
+    );
+
+    let rendered = prompts
+        .render(
+            PRODUCT_EXTRACTION_PROMPT,
+            &prompt_arguments([
+                ("url", "https://shop.example/widget"),
+                ("content_ref", "abc"),
+            ]),
+        )
+        .unwrap();
+    assert!(rendered.system.unwrap().contains("extract_product_data"));
+    assert_eq!(rendered.messages[0].0, "user");
+    assert!(rendered.messages[0]
+        .1
+        .contains("https://shop.example/widget"));
+    assert!(rendered.messages[0].1.contains("content_ref \"abc\""));
+
+    // Sections about an optional argument are dropped when it is not given
+    let article = [
+        ("url", "https://news.example/story"),
+        ("content_ref", "abc"),
+    ];
+    let rendered = prompts
+        .render("article_summary", &prompt_arguments(article))
+        .unwrap();
+    assert!(!rendered.messages[0].1.contains("words"));
+    assert!(!rendered.messages[0].1.contains("{{"));
+    assert!(rendered.messages[0]
+        .1
+        .contains("(content_ref \"abc\"). Start with the title"));
+    let rendered = prompts
+        .render(
+            "article_summary",
+            &prompt_arguments([article[0], article[1], ("max_words", "100")]),
+        )
+        .unwrap();
+    assert!(rendered.messages[0]
+        .1
+        .contains("Keep the summary under 100 words. Start with"));
+    let rendered = prompts
+        .render("table_extraction", &prompt_arguments(article))
+        .unwrap();
+    assert!(rendered.messages[0]
+        .1
+        .starts_with("Extract the table from https://news.example/story"));
+
+    let error = prompts
+        .render(
+            PRODUCT_EXTRACTION_PROMPT,
+            &prompt_arguments([("url", "https://shop.example/widget")]),
+        )
+        .unwrap_err();
+    assert!(error.contains("content_ref"));
+    assert!(prompts
+        .render("no_such_prompt", &Default::default())
+        .is_err());
+}
+
+#[test]
+fn test_prompt_library_loads_operator_templates() {
+    let dir = std::env::temp_dir().join(format!("prompts-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("custom.json"),
+        json!([{
+            "name": "review_summary",
+            "description": "Summarize product reviews",
+            "arguments": [{ "name": "url", "required": true }],
+            "messages": [{ "role": "user", "text": "Summarize the reviews on {{url}}" }]
+        }, {
+            "name": "product_extraction",
+            "messages": [{ "role": "user", "text": "Custom extraction for {{url}}" }],
+            "arguments": [{ "name": "url", "required": true }]
+        }])
+        .to_string(),
+    )
+    .unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a template").unwrap();
+
+    let mut prompts = PromptLibrary::builtin();
+    assert_eq!(prompts.load_dir(&dir).unwrap(), 2);
+    let rendered = prompts
+        .render(
+            "review_summary",
+            &prompt_arguments([("url", "https://a.example")]),
+        )
+        .unwrap();
+    assert_eq!(
+        rendered.messages[0].1,
+        "Summarize the reviews on https://a.example"
+    );
+    // Operator templates replace built-ins of the same name
+    assert!(prompts
+        .get(PRODUCT_EXTRACTION_PROMPT)
+        .unwrap()
+        .system
+        .is_none());
+
+    // A broken file leaves the library untouched
+    std::fs::write(
+        dir.join("broken.json"),
+        json!({ "name": "x", "messages": [{ "role": "system", "text": "" }] }).to_string(),
+    )
+    .unwrap();
+    let mut fresh = PromptLibrary::builtin();
+    assert!(fresh.load_dir(&dir).is_err());
+    assert!(fresh.get("review_summary").is_none());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_mcp_prompts_list_and_get() {
+    let state = MCPServerState::new();
+
+    let response = dispatch_request(&state, None, mcp_request(1, "prompts/list", json!({})))
+        .await
+        .unwrap();
+    let prompts = response.result.unwrap()["prompts"].clone();
+    let summary = prompts
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|p| p["name"] == "article_summary")
+        .expect("article_summary should be listed");
+    assert_eq!(summary["arguments"][0]["name"], "url");
+    assert_eq!(summary["arguments"][0]["required"], true);
+
+    let response = dispatch_request(
+        &state,
+        None,
+        mcp_request(
+            2,
+            "prompts/get",
+            json!({
+                "name": "article_summary",
+                "arguments": { "url": "https://blog.example/post", "content_ref": "abc" }
+            }),
+        ),
+    )
+    .await
+    .unwrap();
+    let messages = response.result.unwrap()["messages"].clone();
+    // The system instructions come first, as a user message
+    assert_eq!(messages.as_array().unwrap().len(), 2);
+    assert_eq!(messages[0]["role"], "user");
+    assert_eq!(messages[1]["content"]["type"], "text");
+    assert!(messages[1]["content"]["text"]
+        .as_str()
+        .unwrap()
+        .contains("https://blog.example/post"));
+
+    let response = dispatch_request(
+        &state,
+        None,
+        mcp_request(3, "prompts/get", json!({ "name": "article_summary" })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.error.unwrap().code, -32602);
+}
+
+#[test]
+fn test_mcp_server_config_parses_stdio_and_http_servers() {
+    let servers = McpServerConfig::parse_servers(
+        &json!({
+            "mcpServers": {
+                "product db": { "command": "db-mcp", "args": ["--readonly"] },
+                "search": { "url": "http://localhost:4000/mcp", "headers": { "Authorization": "Bearer x" } }
+            }
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    assert_eq!(servers.len(), 2);
+    assert_eq!(servers[0].name, "product_db");
+    assert_eq!(
+        servers[0].transport,
+        McpTransportConfig::Stdio {
+            command: "db-mcp".to_string(),
+            args: vec!["--readonly".to_string()],
+            env: HashMap::new(),
+        }
+    );
+    assert!(matches!(
+        &servers[1].transport,
+        McpTransportConfig::Http { url, headers }
+            if url == "http://localhost:4000/mcp" && headers["Authorization"] == "Bearer x"
+    ));
+
+    assert!(McpServerConfig::parse_servers(r#"{"mcpServers": {"bad": {}}}"#).is_err());
+}
+
+#[tokio::test]
+async fn test_mcp_client_registers_namespaced_tools_over_http() {
+    let url = spawn_mcp_server(Arc::new(MCPServerState::new())).await;
+    let mut registry = ToolRegistry::new();
+    let registered = registry
+        .register_mcp_server(&McpServerConfig {
+            name: "extractor".to_string(),
+            transport: McpTransportConfig::Http {
+                url,
+                headers: HashMap::new(),
+            },
+        })
+        .await
+        .expect("Should connect to the local MCP server");
+    assert_eq!(registered, 5);
+
+    let output = registry
+        .call(
+            "extractor__extract_clean_text",
+            &json!({ "html_content": PRODUCT_PAGE }),
+            &ToolContext::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(output.value["clean_text"], "Acme Widget $19.99");
+
+    let error = registry
+        .call(
+            "extractor__extract_clean_text",
+            &json!({}),
+            &ToolContext::default(),
+        )
+        .await
+        .unwrap_err();
+    assert!(error.contains("Missing content_ref or html_content"));
+}
+
+#[tokio::test]
+async fn test_mcp_client_talks_to_stdio_subprocess() {
+    let mut registry = ToolRegistry::new();
+    registry
+        .register_mcp_server(&McpServerConfig {
+            name: "agent".to_string(),
+            transport: McpTransportConfig::Stdio {
+                command: env!("CARGO_BIN_EXE_llm-web-agent").to_string(),
+                args: vec!["--mcp-stdio".to_string()],
+                env: HashMap::new(),
+            },
+        })
+        .await
+        .expect("Should start the subprocess server");
+
+    let names: Vec<String> = registry.definitions().into_iter().map(|t| t.name).collect();
+    assert!(names.contains(&"agent__fetch_page".to_string()));
+    assert!(names.contains(&"agent__extract_product_data".to_string()));
+
+    let output = registry
+        .call(
+            "agent__extract_product_data",
+            &json!({ "html_content": PRODUCT_PAGE, "url": "https://shop.example/widget" }),
+            &ToolContext::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(output.value["extracted_data"]["name"], "Acme Widget");
+}
+
+#[tokio::test]
+async fn test_extraction_loop_passes_page_only_to_tools_that_declare_it() {
+    let provider = Arc::new(ScriptedProvider::new(vec![
+        ChatResponse::tool_call("db__lookup_sku", json!({ "sku": "W-1" })),
+        ChatResponse::text(r#"{"name": "Acme Widget"}"#),
+    ]));
+    let content = Arc::new(ContentStore::default());
+    let mut tools = builtin_tools(content.clone());
+    tools.register(Arc::new(LocalTool::new(
+        "db__lookup_sku",
+        "Look up a SKU in the product database",
+        json!({ "type": "object", "properties": { "sku": { "type": "string" } } }),
+        |arguments| Ok(json!({ "received": arguments })),
+    )));
+    let client = LlamaClient::with_provider(provider.clone(), Arc::new(tools), content);
+
+    client
+        .extract_product_information("https://shop.example/widget", PRODUCT_PAGE)
+        .await
+        .unwrap();
+
+    let requests = provider.requests();
+    let tool_result = requests
+        .last()
+        .unwrap()
+        .iter()
+        .find(|m| m.role == "tool")
+        .unwrap();
+    let result: serde_json::Value = serde_json::from_str(&tool_result.content).unwrap();
+    assert_eq!(result["received"], json!({ "sku": "W-1" }));
+}
+
+/// Stand-in for a browser page that records what the pool does with it
+struct FakePage {
+    id: usize,
+    log: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[async_trait::async_trait]
+impl PoolablePage for FakePage {
+    async fn reset(&self) -> anyhow::Result<()> {
+        self.log.lock().unwrap().push(format!("reset {}", self.id));
+        Ok(())
+    }
+
+    async fn close(self) {
+        self.log.lock().unwrap().push(format!("close {}", self.id));
+    }
+}
+
+fn fake_pool(max_pages: usize, max_idle: usize) -> Arc<PagePool<FakePage>> {
+    PagePool::new(PagePoolConfig {
+        max_pages,
+        max_idle,
+        acquire_timeout: Duration::from_millis(200),
+    })
+}
+
+async fn fake_page(
+    id: usize,
+    log: &Arc<std::sync::Mutex<Vec<String>>>,
+) -> anyhow::Result<FakePage> {
+    Ok(FakePage {
+        id,
+        log: log.clone(),
+    })
+}
+
+#[tokio::test]
+async fn test_page_pool_bounds_open_pages_and_times_out_waiters() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let pool = fake_pool(1, 1);
+
+    let first = pool.acquire(0, || fake_page(1, &log)).await.unwrap();
+    assert_eq!(pool.stats().in_use, 1);
+
+    let error = pool.acquire(0, || fake_page(2, &log)).await.err().unwrap();
+    assert!(error.downcast_ref::<PoolTimeout>().is_some());
+    assert_eq!(pool.stats().timeouts, 1);
+
+    // A queued caller gets the page as soon as it is released
+    let waiter = {
+        let pool = pool.clone();
+        let log = log.clone();
+        tokio::spawn(async move {
+            pool.acquire(0, || fake_page(3, &log))
+                .await
+                .map(|page| page.id)
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(pool.stats().waiting, 1);
+    drop(first);
+
+    assert_eq!(waiter.await.unwrap().unwrap(), 1);
+    let stats = pool.stats();
+    assert_eq!((stats.created, stats.reused), (1, 1));
+    // Reset before it was handed over
+    assert_eq!(log.lock().unwrap()[0], "reset 1");
+}
+
+#[tokio::test]
+async fn test_page_pool_closes_pages_beyond_idle_limit() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let pool = fake_pool(3, 1);
+
+    let first = pool.acquire(0, || fake_page(1, &log)).await.unwrap();
+    let second = pool.acquire(0, || fake_page(2, &log)).await.unwrap();
+    drop(first);
+    drop(second);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let stats = pool.stats();
+    assert_eq!((stats.in_use, stats.idle), (0, 1));
+    let log = log.lock().unwrap();
+    assert!(log.contains(&"reset 1".to_string()));
+    assert!(log.contains(&"close 2".to_string()));
+}
+
+#[tokio::test]
+async fn test_page_pool_never_reuses_pages_from_a_dead_browser() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let pool = fake_pool(2, 2);
+
+    drop(pool.acquire(1, || fake_page(1, &log)).await.unwrap());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(pool.stats().idle, 1);
+
+    // The browser restarted: the idle page is closed instead of handed out
+    let page = pool.acquire(2, || fake_page(2, &log)).await.unwrap();
+    assert_eq!(page.id, 2);
+    assert_eq!(pool.stats().idle, 0);
+    assert!(log.lock().unwrap().contains(&"close 1".to_string()));
+
+    drop(page);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let page = pool.acquire(2, || fake_page(3, &log)).await.unwrap();
+    assert_eq!(page.id, 2);
+}
+
+#[tokio::test]
+async fn test_page_pool_reserved_slots_count_against_the_limit() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let pool = fake_pool(1, 1);
+
+    let slot = pool.reserve().await.unwrap();
+    assert_eq!(pool.stats().in_use, 1);
+    let error = pool.acquire(0, || fake_page(1, &log)).await.err().unwrap();
+    assert!(error.downcast_ref::<PoolTimeout>().is_some());
+
+    drop(slot);
+    assert_eq!(pool.stats().in_use, 0);
+    assert!(pool.acquire(0, || fake_page(2, &log)).await.is_ok());
+}
+
+#[test]
+fn test_session_create_request_isolation() {
+    let request: SessionCreateRequest = serde_json::from_value(json!({})).unwrap();
+    assert_eq!(request.isolation, SessionIsolation::Shared);
+
+    let request: SessionCreateRequest =
+        serde_json::from_value(json!({ "isolation": "isolated" })).unwrap();
+    assert_eq!(request.isolation, SessionIsolation::Isolated);
+
+    assert!(
+        serde_json::from_value::<SessionCreateRequest>(json!({ "isolation": "private" })).is_err()
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a local Chromium"]
+async fn test_isolated_sessions_do_not_share_storage() {
+    let mut first = BrowserSession::with_isolation(SessionIsolation::Isolated)
+        .await
+        .unwrap();
+    let mut second = BrowserSession::with_isolation(SessionIsolation::Isolated)
+        .await
+        .unwrap();
+    first.navigate("https://example.com").await.unwrap();
+    second.navigate("https://example.com").await.unwrap();
+
+    first
+        .interact(&BrowserAction::ExecuteScript {
+            script: "document.cookie = 'tenant=a'".to_string(),
+        })
+        .await
+        .unwrap();
+    let cookies = second
+        .interact(&BrowserAction::ExecuteScript {
+            script: "document.cookie".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(!cookies.contains("tenant=a"));
+}
+
+/// Launches stand-in browsers whose "process" the test can kill or hang
+#[derive(Clone, Default)]
+struct FakeLauncher {
+    processes: Arc<std::sync::Mutex<Vec<tokio::sync::oneshot::Sender<String>>>>,
+    hung: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl FakeLauncher {
+    fn kill_latest(&self) {
+        let process = self.processes.lock().unwrap().pop().unwrap();
+        process.send("killed".to_string()).unwrap();
+    }
+}
+
+#[async_trait::async_trait]
+impl BrowserLauncher for FakeLauncher {
+    type Browser = usize;
+
+    async fn launch(&self) -> anyhow::Result<LaunchedBrowser<usize>> {
+        let (process, exited) = tokio::sync::oneshot::channel();
+        let mut processes = self.processes.lock().unwrap();
+        processes.push(process);
+        Ok(LaunchedBrowser {
+            browser: processes.len(),
+            pid: None,
+            exited,
+        })
+    }
+
+    async fn is_responsive(&self, _browser: &usize) -> bool {
+        !self.hung.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+#[tokio::test]
+async fn test_browser_supervisor_relaunches_after_crash() {
+    let launcher = FakeLauncher::default();
+    let died = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let supervisor = Arc::new(BrowserSupervisor::new(launcher.clone()).on_restart({
+        let died = died.clone();
+        move |generation| died.lock().unwrap().push(generation)
+    }));
+
+    let first = supervisor.get().await.unwrap();
+    assert_eq!((*first.browser, first.generation), (1, 1));
+    // Concurrent callers share the running browser
+    assert_eq!(supervisor.get().await.unwrap().generation, 1);
+
+    launcher.kill_latest();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(died.lock().unwrap().as_slice(), [1]);
+    assert!(!supervisor.is_current(first.generation));
+    let stats = supervisor.stats();
+    assert!(stats.alive, "The browser should be relaunched eagerly");
+    assert_eq!((stats.generation, stats.restarts), (2, 1));
+    assert_eq!(stats.last_failure.as_deref(), Some("killed"));
+    assert!(supervisor.is_current(supervisor.get().await.unwrap().generation));
+}
+
+#[tokio::test]
+async fn test_browser_supervisor_replaces_unresponsive_browser() {
+    let launcher = FakeLauncher::default();
+    let supervisor = Arc::new(
+        BrowserSupervisor::new(launcher.clone()).with_probe_interval(Duration::from_millis(10)),
+    );
+    supervisor.get().await.unwrap();
+
+    launcher
+        .hung
+        .store(true, std::sync::atomic::Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    launcher
+        .hung
+        .store(false, std::sync::atomic::Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let stats = supervisor.stats();
+    assert!(stats.restarts >= 1);
+    assert!(stats.alive);
+    assert_eq!(
+        stats.last_failure.as_deref(),
+        Some("browser stopped responding")
+    );
+}
+
+#[test]
+fn test_invalidated_session_maps_to_gone() {
+    let error = anyhow::Error::from(BrowserRestarted {
+        session_id: "abc".to_string(),
+    });
+    let app_error = AppError::from_browser(error);
+    assert!(matches!(&app_error, AppError::SessionInvalidated(id) if id == "abc"));
+
+    use axum::response::IntoResponse;
+    assert_eq!(
+        app_error.into_response().status(),
+        axum::http::StatusCode::GONE
+    );
+}
+
+/// Kill the running Chromium and wait for the supervisor to relaunch it
+async fn kill_browser() {
+    let before = browser_supervisor().stats();
+    let pid = before.pid.expect("Launched browser should have a pid");
+
+    let status = std::process::Command::new("kill")
+        .args(["-9", &pid.to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(30);
+    while browser_supervisor().stats().generation == before.generation {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "Supervisor should notice the crash"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a local Chromium"]
+async fn test_browser_supervisor_recovers_from_killed_chromium() {
+    let mut session = BrowserSession::new().await.expect("Chromium should launch");
+    kill_browser().await;
+
+    assert!(!session.is_valid());
+    let error = session.navigate("about:blank").await.unwrap_err();
+    assert!(error.downcast_ref::<BrowserRestarted>().is_some());
+
+    // New sessions get the relaunched browser
+    let mut fresh = BrowserSession::new().await.unwrap();
+    fresh.navigate("about:blank").await.unwrap();
+    assert!(browser_supervisor().stats().restarts >= 1);
+}
+
+#[tokio::test]
+#[ignore = "needs a local Chromium"]
+async fn test_crashed_session_error_names_the_api_session_id() {
+    let app = create_router().with_state(AppState::new().await.unwrap());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}/api", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    let client = reqwest::Client::new();
+
+    let created: serde_json::Value = client
+        .post(format!("{base}/browser/session"))
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let session_id = created["session_id"].as_str().unwrap().to_string();
+
+    kill_browser().await;
+
+    let response = client
+        .post(format!("{base}/browser/navigate"))
+        .json(&json!({ "session_id": session_id, "url": "about:blank" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::GONE);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "browser_crashed");
+    assert!(body["error"].as_str().unwrap().contains(&session_id));
+}
+
+#[test]
+fn test_session_timeouts_expiry() {
+    let timeouts = SessionTimeouts {
+        idle: Duration::from_secs(60),
+        max_lifetime: Some(Duration::from_secs(600)),
+    };
+    let created = chrono::Utc::now();
+    let at = |secs| created + chrono::Duration::seconds(secs);
+
+    assert_eq!(timeouts.expiry(created, at(30), at(80)), None);
+    assert_eq!(
+        timeouts.expiry(created, at(30), at(90)),
+        Some(SessionExpiry::Idle)
+    );
+    // A session in constant use still ends at its maximum lifetime
+    assert_eq!(
+        timeouts.expiry(created, at(599), at(600)),
+        Some(SessionExpiry::MaxLifetime)
+    );
+
+    let unlimited = SessionTimeouts {
+        max_lifetime: None,
+        ..timeouts
+    };
+    assert_eq!(unlimited.expiry(created, at(9_999), at(10_000)), None);
+
+    let requested = timeouts
+        .with_idle_seconds(Some(5))
+        .with_max_lifetime_seconds(None);
+    assert_eq!(requested.idle, Duration::from_secs(5));
+    assert_eq!(requested.max_lifetime, timeouts.max_lifetime);
+}
+
+#[tokio::test]
+#[ignore = "needs a local Chromium"]
+async fn test_session_reaper_closes_idle_sessions() {
+    let sessions: BrowserSessions = Default::default();
+    let events = SessionEvents::new();
+    let mut closed = events.subscribe();
+
+    let mut idle = BrowserSession::new().await.unwrap();
+    idle.set_timeouts(SessionTimeouts {
+        idle: Duration::from_millis(100),
+        max_lifetime: None,
+    });
+    let busy = BrowserSession::new().await.unwrap();
+    let busy = Arc::new(tokio::sync::Mutex::new(busy));
+    sessions
+        .write()
+        .await
+        .insert("idle".to_string(), Arc::new(tokio::sync::Mutex::new(idle)));
+    sessions
+        .write()
+        .await
+        .insert("busy".to_string(), busy.clone());
+
+    // A session in the middle of an operation is never idle
+    busy.lock().await.set_timeouts(SessionTimeouts {
+        idle: Duration::from_millis(100),
+        max_lifetime: None,
+    });
+    let _in_use = busy.lock().await;
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let reaped = SessionReaper::new(sessions.clone(), events.clone())
+        .reap()
+        .await;
+
+    assert_eq!(reaped, vec![("idle".to_string(), SessionExpiry::Idle)]);
+    assert!(sessions.read().await.contains_key("busy"));
+    assert_eq!(
+        closed.recv().await.unwrap(),
+        SessionEvent::Closed("idle".to_string())
+    );
+}
+
+#[test]
+fn test_browser_action_name_matches_serde_tag() {
+    let actions = vec![
+        BrowserAction::Click {
+            selector: "a".to_string(),
+        },
+        BrowserAction::Type {
+            selector: "input".to_string(),
+            text: "x".to_string(),
+        },
+        BrowserAction::Wait { duration_ms: 1 },
+        BrowserAction::WaitForElement {
+            selector: "a".to_string(),
+            timeout_ms: None,
+        },
+        BrowserAction::Scroll {
+            direction: ScrollDirection::Down,
+            pixels: None,
+        },
+        BrowserAction::Screenshot,
+        BrowserAction::GetPageSource,
+        BrowserAction::ExecuteScript {
+            script: "1".to_string(),
+        },
+    ];
+
+    for action in actions {
+        assert_eq!(
+            serde_json::to_value(&action).unwrap()["type"],
+            action.name()
+        );
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a local Chromium"]
+async fn test_session_tracks_location_and_history() {
+    let mut session = BrowserSession::new().await.unwrap();
+    assert_eq!(session.current_url(), None);
+
+    session
+        .navigate("data:text/html,<title>First</title>")
+        .await
+        .unwrap();
+    session
+        .navigate("data:text/html,<title>Second</title>")
+        .await
+        .unwrap();
+
+    assert_eq!(session.title(), Some("Second"));
+    assert_eq!(session.history().len(), 2);
+    assert_eq!(
+        session.current_url(),
+        session.history().last().map(String::as_str)
+    );
+    let last_action = session.last_action().unwrap();
+    assert_eq!(
+        (last_action.action.as_str(), last_action.success),
+        ("Navigate", true)
+    );
+
+    assert!(session
+        .interact(&BrowserAction::Click {
+            selector: "#missing".to_string(),
+        })
+        .await
+        .is_err());
+    let last_action = session.last_action().unwrap();
+    assert_eq!(
+        (last_action.action.as_str(), last_action.success),
+        ("Click", false)
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a local Chromium"]
+async fn test_sessions_navigate_in_parallel() {
+    let sessions: BrowserSessions = Default::default();
+    for id in ["a", "b"] {
+        let session = BrowserSession::new().await.unwrap();
+        sessions
+            .write()
+            .await
+            .insert(id.to_string(), Arc::new(tokio::sync::Mutex::new(session)));
+    }
+
+    // Each navigation takes at least two seconds, so running them one after the other
+    // would take at least four
+    let started = std::time::Instant::now();
+    let navigations = ["a", "b"].map(|id| {
+        let sessions = sessions.clone();
+        tokio::spawn(async move {
+            let session = find_session(&sessions, id).await.unwrap();
+            let mut session = session.lock().await;
+            session.navigate("data:text/html,<p>hi</p>").await
+        })
+    });
+    for navigation in navigations {
+        navigation.await.unwrap().unwrap();
+    }
+
+    assert!(started.elapsed() < Duration::from_millis(3500));
+}
+
+#[test]
+fn test_wait_until_parses_conditions_and_timeouts() {
+    let request: NavigateRequest = serde_json::from_value(json!({
+        "session_id": "s",
+        "url": "https://example.com"
+    }))
+    .unwrap();
+    assert_eq!(request.wait_until.unwrap_or_default(), WaitUntil::default());
+
+    let wait: WaitUntil = serde_json::from_value(json!({
+        "type": "selector",
+        "selector": "#price",
+        "timeout_ms": 5000
+    }))
+    .unwrap();
+    assert_eq!(
+        wait.condition,
+        WaitCondition::Selector {
+            selector: "#price".to_string()
+        }
+    );
+    assert_eq!(wait.timeout_ms, Some(5000));
+
+    let wait: WaitUntil = serde_json::from_value(json!({ "type": "network_idle" })).unwrap();
+    assert_eq!(wait.condition, WaitCondition::NetworkIdle { idle_ms: 500 });
+    assert_eq!(wait.condition.name(), "network_idle");
+
+    let wait: WaitUntil = serde_json::from_value(json!({ "type": "domcontentloaded" })).unwrap();
+    assert_eq!(wait.condition, WaitCondition::DomContentLoaded);
+}
+
+#[test]
+fn test_in_flight_requests_idle_time() {
+    let mut requests = InFlightRequests::new();
+    requests.started("1");
+    requests.started("2");
+    // A redirect reports the same request again
+    requests.started("2");
+    requests.finished("1");
+    assert_eq!(requests.idle_for(std::time::Instant::now()), None);
+
+    requests.finished("2");
+    let later = std::time::Instant::now() + Duration::from_millis(300);
+    assert!(requests.idle_for(later).unwrap() >= Duration::from_millis(300));
+
+    // Unknown requests, e.g. from before tracking started, don't reset the idle clock
+    requests.finished("0");
+    assert!(requests.idle_for(later).unwrap() >= Duration::from_millis(300));
+}
+
+/// Serve a page whose `load` event is held up by a slow image, `/slow` itself, and `/hang`,
+/// which never answers
+async fn spawn_slow_server(image_delay: Duration) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            tokio::spawn(async move {
+                let mut request = [0u8; 1024];
+                let read = stream.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let (content_type, body) = if request.starts_with("GET /hang") {
+                    tokio::time::sleep(Duration::from_secs(3600)).await;
+                    return;
+                } else if request.starts_with("GET /slow") {
+                    tokio::time::sleep(image_delay).await;
+                    ("image/gif", String::new())
+                } else {
+                    (
+                        "text/html",
+                        r#"<html><body><p id="ready">hi</p><img src="/slow"></body></html>"#
+                            .to_string(),
+                    )
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    content_type,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    base
+}
+
+#[tokio::test]
+#[ignore = "needs a local Chromium"]
+async fn test_navigate_until_does_not_wait_for_load() {
+    let base = spawn_slow_server(Duration::from_secs(5)).await;
+    let mut session = BrowserSession::new().await.unwrap();
+    let wait = |condition, timeout_ms| WaitUntil {
+        condition,
+        timeout_ms: Some(timeout_ms),
+    };
+
+    // Met long before the image lets `load` fire
+    for condition in [
+        WaitCondition::DomContentLoaded,
+        WaitCondition::Selector {
+            selector: "#ready".to_string(),
+        },
+        WaitCondition::Predicate {
+            script: "document.getElementById('ready')".to_string(),
+        },
+    ] {
+        let outcome = session
+            .navigate_until(&format!("{base}/page"), &wait(condition, 3000))
+            .await
+            .unwrap();
+        assert!(outcome.satisfied, "{}", outcome.condition);
+        assert!(outcome.elapsed_ms < 3000, "{}", outcome.condition);
+    }
+
+    // A timeout shorter than the load is honoured, and the page is still usable
+    let outcome = session
+        .navigate_until(&format!("{base}/page"), &wait(WaitCondition::Load, 500))
+        .await
+        .unwrap();
+    assert!(!outcome.satisfied);
+    assert!(outcome.elapsed_ms < 2000);
+
+    // A server that never answers is a navigation timeout after the requested time
+    let started = std::time::Instant::now();
+    let error = session
+        .navigate_until(&format!("{base}/hang"), &wait(WaitCondition::Load, 500))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<NavigationError>(),
+        Some(NavigationError::Timeout {
+            timeout_ms: 500,
+            ..
+        })
+    ));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+#[ignore = "needs a local Chromium"]
+async fn test_navigate_until_reports_wait_outcome() {
+    let mut session = BrowserSession::new().await.unwrap();
+    let page = "data:text/html,<script>setTimeout(() => document.body.innerHTML = \
+                '<p id=late>hi</p>', 300)</script>";
+
+    let outcome = session
+        .navigate_until(
+            page,
+            &WaitUntil {
+                condition: WaitCondition::Selector {
+                    selector: "#late".to_string(),
+                },
+                timeout_ms: Some(5000),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(outcome.satisfied);
+    assert!(outcome.elapsed_ms >= 300 && outcome.elapsed_ms < 5000);
+
+    let outcome = session
+        .navigate_until(
+            page,
+            &WaitUntil {
+                condition: WaitCondition::Predicate {
+                    script: "window.neverSet".to_string(),
+                },
+                timeout_ms: Some(500),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(outcome.condition, "predicate");
+    assert!(!outcome.satisfied);
+
+    let outcome = session
+        .navigate_until(
+            "data:text/html,<p>static</p>",
+            &WaitUntil {
+                condition: WaitCondition::NetworkIdle { idle_ms: 200 },
+                timeout_ms: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert!(outcome.satisfied);
+}